@@ -0,0 +1,22 @@
+# Fuzzes the Python entry points of stringly with atheris, e.g.
+#
+#     maturin develop && python fuzz/atheris_loads.py -max_total_time=60
+
+import sys
+
+import atheris
+
+with atheris.instrument_imports():
+    import stringly
+
+TYPES = 'Dict[str, List[int]]', 'Tuple[float, Optional[str]]', 'List[Dict[str, float]]'
+
+
+def test_one_input(data: bytes) -> None:
+    stringly._fuzz_parse(data)
+    for annotation in TYPES:
+        stringly._fuzz_loads(annotation, data)
+
+
+atheris.Setup(sys.argv, test_one_input)
+atheris.Fuzz()
@@ -0,0 +1,6 @@
+#![no_main]
+
+// Run with `cargo fuzz run parse` from the root of the repository.
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| stringly::fuzz_parse(data));
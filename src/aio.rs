@@ -0,0 +1,43 @@
+use crate::{guard, options};
+use pyo3::proc_macro::pyfunction;
+use pyo3::types::{PyAny, PyDict, PyModule, PyTuple};
+use pyo3::{wrap_pyfunction, PyObject, PyResult, Python, ToPyObject};
+
+/// Schedules `stringly.<name>(*args, **kwargs)` in the default executor of
+/// the running event loop and returns the awaitable future.
+///
+/// The options in effect in the calling thread, updated with the options in
+/// `kwargs`, are passed on to the worker thread.
+fn run_in_executor<'py>(py: Python<'py>, name: &str, args: &[&PyAny], kwargs: Option<&PyDict>) -> PyResult<&'py PyAny> {
+  let mut items: Vec<PyObject> = vec![py.import("stringly")?.getattr(name)?.to_object(py)];
+  items.extend(args.iter().map(|arg| arg.to_object(py)));
+  let func = py.import("functools")?.call("partial", PyTuple::new(py, items), Some(options::current().with(kwargs)?.to_dict(py)?))?;
+  py.import("asyncio")?.call0("get_running_loop")?.call_method1("run_in_executor", (py.None(), func))
+}
+
+/// Asynchronous variant of `stringly.load`.
+///
+/// The file is read and parsed in a worker thread, the returned future
+/// resolves to the loaded value.
+#[pyfunction(on_mismatch = "\"error\"", options = "**")]
+#[text_signature = "(type, path, on_mismatch='error', **options)"]
+fn load<'py>(py: Python<'py>, ty: &PyAny, path: &PyAny, on_mismatch: &PyAny, options: Option<&PyDict>) -> PyResult<&'py PyAny> {
+  guard("stringly.aio.load", || run_in_executor(py, "load", &[ty, path, on_mismatch], options))
+}
+
+/// Asynchronous variant of `stringly.dump`.
+#[pyfunction(checksum = "false", options = "**")]
+#[text_signature = "(type, value, path, checksum=False, **options)"]
+fn dump<'py>(py: Python<'py>, ty: &PyAny, val: &PyAny, path: &PyAny, checksum: bool, options: Option<&PyDict>) -> PyResult<&'py PyAny> {
+  guard("stringly.aio.dump", || {
+    let checksum = checksum.to_object(py);
+    run_in_executor(py, "dump", &[ty, val, path, checksum.as_ref(py)], options)
+  })
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(load))?;
+  m.add_wrapped(wrap_pyfunction!(dump))?;
+
+  Ok(())
+}
@@ -0,0 +1,175 @@
+use crate::doc::DocString;
+use crate::{dumps_value, guard, loads_value};
+use pyo3::exceptions::ValueError;
+use pyo3::proc_macro::pyfunction;
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyList, PyModule, PyTuple};
+use pyo3::{wrap_pyfunction, PyObject, PyResult, Python, ToPyObject};
+
+/// The names of the workloads, in the order in which they are run.
+const WORKLOADS: &[&str] = &["flat_dict", "deep_struct", "float_list", "docstring"];
+
+/// The number of items of the flat dict and the float list.
+const ITEMS: usize = 1000;
+
+/// The nesting depth of the deep struct.
+const DEPTH: usize = 20;
+
+/// The number of documented arguments of the docstring.
+const ARGUMENTS: usize = 50;
+
+/// A workload: a type and a value of it, serialized by `dumps` and `loads`, or
+/// a docstring parsed by `stringly.DocString`.
+enum Workload {
+  Value { ty: PyObject, value: PyObject },
+  Docstring(PyObject),
+}
+
+/// Returns workload `name`.
+///
+/// The workloads are generated deterministically, so that timings are
+/// comparable between runs and platforms.
+fn workload(py: Python, name: &str) -> PyResult<Workload> {
+  let typing = py.import("typing")?;
+  let builtins = py.import("builtins")?;
+  match name {
+    "flat_dict" => {
+      let ty = typing.getattr("Dict")?.get_item((builtins.getattr("str")?, builtins.getattr("int")?))?;
+      let value = (0..ITEMS).map(|i| (format!("key{}", i), i * 7919 % 100_003)).into_py_dict(py);
+      Ok(Workload::Value { ty: ty.into(), value: value.into() })
+    }
+    "deep_struct" => {
+      let dataclasses = py.import("dataclasses")?;
+      let mut ty = dataclasses.call1("make_dataclass", ("Level0", vec![("name", builtins.getattr("str")?), ("scale", builtins.getattr("float")?)]))?;
+      let mut value = ty.call1(("leaf", 0.5))?;
+      for depth in 1..=DEPTH {
+        let fields = vec![("name", builtins.getattr("str")?), ("scale", builtins.getattr("float")?), ("child", ty)];
+        ty = dataclasses.call1("make_dataclass", (format!("Level{}", depth), fields))?;
+        value = ty.call1((format!("level{}", depth), depth as f64 * 1.5, value))?;
+      }
+      Ok(Workload::Value { ty: ty.into(), value: value.into() })
+    }
+    "float_list" => {
+      let ty = typing.getattr("List")?.get_item(builtins.getattr("float")?)?;
+      let value = PyList::new(py, (0..ITEMS).map(|i| (i as f64 * 0.618_033_988_749_895).sin() * 1e3));
+      Ok(Workload::Value { ty: ty.into(), value: value.into() })
+    }
+    "docstring" => {
+      let mut doc = "Runs a benchmark.\n\nThe arguments are generated.\n\n.. arguments::\n\n".to_string();
+      for i in 0..ARGUMENTS {
+        doc.push_str(&format!("   arg{}: int [{}]\n     Argument number {}.\n\n", i, i, i));
+      }
+      let types = py.import("types")?;
+      Ok(Workload::Docstring(types.call("SimpleNamespace", (), Some([("__doc__", doc)].into_py_dict(py)))?.into()))
+    }
+    _ => Err(ValueError::py_err(format!("unknown workload '{}'; valid workloads are: {}", name, WORKLOADS.join(", ")))),
+  }
+}
+
+/// Returns the best time in seconds per call of `f` over `repeat` rounds of
+/// `number` calls.
+fn time(py: Python, repeat: usize, number: usize, mut f: impl FnMut() -> PyResult<()>) -> PyResult<f64> {
+  let perf_counter = py.import("time")?.getattr("perf_counter")?;
+  let mut best = f64::INFINITY;
+  for _ in 0..repeat.max(1) {
+    let start: f64 = perf_counter.call0()?.extract()?;
+    for _ in 0..number.max(1) {
+      f()?;
+    }
+    let stop: f64 = perf_counter.call0()?.extract()?;
+    best = best.min((stop - start) / number.max(1) as f64);
+  }
+  Ok(best)
+}
+
+/// Returns the timings of `workload` as pairs of an operation and the best
+/// time in seconds per call.
+fn measure(py: Python, workload: &Workload, repeat: usize, number: usize) -> PyResult<Vec<(&'static str, f64)>> {
+  match workload {
+    Workload::Value { ty, value } => {
+      let (ty, value) = (ty.as_ref(py), value.as_ref(py));
+      let s = dumps_value(py, ty, value)?;
+      let dumps = time(py, repeat, number, || dumps_value(py, ty, value).map(drop))?;
+      let loads = time(py, repeat, number, || loads_value(py, ty, &s).map(drop))?;
+      Ok(vec![("dumps", dumps), ("loads", loads)])
+    }
+    Workload::Docstring(f) => Ok(vec![("parse", time(py, repeat, number, || DocString::new(f.as_ref(py)).map(drop))?)]),
+  }
+}
+
+/// Returns the names of the available workloads.
+#[pyfunction]
+#[text_signature = "()"]
+fn workloads() -> Vec<&'static str> {
+  WORKLOADS.to_vec()
+}
+
+/// Runs the workloads `names`, or all workloads if `None`, and returns a dict
+/// of `workload.operation` to the best time in seconds per call over `repeat`
+/// rounds of `number` calls.
+#[pyfunction(names = "None", repeat = "5", number = "20")]
+#[text_signature = "(names=None, repeat=5, number=20)"]
+fn run<'py>(py: Python<'py>, names: Option<Vec<String>>, repeat: usize, number: usize) -> PyResult<&'py PyDict> {
+  guard("stringly.bench.run", || {
+    let names = names.unwrap_or_else(|| WORKLOADS.iter().map(|name| name.to_string()).collect());
+    let results = PyDict::new(py);
+    for name in &names {
+      for (operation, seconds) in measure(py, &workload(py, name)?, repeat, number)? {
+        results.set_item(format!("{}.{}", name, operation), seconds)?;
+      }
+    }
+    Ok(results)
+  })
+}
+
+/// Runs the benchmarks from the command line and prints the timings, e.g.
+/// `python -m stringly.bench --repeat 10 flat_dict`.
+///
+/// If `argv` is `None` the arguments are taken from `sys.argv`.
+#[pyfunction(argv = "None")]
+#[text_signature = "(argv=None)"]
+fn main(py: Python, argv: Option<Vec<String>>) -> PyResult<()> {
+  guard("stringly.bench.main", || {
+    let argparse = py.import("argparse")?;
+    let parser = argparse.call("ArgumentParser", (), Some([("prog", "python -m stringly.bench"), ("description", "Times representative stringly workloads.")].into_py_dict(py)))?;
+    let add = |args: &PyTuple, kwargs: &PyDict| parser.call_method("add_argument", args, Some(kwargs)).map(drop);
+    add(PyTuple::new(py, &["workloads"]), [("nargs", "*".to_object(py)), ("choices", WORKLOADS.to_object(py)), ("help", "the workloads to run, all by default".to_object(py))].into_py_dict(py))?;
+    add(PyTuple::new(py, &["--repeat"]), [("type", py.import("builtins")?.getattr("int")?.to_object(py)), ("default", 5.to_object(py)), ("help", "the number of rounds, of which the best is reported".to_object(py))].into_py_dict(py))?;
+    add(PyTuple::new(py, &["--number"]), [("type", py.import("builtins")?.getattr("int")?.to_object(py)), ("default", 20.to_object(py)), ("help", "the number of calls per round".to_object(py))].into_py_dict(py))?;
+    let args = parser.call_method1("parse_args", (argv.to_object(py),))?;
+    let mut names: Vec<String> = args.getattr("workloads")?.extract()?;
+    if names.is_empty() {
+      names = WORKLOADS.iter().map(|name| name.to_string()).collect();
+    }
+    let (repeat, number): (usize, usize) = (args.getattr("repeat")?.extract()?, args.getattr("number")?.extract()?);
+    let print = py.import("builtins")?.getattr("print")?;
+    for name in &names {
+      for (operation, seconds) in measure(py, &workload(py, name)?, repeat, number)? {
+        print.call1((format!("{:<24} {:>12.3} us", format!("{}.{}", name, operation), seconds * 1e6),))?;
+      }
+    }
+    Ok(())
+  })
+}
+
+/// Returns the code run by `python -m stringly.bench`.
+#[pyfunction]
+#[text_signature = "(name, /)"]
+fn get_code(py: Python, _name: &str) -> PyResult<PyObject> {
+  Ok(py.import("builtins")?.call1("compile", ("import stringly\nstringly.bench.main()\n", "<stringly.bench>", "exec"))?.into())
+}
+
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(workloads))?;
+  m.add_wrapped(wrap_pyfunction!(run))?;
+  m.add_wrapped(wrap_pyfunction!(main))?;
+
+  // The extension is a single module rather than a package, so `python -m
+  // stringly.bench` finds this submodule in `sys.modules` with a loader that
+  // provides the code to run.
+  let loader = py.import("types")?.call("SimpleNamespace", (), Some([("get_code", wrap_pyfunction!(get_code)(py))].into_py_dict(py)))?;
+  let spec = py.import("importlib.util")?.call1("spec_from_loader", ("stringly.bench", loader))?;
+  m.setattr("__spec__", spec)?;
+  py.import("sys")?.getattr("modules")?.set_item("stringly.bench", m)?;
+
+  Ok(())
+}
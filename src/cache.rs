@@ -0,0 +1,155 @@
+use crate::options::Options;
+use crate::schema::{self, Schema};
+use crate::{guard, preprocess, stats};
+use pyo3::proc_macro::pyfunction;
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyModule};
+use pyo3::{wrap_pyfunction, AsPyPointer, PyObject, PyResult, Python};
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An entry of the cache: a serialized string of a type, with the options in
+/// effect, and its intermediate form.
+struct Entry {
+  ty: PyObject,
+  /// The hash of `options` and `input`, compared before the strings.
+  digest: u64,
+  options: String,
+  input: String,
+  schema: Rc<dyn Schema>,
+  parsed: String,
+}
+
+/// A size-bounded cache of parsed serialized strings, see `set_cache_size`.
+#[derive(Default)]
+struct Cache {
+  /// The value of `GENERATION` when the entries were added.
+  generation: usize,
+  /// The entries, least recently used first.
+  entries: VecDeque<Entry>,
+  hits: usize,
+  misses: usize,
+}
+
+/// The maximum number of entries per thread.
+static MAXSIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Incremented by `clear_cache` to invalidate the caches of all threads.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+  /// The cache of the current thread; schemas are not shared between threads.
+  static CACHE: RefCell<Cache> = RefCell::new(Cache::default());
+}
+
+/// Runs `f` with the cache of the current thread, after dropping the entries
+/// invalidated by `clear_cache` and `set_cache_size`.
+fn with_cache<T>(f: impl FnOnce(&mut Cache) -> T) -> T {
+  CACHE.with(|cache| {
+    let mut cache = cache.borrow_mut();
+    let generation = GENERATION.load(Ordering::Relaxed);
+    if cache.generation != generation {
+      *cache = Cache { generation, ..Cache::default() };
+    }
+    let maxsize = MAXSIZE.load(Ordering::Relaxed);
+    while cache.entries.len() > maxsize {
+      cache.entries.pop_front();
+    }
+    f(&mut cache)
+  })
+}
+
+/// Returns the schema of type `ty` and serialized string `s` with presets
+/// expanded and references resolved, see `preprocess`, built and parsed
+/// unless cached.
+///
+/// Types are compared by identity; the parametrized aliases of `typing`, such
+/// as `List[int]`, are cached by Python and hence identical between calls.
+/// The cache is disabled if its size is zero.
+pub fn prepared(py: Python, ty: &PyAny, s: &str, options: &Options) -> PyResult<(Rc<dyn Schema>, String)> {
+  let prepare = || -> PyResult<(Rc<dyn Schema>, String)> {
+    let schema = stats::timed(stats::Phase::Build, || schema::build_with(py, ty, options))?;
+    let parsed = stats::timed(stats::Phase::Parse, || preprocess(s, options))?;
+    Ok((schema.into(), parsed))
+  };
+  if MAXSIZE.load(Ordering::Relaxed) == 0 {
+    return prepare();
+  }
+  let key = options.to_dict(py)?.repr()?.to_string();
+  let mut hasher = DefaultHasher::new();
+  (&key, s).hash(&mut hasher);
+  let digest = hasher.finish();
+  let hit = with_cache(|cache| {
+    let index = cache.entries.iter().position(|entry| entry.ty.as_ptr() == ty.as_ptr() && entry.digest == digest && entry.options == key && entry.input == s);
+    match index {
+      Some(index) => {
+        let entry = cache.entries.remove(index).unwrap();
+        let hit = (entry.schema.clone(), entry.parsed.clone());
+        cache.entries.push_back(entry);
+        cache.hits += 1;
+        Some(hit)
+      }
+      None => {
+        cache.misses += 1;
+        None
+      }
+    }
+  });
+  if let Some(hit) = hit {
+    return Ok(hit);
+  }
+  let (schema, parsed) = prepare()?;
+  with_cache(|cache| {
+    cache.entries.push_back(Entry { ty: ty.into(), digest, options: key, input: s.to_string(), schema: schema.clone(), parsed: parsed.clone() });
+    if cache.entries.len() > MAXSIZE.load(Ordering::Relaxed) {
+      cache.entries.pop_front();
+    }
+  });
+  Ok((schema, parsed))
+}
+
+/// Sets the maximum number of parsed strings kept by the cache of `loads`;
+/// zero, the default, disables the cache.
+///
+/// Servers that repeatedly load identical strings skip building the schema of
+/// the type and parsing the string, i.e. expanding presets and resolving
+/// references, but still construct a new value for every call. Every thread
+/// keeps a cache of its own.
+#[pyfunction]
+#[text_signature = "(maxsize, /)"]
+fn set_cache_size(maxsize: usize) -> PyResult<()> {
+  guard("stringly.set_cache_size", || {
+    MAXSIZE.store(maxsize, Ordering::Relaxed);
+    Ok(())
+  })
+}
+
+/// Removes all entries from the caches of all threads and resets their
+/// statistics.
+#[pyfunction]
+#[text_signature = "()"]
+fn clear_cache() -> PyResult<()> {
+  guard("stringly.clear_cache", || {
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+  })
+}
+
+/// Returns the statistics of the cache of the current thread as a dict with
+/// the keys `hits`, `misses`, `size` and `maxsize`.
+#[pyfunction]
+#[text_signature = "()"]
+fn cache_info(py: Python) -> PyResult<&PyDict> {
+  guard("stringly.cache_info", || with_cache(|cache| Ok([("hits", cache.hits), ("misses", cache.misses), ("size", cache.entries.len()), ("maxsize", MAXSIZE.load(Ordering::Relaxed))].into_py_dict(py))))
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(set_cache_size))?;
+  m.add_wrapped(wrap_pyfunction!(clear_cache))?;
+  m.add_wrapped(wrap_pyfunction!(cache_info))?;
+
+  Ok(())
+}
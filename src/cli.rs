@@ -0,0 +1,466 @@
+use crate::merge::{items, merge};
+use crate::doc::DocString;
+use crate::{file, guard, loads_value, SerializationError};
+use pyo3::exceptions::{TypeError, ValueError};
+use pyo3::proc_macro::{pyclass, pyfunction, pymethods};
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyModule, PyTuple};
+use pyo3::{wrap_pyfunction, AsPyPointer, PyErr, PyObject, PyResult, Python, ToPyObject};
+use std::ops::Range;
+use stringly::util::{protect, safesplit_once};
+
+/// Returns `argparse.ArgumentError` for a failed `TypedAction`.
+fn argument_error(py: Python, option: &str, value: &str, err: PyErr) -> PyResult<PyErr> {
+  let message = format!("argument {}: invalid value '{}': {}", option, value, err.to_object(py).as_ref(py));
+  Ok(PyErr::from_instance(py.import("argparse")?.getattr("ArgumentError")?.call1((py.None(), message))?))
+}
+
+/// An `argparse` action that parses its argument with `stringly.loads`.
+///
+/// Usage: `parser.add_argument('--solver', action=TypedAction,
+/// stringly_type=Solver)`. A string default is parsed as well.
+#[pyclass(dict)]
+struct TypedAction {
+  #[pyo3(get)]
+  option_strings: Vec<String>,
+  #[pyo3(get)]
+  dest: String,
+  #[pyo3(get)]
+  stringly_type: PyObject,
+  #[pyo3(get, set)]
+  default: PyObject,
+  #[pyo3(get, set)]
+  required: bool,
+  #[pyo3(get, set)]
+  help: PyObject,
+  #[pyo3(get, set)]
+  metavar: PyObject,
+  #[pyo3(get)]
+  deprecated: bool,
+}
+
+#[pymethods]
+impl TypedAction {
+  #[new]
+  #[args(kwargs = "**")]
+  fn new(py: Python, option_strings: Vec<String>, dest: String, stringly_type: &PyAny, kwargs: Option<&PyDict>) -> PyResult<Self> {
+    guard("stringly.cli.TypedAction", || {
+      let mut action = TypedAction { option_strings, dest, stringly_type: stringly_type.into(), default: py.None(), required: false, help: py.None(), metavar: py.None(), deprecated: false };
+      for (key, value) in kwargs.into_iter().flat_map(|kwargs| kwargs.iter()) {
+        match key.extract::<&str>()? {
+          "default" => action.default = value.into(),
+          "required" => action.required = value.extract()?,
+          "help" => action.help = value.into(),
+          "metavar" => action.metavar = value.into(),
+          "deprecated" => action.deprecated = value.extract()?,
+          "nargs" | "const" | "type" | "choices" if value.is_none() => (),
+          "nargs" | "const" | "type" | "choices" => return Err(ValueError::py_err(format!("TypedAction does not support argument '{}'", key))),
+          key => return Err(TypeError::py_err(format!("TypedAction got an unexpected keyword argument '{}'", key))),
+        }
+      }
+      let default = action.default.clone_ref(py);
+      if let Ok(s) = default.as_ref(py).extract::<&str>() {
+        if s != "==SUPPRESS==" {
+          let option = action.option_strings.first().unwrap_or(&action.dest).clone();
+          action.default = match loads_value(py, stringly_type, s) {
+            Ok(value) => value,
+            Err(err) if err.is_instance::<SerializationError>(py) => return Err(argument_error(py, &option, s, err)?),
+            Err(err) => return Err(err),
+          };
+        }
+      }
+      Ok(action)
+    })
+  }
+  #[getter]
+  fn get_nargs(&self, py: Python) -> PyObject {
+    py.None()
+  }
+  #[getter]
+  fn get_const(&self, py: Python) -> PyObject {
+    py.None()
+  }
+  #[getter]
+  fn get_type(&self, py: Python) -> PyObject {
+    py.None()
+  }
+  #[getter]
+  fn get_choices(&self, py: Python) -> PyObject {
+    py.None()
+  }
+  #[call]
+  #[args(option_string = "None")]
+  fn __call__(&self, py: Python, _parser: &PyAny, namespace: &PyAny, values: &str, option_string: Option<&str>) -> PyResult<()> {
+    guard("stringly.cli.TypedAction.__call__", || {
+      match loads_value(py, self.stringly_type.as_ref(py), values) {
+        Ok(value) => namespace.setattr(self.dest.as_str(), value),
+        Err(err) if err.is_instance::<SerializationError>(py) => Err(argument_error(py, option_string.unwrap_or(&self.dest), values, err)?),
+        Err(err) => Err(err),
+      }
+    })
+  }
+}
+
+/// Adds a `--name` option to `parser` for every parameter of `func`.
+///
+/// The options are typed by the annotations of `func`, defaults and help
+/// texts are taken from the signature and the `DocString` of `func`. If
+/// `skip_first` is true the first parameter (`self`) is skipped. Returns the
+/// names of the parameters.
+fn add_arguments(py: Python, parser: &PyAny, func: &PyAny, skip_first: bool) -> PyResult<Vec<String>> {
+  let inspect = py.import("inspect")?;
+  let empty = inspect.getattr("Parameter")?.getattr("empty")?;
+  let doc = DocString::new(func)?;
+  let mut names = Vec::new();
+  for (index, param) in inspect.call1("signature", (func,))?.getattr("parameters")?.call_method0("values")?.iter()?.enumerate() {
+    let param = param?;
+    // Skip `self`, `*args` and `**kwargs`.
+    if (skip_first && index == 0) || [2, 4].contains(&param.getattr("kind")?.extract::<i32>()?) {
+      continue;
+    }
+    let name: String = param.getattr("name")?.extract()?;
+    let annotation = param.getattr("annotation")?;
+    let annotation = if annotation.as_ptr() == empty.as_ptr() { py.import("builtins")?.getattr("str")? } else { annotation };
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("action", py.import("stringly")?.getattr("cli")?.getattr("TypedAction")?)?;
+    kwargs.set_item("stringly_type", annotation)?;
+    kwargs.set_item("dest", &name)?;
+    let default = param.getattr("default")?;
+    if default.as_ptr() != empty.as_ptr() {
+      kwargs.set_item("default", default)?;
+    } else if let Some((_, default)) = doc.defaults.iter().find(|(key, _)| key == &name) {
+      kwargs.set_item("default", default)?;
+    } else {
+      kwargs.set_item("required", true)?;
+    }
+    if let Some((_, help)) = doc.argdocs.iter().find(|(key, _)| key == &name) {
+      kwargs.set_item("help", help)?;
+    }
+    parser.call_method("add_argument", (format!("--{}", name),), Some(kwargs))?;
+    names.push(name);
+  }
+  Ok(names)
+}
+
+/// A subcommand of `Commands`.
+struct Command {
+  name: String,
+  /// Whether the method requires an instance of the class.
+  bound: bool,
+  params: Vec<String>,
+}
+
+/// A multi-command line interface built by `from_class`.
+///
+/// Calling the object parses the command line and returns the result of the
+/// selected method.
+#[pyclass]
+struct Commands {
+  cls: PyObject,
+  #[pyo3(get)]
+  parser: PyObject,
+  commands: Vec<Command>,
+}
+
+#[pymethods]
+impl Commands {
+  #[call]
+  #[args(argv = "None")]
+  fn __call__(&self, py: Python, argv: Option<&PyAny>) -> PyResult<PyObject> {
+    guard("stringly.cli.Commands.__call__", || {
+      let namespace = self.parser.as_ref(py).call_method1("parse_args", (argv,))?;
+      let name: String = namespace.getattr("command")?.extract()?;
+      let command = match self.commands.iter().find(|command| command.name == name) {
+        Some(command) => command,
+        None => return Err(ValueError::py_err(format!("unknown command '{}'", name))),
+      };
+      let kwargs: Vec<(&str, &PyAny)> = command.params.iter().map(|param| Ok((param.as_str(), namespace.getattr(param.as_str())?))).collect::<PyResult<_>>()?;
+      let cls = self.cls.as_ref(py);
+      let target = if command.bound { cls.call0()? } else { cls };
+      Ok(target.call_method(name.as_str(), PyTuple::empty(py), Some(kwargs.into_py_dict(py)))?.into())
+    })
+  }
+}
+
+/// Returns a multi-command line interface for the public methods of `cls`.
+///
+/// Every method that does not start with an underscore becomes a subcommand,
+/// with options derived from its annotations and `DocString`. Regular methods
+/// are called on an instance created by calling `cls` without arguments.
+#[pyfunction]
+#[text_signature = "(cls, /)"]
+fn from_class(py: Python, cls: &PyAny) -> PyResult<Commands> {
+  guard("stringly.cli.from_class", || {
+    let inspect = py.import("inspect")?;
+    let builtins = py.import("builtins")?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("description", DocString::new(cls)?.text)?;
+    let parser = py.import("argparse")?.call("ArgumentParser", (), Some(kwargs))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("dest", "command")?;
+    kwargs.set_item("required", true)?;
+    let subparsers = parser.call_method("add_subparsers", (), Some(kwargs))?;
+    let mut commands = Vec::new();
+    let mut names: Vec<String> = builtins.call1("dir", (cls,))?.extract()?;
+    names.sort();
+    for name in names.into_iter().filter(|name| !name.starts_with('_')) {
+      let member = inspect.call1("getattr_static", (cls, name.as_str()))?;
+      let isinstance = |ty: &str| -> PyResult<bool> { builtins.call1("isinstance", (member, builtins.getattr(ty)?))?.is_true() };
+      let bound = if isinstance("staticmethod")? || isinstance("classmethod")? {
+        false
+      } else if inspect.call1("isfunction", (member,))?.is_true()? {
+        true
+      } else {
+        continue;
+      };
+      let func = cls.getattr(name.as_str())?;
+      let doc = DocString::new(func)?;
+      let kwargs = PyDict::new(py);
+      kwargs.set_item("help", doc.text.split('\n').next().unwrap_or(""))?;
+      kwargs.set_item("description", &doc.text)?;
+      let subparser = subparsers.call_method("add_parser", (name.as_str(),), Some(kwargs))?;
+      let params = add_arguments(py, subparser, func, bound)?;
+      commands.push(Command { name, bound, params });
+    }
+    Ok(Commands { cls: cls.into(), parser: parser.into(), commands })
+  })
+}
+
+/// Returns the words of `possibilities` that are close to `word`.
+fn close_matches(py: Python, word: &str, possibilities: Vec<&str>) -> PyResult<Vec<String>> {
+  py.import("difflib")?.call1("get_close_matches", (word, possibilities))?.extract()
+}
+
+/// A command line error.
+struct Failure {
+  message: String,
+  /// The offending command line item and the range of bytes to mark.
+  item: Option<(String, Range<usize>)>,
+  suggestions: Vec<String>,
+}
+
+impl Failure {
+  fn new(message: String) -> Self {
+    Failure { message, item: None, suggestions: Vec::new() }
+  }
+
+  /// Renders the error for the terminal, optionally with ANSI colors.
+  fn render(&self, prog: &str, color: bool) -> String {
+    let (bold, red, green, reset) = if color { ("\x1b[1m", "\x1b[1;31m", "\x1b[32m", "\x1b[0m") } else { ("", "", "", "") };
+    let mut rendered = format!("{}{}:{} {}error:{} {}\n", bold, prog, reset, red, reset, self.message);
+    if let Some((item, range)) = &self.item {
+      let indent = item[..range.start].chars().count();
+      let width = std::cmp::max(1, item[range.clone()].chars().count());
+      rendered.push_str(&format!("  {}\n  {}{}{}{}\n", item, " ".repeat(indent), red, "^".repeat(width), reset));
+    }
+    match self.suggestions.len() {
+      0 => (),
+      1 => rendered.push_str(&format!("  did you mean {}{}{}?\n", green, self.suggestions[0], reset)),
+      _ => rendered.push_str(&format!("  did you mean one of {}{}{}?\n", green, self.suggestions.join(", "), reset)),
+    }
+    rendered
+  }
+}
+
+enum RunError {
+  Python(PyErr),
+  Failure(Failure),
+}
+
+impl From<PyErr> for RunError {
+  fn from(err: PyErr) -> Self {
+    RunError::Python(err)
+  }
+}
+
+/// A console script entry point created by `main`.
+#[pyclass]
+struct Main {
+  func: PyObject,
+  pretty_errors: bool,
+  color: Option<bool>,
+}
+
+impl Main {
+  /// Returns the usage text.
+  fn usage(&self, prog: &str, doc: &DocString) -> String {
+    let mut usage = format!("usage: {} [preset] [@config] [key=value ...]\n", prog);
+    if !doc.text.is_empty() {
+      usage.push('\n');
+      usage.push_str(&doc.text);
+      usage.push('\n');
+    }
+    if !doc.argdocs.is_empty() {
+      usage.push_str("\narguments:\n");
+      for (arg, description) in &doc.argdocs {
+        match doc.defaults.iter().find(|(key, _)| key == arg) {
+          Some((_, default)) => usage.push_str(&format!("  {} [{}]\n", arg, default)),
+          None => usage.push_str(&format!("  {}\n", arg)),
+        }
+        for line in description.split('\n') {
+          usage.push_str(&format!("    {}\n", line));
+        }
+      }
+    }
+    if !doc.presets.is_empty() {
+      usage.push_str("\npresets:\n");
+      for (preset, _) in &doc.presets {
+        usage.push_str(&format!("  {}\n", preset));
+      }
+    }
+    usage
+  }
+
+  /// Returns the layered arguments for command line items `argv`.
+  ///
+  /// The layers are, in increasing order of precedence: the defaults of the
+  /// docstring, environment variables `FUNC_ARG` and the command line items in
+  /// order. A command line item is either a preset name, a configuration file
+  /// `@path` or an argument `key=value`. Also returns, per argument given as
+  /// `key=value` item, the index of the item in `argv`.
+  fn arguments(&self, py: Python, doc: &DocString, params: &[String], argv: &[String]) -> Result<(String, Vec<(String, usize)>), RunError> {
+    let mut args = String::new();
+    let mut origins = Vec::new();
+    for (key, value) in &doc.defaults {
+      args = merge(&args, &format!("{}={}", key, protect(value, ',')))?;
+    }
+    let prefix = self.func.as_ref(py).getattr("__name__")?.extract::<String>()?.to_uppercase();
+    for param in params {
+      if let Ok(value) = std::env::var(format!("{}_{}", prefix, param.to_uppercase())) {
+        args = merge(&args, &format!("{}={}", param, protect(&value, ',')))?;
+      }
+    }
+    for (index, item) in argv.iter().enumerate() {
+      if item.starts_with('@') {
+        args = merge(&args, &file::read_config(py, std::path::Path::new(&item[1..]))?)?;
+      } else if let Ok((key, value)) = safesplit_once(item, '=') {
+        args = merge(&args, &format!("{}={}", key.trim(), protect(value, ',')))?;
+        origins.retain(|(origin, _)| origin != key.trim());
+        origins.push((key.trim().to_string(), index));
+      } else if let Some((_, preset)) = doc.presets.iter().find(|(preset, _)| preset == item) {
+        for (key, value) in preset {
+          args = merge(&args, &format!("{}={}", key, protect(value, ',')))?;
+        }
+      } else {
+        let mut failure = Failure::new(format!("unknown preset '{}'", item));
+        failure.item = Some((item.clone(), 0..item.len()));
+        failure.suggestions = close_matches(py, item, doc.presets.iter().map(|(preset, _)| preset.as_str()).collect())?;
+        return Err(RunError::Failure(failure));
+      }
+    }
+    Ok((args, origins))
+  }
+
+  /// Parses `argv`, calls the function and returns the exit code.
+  fn run(&self, py: Python, prog: &str, argv: &[String]) -> Result<PyObject, RunError> {
+    let func = self.func.as_ref(py);
+    let doc = DocString::new(func)?;
+    if argv.iter().any(|item| item == "-h" || item == "--help") {
+      py.import("sys")?.getattr("stdout")?.call_method1("write", (self.usage(prog, &doc),))?;
+      return Ok(0.to_object(py));
+    }
+    let inspect = py.import("inspect")?;
+    let empty = inspect.getattr("Parameter")?.getattr("empty")?;
+    let params: Vec<&PyAny> = inspect.call1("signature", (func,))?.getattr("parameters")?.call_method0("values")?.iter()?.collect::<PyResult<_>>()?;
+    let names: Vec<String> = params.iter().map(|param| param.getattr("name")?.extract()).collect::<PyResult<_>>()?;
+    let (args, origins) = self.arguments(py, &doc, &names, argv)?;
+    let origin = |key: &str| origins.iter().find(|(origin, _)| origin == key).map(|(_, index)| argv[*index].clone());
+    let kwargs = PyDict::new(py);
+    for (key, value) in items(&args)? {
+      let param = match names.iter().position(|name| name == key) {
+        Some(index) => params[index],
+        None => {
+          let mut failure = Failure::new(format!("unknown argument '{}'", key));
+          failure.item = origin(key).map(|item| (item, 0..key.len()));
+          failure.suggestions = close_matches(py, key, names.iter().map(|name| name.as_str()).collect())?;
+          return Err(RunError::Failure(failure));
+        }
+      };
+      let annotation = param.getattr("annotation")?;
+      let annotation = if annotation.as_ptr() == empty.as_ptr() { py.import("builtins")?.getattr("str")? } else { annotation };
+      match loads_value(py, annotation, value) {
+        Ok(value) => kwargs.set_item(key, value)?,
+        Err(err) if err.is_instance::<SerializationError>(py) => {
+          let mut failure = Failure::new(format!("invalid value for argument '{}': {}", key, err.to_object(py).as_ref(py)));
+          failure.item = origin(key).map(|item| {
+            let start = item.find('=').map_or(0, |index| index + 1);
+            let end = item.len();
+            (item, start..end)
+          });
+          return Err(RunError::Failure(failure));
+        }
+        Err(err) => return Err(err.into()),
+      }
+    }
+    for (param, name) in params.iter().zip(&names) {
+      if param.getattr("default")?.as_ptr() == empty.as_ptr() && ![2, 4].contains(&param.getattr("kind")?.extract::<i32>()?) && kwargs.get_item(name.as_str()).is_none() {
+        return Err(RunError::Failure(Failure::new(format!("missing argument '{}'", name))));
+      }
+    }
+    let result = func.call((), Some(kwargs))?;
+    Ok(if result.is_none() { 0.to_object(py) } else { result.into() })
+  }
+
+  /// Reports a failure on stderr and returns exit code 2, or raises a
+  /// `SerializationError` if pretty errors are disabled.
+  fn report(&self, py: Python, prog: &str, failure: Failure) -> PyResult<PyObject> {
+    if !self.pretty_errors {
+      return Err(SerializationError::py_err(failure.message));
+    }
+    let stderr = py.import("sys")?.getattr("stderr")?;
+    let color = match self.color {
+      Some(color) => color,
+      None => std::env::var_os("NO_COLOR").is_none() && stderr.call_method0("isatty")?.is_true()?,
+    };
+    stderr.call_method1("write", (failure.render(prog, color),))?;
+    Ok(2.to_object(py))
+  }
+}
+
+#[pymethods]
+impl Main {
+  #[call]
+  #[args(argv = "None")]
+  fn __call__(&self, py: Python, argv: Option<Vec<String>>) -> PyResult<PyObject> {
+    guard("stringly.cli.Main.__call__", || {
+      let sys = py.import("sys")?;
+      let sys_argv: Vec<String> = sys.getattr("argv")?.extract()?;
+      let prog = py.import("os")?.getattr("path")?.call_method1("basename", (sys_argv.first().map_or("", |arg| arg.as_str()),))?.extract::<String>()?;
+      let argv = argv.unwrap_or_else(|| sys_argv.into_iter().skip(1).collect());
+      match self.run(py, &prog, &argv) {
+        Ok(code) => Ok(code),
+        Err(RunError::Python(err)) if err.is_instance::<SerializationError>(py) => self.report(py, &prog, Failure::new(format!("{}", err.to_object(py).as_ref(py)))),
+        Err(RunError::Python(err)) => Err(err),
+        Err(RunError::Failure(failure)) => self.report(py, &prog, failure),
+      }
+    })
+  }
+}
+
+/// Returns a console script entry point for `func`.
+///
+/// The entry point resolves presets, configuration files, environment
+/// variables and `key=value` arguments from the command line, see the usage
+/// printed by `--help`, and calls `func` with the parsed arguments. The exit
+/// code is the return value of `func`, or 0 if it returns `None`. Use as
+/// `main = stringly.cli.main(func)` and `console_scripts` target `pkg.mod:main`.
+///
+/// Parse errors are rendered on stderr, marking the offending command line
+/// item and suggesting close matches, with exit code 2. Colors are used if
+/// `color` is true, or if `color` is `None`, stderr is a terminal and
+/// `NO_COLOR` is not set. If `pretty_errors` is false parse errors are raised
+/// as `SerializationError` instead.
+#[pyfunction(pretty_errors = "true", color = "None")]
+#[text_signature = "(func, pretty_errors=True, color=None)"]
+fn main(func: &PyAny, pretty_errors: bool, color: Option<bool>) -> Main {
+  Main { func: func.into(), pretty_errors, color }
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_class::<TypedAction>()?;
+  m.add_class::<Commands>()?;
+  m.add_wrapped(wrap_pyfunction!(from_class))?;
+  m.add_class::<Main>()?;
+  m.add_wrapped(wrap_pyfunction!(main))?;
+
+  Ok(())
+}
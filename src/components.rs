@@ -0,0 +1,107 @@
+use crate::guard;
+use pyo3::exceptions::TypeError;
+use pyo3::proc_macro::pyfunction;
+use pyo3::types::{PyAny, PyModule, PyTuple};
+use pyo3::{wrap_pyfunction, PyObject, PyResult, Python};
+
+/// The class attribute that marks a class as a component, see `discover`.
+const MARKER: &str = "__stringly_component__";
+
+/// Returns whether class `cls` itself, not a base class, is a component.
+fn is_component(cls: &PyAny) -> PyResult<bool> {
+  Ok(cls.getattr("__dict__")?.call_method1("__contains__", (MARKER,))?.is_true()?)
+}
+
+/// Marks class `cls` as a component and returns it.
+fn mark<'py>(py: Python, cls: &'py PyAny, decorator: &str) -> PyResult<&'py PyAny> {
+  let builtins = py.import("builtins")?;
+  if !builtins.call1("isinstance", (cls, builtins.getattr("type")?))?.is_true()? {
+    return Err(TypeError::py_err(format!("{} expects a class but got {}", decorator, cls.repr()?)));
+  }
+  cls.setattr(MARKER, true)?;
+  Ok(cls)
+}
+
+/// Class decorator that makes `cls` a dataclass, unless it is one already,
+/// and marks it as a component for `discover`.
+#[pyfunction]
+#[text_signature = "(cls, /)"]
+fn struct_<'py>(py: Python<'py>, cls: &'py PyAny) -> PyResult<&'py PyAny> {
+  guard("stringly.struct", || {
+    let dataclasses = py.import("dataclasses")?;
+    let cls = if dataclasses.call1("is_dataclass", (cls,))?.is_true()? { cls } else { dataclasses.call1("dataclass", (cls,))? };
+    mark(py, cls, "stringly.struct")
+  })
+}
+
+/// Class decorator that marks `cls`, e.g. an `enum.Enum` or a class that
+/// serializes itself, as a component for `discover` without changing it.
+#[pyfunction]
+#[text_signature = "(cls, /)"]
+fn choice<'py>(py: Python<'py>, cls: &'py PyAny) -> PyResult<&'py PyAny> {
+  guard("stringly.choice", || mark(py, cls, "stringly.choice"))
+}
+
+/// Returns the union of the components, classes decorated with
+/// `stringly.struct` or `stringly.choice`, defined in `package` and its
+/// subpackages, e.g. `Solver = stringly.discover('myapp.solvers')`.
+///
+/// The package, a module or its name, may be a namespace package spread over
+/// several distributions; all its modules are imported. If `base` is given
+/// only its subclasses are included. The components are ordered by module
+/// name and then by definition; a single component is returned as is. The
+/// variants of the union are tagged by class name or by the short name
+/// registered by `stringly.alias`.
+#[pyfunction(base = "None")]
+#[text_signature = "(package, base=None)"]
+fn discover(py: Python, package: &PyAny, base: Option<&PyAny>) -> PyResult<PyObject> {
+  guard("stringly.discover", || {
+    let builtins = py.import("builtins")?;
+    let importlib = py.import("importlib")?;
+    let package = match package.extract::<&str>() {
+      Ok(name) => importlib.call_method1("import_module", (name,))?,
+      Err(_) => package,
+    };
+    let mut modules = vec![package];
+    if let Ok(path) = package.getattr("__path__") {
+      let prefix = format!("{}.", package.getattr("__name__")?.extract::<&str>()?);
+      let mut names = Vec::new();
+      for info in py.import("pkgutil")?.call_method1("walk_packages", (path, prefix))?.iter()? {
+        names.push(info?.getattr("name")?.extract::<String>()?);
+      }
+      names.sort();
+      for name in names {
+        modules.push(importlib.call_method1("import_module", (name,))?);
+      }
+    }
+    let mut components: Vec<&PyAny> = Vec::new();
+    for module in modules {
+      let module_name: String = module.getattr("__name__")?.extract()?;
+      for value in module.getattr("__dict__")?.call_method0("values")?.iter()? {
+        let value = value?;
+        if !builtins.call1("isinstance", (value, builtins.getattr("type")?))?.is_true()? || !is_component(value)? || value.getattr("__module__")?.extract::<&str>().ok() != Some(module_name.as_str()) {
+          continue;
+        }
+        if let Some(base) = base {
+          if !builtins.call1("issubclass", (value, base))?.is_true()? {
+            continue;
+          }
+        }
+        components.push(value);
+      }
+    }
+    match components.len() {
+      0 => Err(TypeError::py_err(format!("no components found in {}", package.getattr("__name__")?.extract::<&str>()?))),
+      1 => Ok(components[0].into()),
+      _ => Ok(py.import("typing")?.getattr("Union")?.get_item(PyTuple::new(py, components))?.into()),
+    }
+  })
+}
+
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+  m.add("struct", wrap_pyfunction!(struct_)(py))?;
+  m.add_wrapped(wrap_pyfunction!(choice))?;
+  m.add_wrapped(wrap_pyfunction!(discover))?;
+
+  Ok(())
+}
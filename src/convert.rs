@@ -0,0 +1,110 @@
+use crate::merge::join;
+use crate::schema::{build_with, Schema};
+use crate::{dumps_value_with, guard, loads_value_with, options, preprocess, SerializationError};
+use pyo3::proc_macro::{pyclass, pyfunction, pymethods};
+use pyo3::types::{PyAny, PyDict, PyModule};
+use pyo3::{wrap_pyfunction, PyResult, Python};
+
+/// The result of `stringly.convert`: the serialized `value` of the new type
+/// and the fields, as dotted paths, that were renamed, dropped because the
+/// new type lacks them, or filled from defaults of the new type.
+#[pyclass]
+#[derive(Default)]
+pub struct Conversion {
+  #[pyo3(get)]
+  value: String,
+  #[pyo3(get)]
+  renamed: Vec<(String, String)>,
+  #[pyo3(get)]
+  dropped: Vec<String>,
+  #[pyo3(get)]
+  defaulted: Vec<String>,
+}
+
+#[pymethods]
+impl Conversion {
+  fn __repr__(&self) -> String {
+    format!("Conversion(value={:?}, renamed={:?}, dropped={:?}, defaulted={:?})", self.value, self.renamed, self.dropped, self.defaulted)
+  }
+}
+
+impl Conversion {
+  /// Returns serialized string `s` of schema `old` re-targeted to schema
+  /// `new`, with the fields of nested structs at path `prefix` renamed by
+  /// `renames`.
+  fn convert(&mut self, old: &dyn Schema, new: &dyn Schema, s: &str, renames: &[(String, String)], prefix: &str) -> PyResult<String> {
+    let (old, new) = match (old.as_struct(), new.as_struct()) {
+      (Some(old), Some(new)) => (old, new),
+      _ => return Ok(s.to_string()),
+    };
+    let mut items = Vec::new();
+    for (key, value) in old.items(s)? {
+      let key = key.trim();
+      let path = format!("{}{}", prefix, key);
+      let name = match renames.iter().find(|(from, _)| *from == path) {
+        Some((_, to)) => {
+          self.renamed.push((path.clone(), format!("{}{}", prefix, to)));
+          to.as_str()
+        }
+        None => key,
+      };
+      let field = match new.fields.iter().find(|field| field.name == name) {
+        Some(field) => field,
+        None => {
+          self.dropped.push(path);
+          continue;
+        }
+      };
+      let value = match old.fields.iter().find(|field| field.name == key) {
+        Some(old_field) => self.convert(old_field.schema.as_ref(), field.schema.as_ref(), value, renames, &format!("{}.", path))?,
+        None => value.to_string(),
+      };
+      items.push((name.to_string(), value));
+    }
+    for field in &new.fields {
+      if items.iter().any(|(key, _)| *key == field.name) {
+        continue;
+      }
+      let path = format!("{}{}", prefix, field.name);
+      if field.required && field.env.is_none() {
+        return Err(SerializationError::py_err(format!("missing value for field '{}' of the new type", path)));
+      }
+      self.defaulted.push(path);
+    }
+    Ok(join(&items))
+  }
+}
+
+/// Re-targets `value`, serialized according to annotation `old_type`, to the
+/// revised annotation `new_type` and returns a `Conversion`.
+///
+/// Fields of structs are matched by name, after applying `renames`, a mapping
+/// of dotted paths of old fields to their new names, e.g.
+/// `{'solver.tol': 'tolerance'}`. Fields that the new type lacks are dropped
+/// and fields that the value lacks are filled from the defaults of the new
+/// type; a missing field without default is an error. The result is loaded
+/// and dumped according to the new type, for one-off refactors of a type
+/// that do not warrant a migration.
+#[pyfunction(renames = "None", options = "**")]
+#[text_signature = "(old_type, new_type, value, renames=None, **options)"]
+fn convert(py: Python, old_type: &PyAny, new_type: &PyAny, value: &str, renames: Option<&PyDict>, options: Option<&PyDict>) -> PyResult<Conversion> {
+  guard("stringly.convert", || {
+    let options = options::current().with(options)?;
+    let renames = match renames {
+      Some(renames) => renames.iter().map(|(from, to)| Ok((from.extract()?, to.extract()?))).collect::<PyResult<Vec<(String, String)>>>()?,
+      None => Vec::new(),
+    };
+    let mut conversion = Conversion::default();
+    let converted = conversion.convert(build_with(py, old_type, &options)?.as_ref(), build_with(py, new_type, &options)?.as_ref(), &preprocess(value, &options)?, &renames, "")?;
+    let value = loads_value_with(py, new_type, &converted, &options)?;
+    conversion.value = dumps_value_with(py, new_type, value.as_ref(py), &options)?;
+    Ok(conversion)
+  })
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_class::<Conversion>()?;
+  m.add_wrapped(wrap_pyfunction!(convert))?;
+
+  Ok(())
+}
@@ -0,0 +1,469 @@
+use crate::text::{split_and_dedent, IterLines, JoinLines, LineIter};
+use crate::{dumps_value, file, guard, loads_value, merge, options, SerializationError, StringlyWarning};
+use pyo3::proc_macro::{pyclass, pyfunction, pymethods};
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyList, PyModule};
+use pyo3::type_object::PyTypeObject;
+use pyo3::{wrap_pyfunction, AsPyPointer, PyErr, PyObject, PyResult, Python, ToPyObject};
+
+/// The parsed docstring of a function or class.
+///
+/// Besides free text a docstring may contain `.. arguments::` blocks that
+/// document the arguments, their types and their defaults as
+/// `name: type [default]` with optional type and default, each argument in
+/// one block only, and a `.. presets::` block of named `key=value` parameter
+/// sets. Other directives are kept as text; with the option
+/// `warn_unknown_directives` they are reported by a `StringlyWarning`.
+#[pyclass]
+pub struct DocString {
+  pub doc: String,
+  #[pyo3(get)]
+  pub text: String,
+  pub defaults: Vec<(String, String)>,
+  pub types: Vec<(String, String)>,
+  pub argdocs: Vec<(String, String)>,
+  pub presets: Vec<(String, Vec<(String, String)>)>,
+}
+
+#[pymethods]
+impl DocString {
+  #[new]
+  pub fn new(f: &PyAny) -> PyResult<Self> {
+    guard("stringly.DocString", || {
+      let py = f.py();
+      let warn_unknown = options::current().warn_unknown_directives;
+      let doc = if let Ok(doc) = f.getattr("__doc__") { doc.extract()? } else { "" };
+      let lines = split_and_dedent(doc);
+
+      let doc = lines.iter().copied().join_lines();
+
+      let mut text = String::new();
+      let mut defaults = Vec::new();
+      let mut types = Vec::new();
+      let mut argdocs = Vec::new();
+      let mut presets = Vec::new();
+      let mut lines = lines.iter_lines();
+      lines.gobble_empty_lines();
+      // Parse blocks separated by white lines.
+      while let Some(line) = lines.peek_unempty() {
+        // Gobble empty lines.
+        lines.gobble_empty_lines();
+        if line == ".. arguments::" {
+          lines.next();
+          let mut lines = lines.dedent(3);
+          while let Some(line) = lines.next() {
+            let arg = line.trim();
+            let (arg, default) = match (arg.find(" ["), arg.ends_with(']')) {
+              (Some(index), true) => (&arg[..index], Some(&arg[index + 2..arg.len() - 1])),
+              _ => (arg, None),
+            };
+            let arg = match arg.find(':') {
+              Some(index) => {
+                types.push((arg[..index].trim_end().to_string(), arg[index + 1..].trim().to_string()));
+                arg[..index].trim_end()
+              }
+              None => arg,
+            };
+            // Several blocks, e.g. concatenated by decorators, are merged.
+            if argdocs.iter().any(|(other, _)| other == arg) {
+              return Err(SerializationError::py_err(format!("argument '{}' is documented more than once", arg)));
+            }
+            if let Some(default) = default {
+              defaults.push((arg.to_string(), default.to_string()));
+            }
+            let description = lines.dedent(2).join_lines();
+            argdocs.push((arg.to_string(), description.trim_end().to_string()));
+          }
+        } else if line == ".. presets::" {
+          lines.next();
+          let mut lines = lines.dedent(3);
+          while let Some(line) = lines.next() {
+            let preset = line.trim();
+            let mut parameters = Vec::new();
+            use stringly::util::{safesplit, safesplit_once, unprotect};
+            let parameter_string = file::deprettify(&lines.dedent(2).join_lines())?;
+            for si in safesplit(&parameter_string, ',') {
+              if let Ok((key, value)) = safesplit_once(si, '=') {
+                parameters.push((unprotect(key).to_string(), unprotect(value).to_string()));
+              } else {
+                return Err(SerializationError::py_err(format!("preset {} has no value for argument {}", preset, unprotect(si))));
+              }
+            }
+            presets.push((preset.to_string(), parameters));
+          }
+        } else {
+          if warn_unknown && line.starts_with(".. ") && line.trim_end().ends_with("::") {
+            let message = format!("unknown directive '{}' on line {} of the docstring of {}, treated as text", line.trim(), lines.line_number(), f.repr()?);
+            PyErr::warn(py, StringlyWarning::type_object(py).as_ref(), &message, 1)?;
+          }
+          // Insert a white line unless this is the first paragraph.
+          if !text.is_empty() {
+            text.push('\n');
+          }
+          // Copy a single paragraph.
+          while let Some(line) = lines.next_if_unempty() {
+            text.push_str(line);
+            text.push('\n');
+          }
+        }
+      }
+
+      Ok(DocString { doc, text: text.trim().to_string(), defaults, types, argdocs, presets })
+    })
+  }
+  fn __str__(&self) -> PyResult<&str> {
+    Ok(&self.doc)
+  }
+  #[getter]
+  fn defaults<'py>(&self, py: Python<'py>) -> &'py PyDict {
+    self.defaults[..].into_py_dict(py)
+  }
+  #[getter]
+  fn types<'py>(&self, py: Python<'py>) -> &'py PyDict {
+    self.types[..].into_py_dict(py)
+  }
+  #[getter]
+  fn argdocs<'py>(&self, py: Python<'py>) -> &'py PyDict {
+    self.argdocs[..].into_py_dict(py)
+  }
+  #[getter]
+  fn presets<'py>(&self, py: Python<'py>) -> &'py PyDict {
+    let p: Vec<(&str, &PyDict)> = self.presets.iter().map(|(key, value)| (key.as_str(), value.into_py_dict(py))).collect();
+    p[..].into_py_dict(py)
+  }
+  /// Returns the defaults as a single struct string, for use as the base of
+  /// `stringly.merge` or `stringly.loads`.
+  fn default_string(&self) -> PyResult<String> {
+    guard("stringly.DocString.default_string", || Ok(merge::join(&self.defaults)))
+  }
+  /// Returns the text followed by the arguments as reStructuredText field
+  /// lists, `:param name:`, `:type name:` and `:default name:`, for Sphinx,
+  /// e.g. from an `autodoc-process-docstring` handler.
+  fn to_rst_fields(&self) -> String {
+    let mut s = self.text.clone();
+    if !self.argdocs.is_empty() && !s.is_empty() {
+      s.push_str("\n\n");
+    }
+    for (arg, description) in &self.argdocs {
+      let mut lines = description.lines();
+      s.push_str(&format!(":param {}:", arg));
+      if let Some(line) = lines.next().filter(|line| !line.is_empty()) {
+        s.push(' ');
+        s.push_str(line);
+      }
+      s.push('\n');
+      for line in lines {
+        s.push_str(if line.is_empty() { "" } else { "   " });
+        s.push_str(line);
+        s.push('\n');
+      }
+      if let Some((_, ty)) = self.types.iter().find(|(key, _)| key == arg) {
+        s.push_str(&format!(":type {}: {}\n", arg, ty));
+      }
+      if let Some((_, default)) = self.defaults.iter().find(|(key, _)| key == arg) {
+        s.push_str(&format!(":default {}: ``{}``\n", arg, default));
+      }
+    }
+    s
+  }
+}
+
+/// A parameter of a signature, see `parameters`.
+struct Parameter<'py> {
+  name: String,
+  /// Whether the parameter can be passed by keyword.
+  keyword: bool,
+  annotation: Option<&'py PyAny>,
+  default: Option<&'py PyAny>,
+}
+
+/// Returns the parameters of `func`, excluding `*args` and `**kwargs`.
+fn parameters<'py>(py: Python<'py>, func: &'py PyAny) -> PyResult<Vec<Parameter<'py>>> {
+  let inspect = py.import("inspect")?;
+  let empty = inspect.getattr("Parameter")?.getattr("empty")?;
+  let mut parameters = Vec::new();
+  for param in inspect.call1("signature", (func,))?.getattr("parameters")?.call_method0("values")?.iter()? {
+    let param = param?;
+    if [2, 4].contains(&param.getattr("kind")?.extract::<i32>()?) {
+      continue;
+    }
+    let annotation = param.getattr("annotation")?;
+    let default = param.getattr("default")?;
+    let keyword = param.getattr("kind")?.extract::<i32>()? != 0;
+    parameters.push(Parameter { name: param.getattr("name")?.extract()?, keyword, annotation: if annotation.as_ptr() == empty.as_ptr() { None } else { Some(annotation) }, default: if default.as_ptr() == empty.as_ptr() { None } else { Some(default) } });
+  }
+  Ok(parameters)
+}
+
+/// Returns a docstring for `func` with an `.. arguments::` block covering all
+/// parameters of its signature.
+///
+/// The text, argument descriptions and presets of the current docstring are
+/// kept. Defaults are taken from the current docstring or else serialized
+/// from the defaults of the signature.
+#[pyfunction]
+#[text_signature = "(func, /)"]
+fn generate(py: Python, func: &PyAny) -> PyResult<String> {
+  guard("stringly.doc.generate", || {
+    let doc = DocString::new(func)?;
+    let mut s = doc.text.clone();
+    let parameters = parameters(py, func)?;
+    if !parameters.is_empty() {
+      if !s.is_empty() {
+        s.push_str("\n\n");
+      }
+      s.push_str(".. arguments::\n");
+      for param in &parameters {
+        s.push_str("\n   ");
+        s.push_str(&param.name);
+        if let Some((_, ty)) = doc.types.iter().find(|(key, _)| *key == param.name) {
+          s.push_str(&format!(": {}", ty));
+        }
+        match (doc.defaults.iter().find(|(key, _)| *key == param.name), param.default) {
+          (Some((_, default)), _) => s.push_str(&format!(" [{}]", default)),
+          (None, Some(default)) => s.push_str(&format!(" [{}]", dumps_value(py, param.annotation.unwrap_or_else(|| default.get_type().as_ref()), default)?)),
+          (None, None) => (),
+        }
+        s.push('\n');
+        if let Some((_, description)) = doc.argdocs.iter().find(|(key, _)| *key == param.name) {
+          for line in description.split('\n') {
+            s.push_str(if line.is_empty() { "" } else { "     " });
+            s.push_str(line);
+            s.push('\n');
+          }
+        }
+      }
+    }
+    if !doc.presets.is_empty() {
+      s.push_str("\n.. presets::\n");
+      for (preset, parameters) in &doc.presets {
+        s.push_str("\n   ");
+        s.push_str(preset);
+        s.push('\n');
+        let parameters = parameters.iter().map(|(key, value)| format!("{}={}", key, stringly::util::protect(value, ','))).collect::<Vec<_>>().join(",");
+        for line in stringly::util::prettify(&parameters).lines() {
+          s.push_str("     ");
+          s.push_str(line);
+          s.push('\n');
+        }
+      }
+    }
+    Ok(s)
+  })
+}
+
+/// Returns the problems of the docstring of `func` as a list of messages.
+///
+/// Reported are arguments that are documented but are not parameters of the
+/// signature and vice versa, defaults that do not load according to the
+/// annotation of the parameter and presets with unknown arguments.
+#[pyfunction]
+#[text_signature = "(func, /)"]
+fn validate(py: Python, func: &PyAny) -> PyResult<Vec<String>> {
+  guard("stringly.doc.validate", || {
+    let doc = DocString::new(func)?;
+    let parameters = parameters(py, func)?;
+    let mut problems = Vec::new();
+    for (arg, _) in &doc.argdocs {
+      if !parameters.iter().any(|param| param.name == *arg) {
+        problems.push(format!("argument '{}' is documented but is not a parameter", arg));
+      }
+    }
+    for param in &parameters {
+      if !doc.argdocs.iter().any(|(arg, _)| *arg == param.name) {
+        problems.push(format!("parameter '{}' is not documented", param.name));
+      }
+    }
+    for (arg, default) in &doc.defaults {
+      if let Some(annotation) = parameters.iter().find(|param| param.name == *arg).and_then(|param| param.annotation) {
+        if let Err(err) = loads_value(py, annotation, default) {
+          problems.push(format!("default '{}' of argument '{}' is invalid: {}", default, arg, err.to_object(py).as_ref(py)));
+        }
+      }
+    }
+    for (preset, values) in &doc.presets {
+      for (arg, _) in values {
+        if !parameters.iter().any(|param| param.name == *arg) {
+          problems.push(format!("preset '{}' sets unknown argument '{}'", preset, arg));
+        }
+      }
+    }
+    Ok(problems)
+  })
+}
+
+/// Returns the public callables of `module` and their names: the functions
+/// and classes defined in the module itself, or the names of `__all__` if
+/// defined, in order of definition.
+fn public_callables<'py>(py: Python<'py>, module: &'py PyAny) -> PyResult<Vec<(String, &'py PyAny)>> {
+  let builtins = py.import("builtins")?;
+  let module_name: String = module.getattr("__name__")?.extract()?;
+  let mut callables = Vec::new();
+  if let Ok(names) = module.getattr("__all__") {
+    for name in names.iter()? {
+      let name: String = name?.extract()?;
+      let value = module.getattr(name.as_str())?;
+      if builtins.call1("callable", (value,))?.is_true()? {
+        callables.push((name, value));
+      }
+    }
+    return Ok(callables);
+  }
+  for item in module.getattr("__dict__")?.call_method0("items")?.iter()? {
+    let (name, value): (String, &PyAny) = item?.extract()?;
+    if name.starts_with('_') || !builtins.call1("callable", (value,))?.is_true()? {
+      continue;
+    }
+    if value.getattr("__module__").and_then(|owner| owner.extract::<String>()).map_or(false, |owner| owner == module_name) {
+      callables.push((name, value));
+    }
+  }
+  Ok(callables)
+}
+
+/// Returns the reference documentation of the public callables of `module`,
+/// see `public_callables`, parsed from their docstrings.
+///
+/// The result is a list of dicts with the `name`, the `signature`, the free
+/// `text`, the `arguments` as dicts of `name`, `type`, `default` and `doc`,
+/// with `None` for what is not documented, and the `presets` of each
+/// callable. With `render=True` the documentation is instead rendered as
+/// text, e.g. for the reference page of a command line tool.
+#[pyfunction(render = "false")]
+#[text_signature = "(module, render=False)"]
+fn document_module(py: Python, module: &PyAny, render: bool) -> PyResult<PyObject> {
+  guard("stringly.doc.document_module", || {
+    let inspect = py.import("inspect")?;
+    let entries = PyList::empty(py);
+    let mut s = String::new();
+    for (name, func) in public_callables(py, module)? {
+      let doc = DocString::new(func)?;
+      let signature = match inspect.call1("signature", (func,)) {
+        Ok(signature) => signature.str()?.to_str()?.to_string(),
+        Err(_) => "(...)".to_string(),
+      };
+      let find = |list: &[(String, String)], arg: &str| list.iter().find(|(key, _)| key == arg).map(|(_, value)| value.clone());
+      if render {
+        if !s.is_empty() {
+          s.push('\n');
+        }
+        s.push_str(&format!("{}{}\n", name, signature));
+        for line in doc.text.lines() {
+          s.push_str(if line.is_empty() { "" } else { "    " });
+          s.push_str(line);
+          s.push('\n');
+        }
+        for (arg, description) in &doc.argdocs {
+          s.push_str(&format!("\n    {}", arg));
+          if let Some(ty) = find(&doc.types, arg) {
+            s.push_str(&format!(": {}", ty));
+          }
+          if let Some(default) = find(&doc.defaults, arg) {
+            s.push_str(&format!(" [{}]", default));
+          }
+          s.push('\n');
+          for line in description.lines() {
+            s.push_str(if line.is_empty() { "" } else { "        " });
+            s.push_str(line);
+            s.push('\n');
+          }
+        }
+        continue;
+      }
+      let arguments = PyList::empty(py);
+      for (arg, description) in &doc.argdocs {
+        let argument = vec![("name", arg.to_object(py)), ("type", find(&doc.types, arg).to_object(py)), ("default", find(&doc.defaults, arg).to_object(py)), ("doc", description.to_object(py))];
+        arguments.append(argument.into_py_dict(py))?;
+      }
+      let entry = vec![("name", name.to_object(py)), ("signature", signature.to_object(py)), ("text", doc.text.to_object(py)), ("arguments", arguments.to_object(py)), ("presets", doc.presets(py).to_object(py))];
+      entries.append(entry.into_py_dict(py))?;
+    }
+    if render {
+      Ok(s.to_object(py))
+    } else {
+      Ok(entries.to_object(py))
+    }
+  })
+}
+
+/// Returns a `TypedDict` of the arguments of the `.. arguments::` block of the
+/// docstring of `func`.
+///
+/// The type of an argument is its declared type string, see
+/// `stringly.typing.parse_type_str`, else the type of its default in the
+/// signature, else `str`. Arguments with a default, in the docstring or in
+/// the signature, are not required: they are marked `NotRequired`, of
+/// `typing` or else of `typing_extensions` if installed, or otherwise declared
+/// in a `total=False` subclass of a `TypedDict` of the required arguments.
+pub fn type_from_docstring(py: Python, func: &PyAny) -> PyResult<PyObject> {
+  let doc = DocString::new(func)?;
+  let parameters = parameters(py, func)?;
+  let typing = py.import("typing")?;
+  let (typed_dict, not_required) = match typing.getattr("NotRequired") {
+    Ok(not_required) => (typing.getattr("TypedDict")?, Some(not_required)),
+    Err(_) => match py.import("typing_extensions") {
+      Ok(extensions) if extensions.hasattr("NotRequired")? => (extensions.getattr("TypedDict")?, Some(extensions.getattr("NotRequired")?)),
+      _ => (typing.getattr("TypedDict")?, None),
+    },
+  };
+  let annotations = PyDict::new(py);
+  let optional = PyDict::new(py);
+  for (arg, _) in &doc.argdocs {
+    let param = parameters.iter().find(|param| param.name == *arg);
+    let ty: PyObject = match (doc.types.iter().find(|(key, _)| key == arg), param.and_then(|param| param.default)) {
+      (Some((_, ty)), _) => crate::typing::parse(py, ty)?,
+      (None, Some(default)) => default.get_type().into(),
+      (None, None) => py.import("builtins")?.getattr("str")?.into(),
+    };
+    let defaulted = doc.defaults.iter().any(|(key, _)| key == arg) || param.map_or(false, |param| param.default.is_some());
+    match not_required {
+      Some(not_required) if defaulted => annotations.set_item(arg, not_required.get_item(ty)?)?,
+      None if defaulted => optional.set_item(arg, ty)?,
+      _ => annotations.set_item(arg, ty)?,
+    }
+  }
+  let name: String = func.getattr("__name__")?.extract()?;
+  let required = typed_dict.call1((name.as_str(), annotations))?;
+  if optional.len() == 0 {
+    return Ok(required.into());
+  }
+  let namespace = [("__annotations__", optional.as_ref()), ("__module__", required.getattr("__module__")?)].into_py_dict(py);
+  Ok(required.get_type().as_ref().call((name, (required,), namespace), Some([("total", false)].into_py_dict(py)))?.into())
+}
+
+/// Returns a dataclass with a field for every keyword parameter of `func`.
+///
+/// The type of a field is the annotation of the parameter, else the type of
+/// its default, else `str`. Parameters with a default give fields with the
+/// same default, which follow the fields of the parameters without default.
+pub fn kwargs_type(py: Python, func: &PyAny) -> PyResult<PyObject> {
+  let dataclasses = py.import("dataclasses")?;
+  let builtins = py.import("builtins")?;
+  let (mut required, mut defaulted) = (Vec::new(), Vec::new());
+  for param in parameters(py, func)?.into_iter().filter(|param| param.keyword) {
+    let ty: &PyAny = match (param.annotation, param.default) {
+      (Some(annotation), _) => annotation,
+      (None, Some(default)) => default.get_type().as_ref(),
+      (None, None) => builtins.getattr("str")?,
+    };
+    let field = match param.default {
+      Some(default) if default.hash().is_ok() => dataclasses.call("field", (), Some([("default", default)].into_py_dict(py)))?,
+      Some(default) => dataclasses.call("field", (), Some([("default_factory", py.import("functools")?.call1("partial", (py.import("copy")?.getattr("deepcopy")?, default))?)].into_py_dict(py)))?,
+      None => dataclasses.call0("field")?,
+    };
+    let fields = if param.default.is_some() { &mut defaulted } else { &mut required };
+    fields.push((param.name, ty, field).to_object(py));
+  }
+  // Ordered rather than `kw_only`, which requires Python 3.10.
+  required.append(&mut defaulted);
+  let name: String = func.getattr("__name__")?.extract()?;
+  Ok(dataclasses.call1("make_dataclass", (name, required))?.into())
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_class::<DocString>()?;
+  m.add_wrapped(wrap_pyfunction!(generate))?;
+  m.add_wrapped(wrap_pyfunction!(validate))?;
+  m.add_wrapped(wrap_pyfunction!(document_module))?;
+
+  Ok(())
+}
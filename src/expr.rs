@@ -0,0 +1,220 @@
+/// The named constants that are available if constants are enabled.
+///
+/// Physical constants are in SI units.
+pub const CONSTANTS: &[(&str, f64)] = &[
+  ("pi", std::f64::consts::PI),
+  ("e", std::f64::consts::E),
+  ("tau", 2. * std::f64::consts::PI),
+  ("c", 299_792_458.),
+  ("h", 6.626_070_15e-34),
+  ("hbar", 1.054_571_817e-34),
+  ("k_B", 1.380_649e-23),
+  ("N_A", 6.022_140_76e23),
+  ("q_e", 1.602_176_634e-19),
+  ("G", 6.674_30e-11),
+  ("g0", 9.806_65),
+];
+
+/// The maximum nesting depth of parentheses and unary operators.
+const MAX_DEPTH: usize = 100;
+
+/// Evaluates constant arithmetic expression `s`.
+///
+/// The grammar supports decimal number literals, names of `constants`,
+/// parentheses, unary `+` and `-` and the binary operators `+`, `-`, `*`, `/`
+/// and `**`, with the usual precedence. Exponentiation is right associative
+/// and binds tighter than a unary minus on its left, as in Python. Results
+/// that overflow to infinity or are not a number are rejected.
+pub fn evaluate(s: &str, constants: &[(String, f64)]) -> Result<f64, String> {
+  let mut parser = Parser { s, pos: 0, depth: 0, constants };
+  let value = parser.sum()?;
+  parser.skip_whitespace();
+  if parser.pos < s.len() {
+    return Err(format!("unexpected '{}' at position {}", &s[parser.pos..], parser.pos));
+  }
+  if !value.is_finite() {
+    return Err(format!("result {} of '{}' is not a finite number", value, s));
+  }
+  Ok(value)
+}
+
+/// A recursive descent parser and evaluator, see `evaluate`.
+struct Parser<'a> {
+  s: &'a str,
+  pos: usize,
+  depth: usize,
+  constants: &'a [(String, f64)],
+}
+
+impl<'a> Parser<'a> {
+  fn skip_whitespace(&mut self) {
+    self.pos = self.s.len() - self.s[self.pos..].trim_start().len();
+  }
+  /// Consumes `token` if it is next, skipping leading whitespace.
+  fn eat(&mut self, token: &str) -> bool {
+    self.skip_whitespace();
+    if self.s[self.pos..].starts_with(token) {
+      self.pos += token.len();
+      true
+    } else {
+      false
+    }
+  }
+  fn sum(&mut self) -> Result<f64, String> {
+    let mut value = self.product()?;
+    loop {
+      if self.eat("+") {
+        value += self.product()?;
+      } else if self.eat("-") {
+        value -= self.product()?;
+      } else {
+        return Ok(value);
+      }
+    }
+  }
+  fn product(&mut self) -> Result<f64, String> {
+    let mut value = self.unary()?;
+    loop {
+      if self.eat("*") {
+        value *= self.unary()?;
+      } else if self.eat("/") {
+        let divisor = self.unary()?;
+        if divisor == 0. {
+          return Err("division by zero".to_string());
+        }
+        value /= divisor;
+      } else {
+        return Ok(value);
+      }
+    }
+  }
+  fn unary(&mut self) -> Result<f64, String> {
+    if self.depth == MAX_DEPTH {
+      return Err("expression is nested too deeply".to_string());
+    }
+    self.depth += 1;
+    let value = if self.eat("-") {
+      self.unary().map(|value| -value)
+    } else if self.eat("+") {
+      self.unary()
+    } else {
+      self.power()
+    };
+    self.depth -= 1;
+    value
+  }
+  fn power(&mut self) -> Result<f64, String> {
+    let base = self.atom()?;
+    if self.eat("**") {
+      Ok(base.powf(self.unary()?))
+    } else {
+      Ok(base)
+    }
+  }
+  fn atom(&mut self) -> Result<f64, String> {
+    if self.eat("(") {
+      let value = self.sum()?;
+      if !self.eat(")") {
+        return Err(format!("expected ')' at position {}", self.pos));
+      }
+      return Ok(value);
+    }
+    self.skip_whitespace();
+    let start = self.pos;
+    let bytes = self.s.as_bytes();
+    if self.pos < bytes.len() && (bytes[self.pos].is_ascii_alphabetic() || bytes[self.pos] == b'_') {
+      while self.pos < bytes.len() && (bytes[self.pos].is_ascii_alphanumeric() || bytes[self.pos] == b'_') {
+        self.pos += 1;
+      }
+      let name = &self.s[start..self.pos];
+      return match self.constants.iter().find(|(constant, _)| constant == name) {
+        Some((_, value)) => Ok(*value),
+        None => Err(format!("unknown name '{}'", name)),
+      };
+    }
+    while self.pos < bytes.len() && (bytes[self.pos].is_ascii_digit() || bytes[self.pos] == b'.') {
+      self.pos += 1;
+    }
+    if self.pos > start && self.pos < bytes.len() && (bytes[self.pos] == b'e' || bytes[self.pos] == b'E') {
+      let mantissa = self.pos;
+      self.pos += 1;
+      if self.pos < bytes.len() && (bytes[self.pos] == b'+' || bytes[self.pos] == b'-') {
+        self.pos += 1;
+      }
+      if self.pos < bytes.len() && bytes[self.pos].is_ascii_digit() {
+        while self.pos < bytes.len() && bytes[self.pos].is_ascii_digit() {
+          self.pos += 1;
+        }
+      } else {
+        self.pos = mantissa;
+      }
+    }
+    if self.pos == start {
+      return Err(format!("expected a number at position {}", start));
+    }
+    self.s[start..self.pos].parse().map_err(|_| format!("invalid number '{}'", &self.s[start..self.pos]))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn eval(s: &str) -> Result<f64, String> {
+    evaluate(s, &[("e".to_string(), std::f64::consts::E)])
+  }
+
+  #[test]
+  fn precedence() {
+    assert_eq!(eval("1 + 2 * 3"), Ok(7.));
+    assert_eq!(eval("(1 + 2) * 3"), Ok(9.));
+    assert_eq!(eval("-2**2"), Ok(-4.));
+    assert_eq!(eval("(-2)**2"), Ok(4.));
+    assert_eq!(eval("2**-1"), Ok(0.5));
+    assert_eq!(eval("8 / 2 / 2"), Ok(2.));
+  }
+
+  #[test]
+  fn power_is_right_associative() {
+    assert_eq!(eval("2**3**2"), Ok(512.));
+    assert_eq!(eval("(2**3)**2"), Ok(64.));
+  }
+
+  #[test]
+  fn max_depth() {
+    let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(eval(&nested(MAX_DEPTH - 1)), Ok(1.));
+    assert_eq!(eval(&nested(MAX_DEPTH)), Err("expression is nested too deeply".to_string()));
+    assert_eq!(eval(&format!("{}1", "-".repeat(10 * MAX_DEPTH))), Err("expression is nested too deeply".to_string()));
+  }
+
+  #[test]
+  fn division_by_zero() {
+    assert_eq!(eval("1 / 0"), Err("division by zero".to_string()));
+    assert_eq!(eval("1 / (2 - 2)"), Err("division by zero".to_string()));
+  }
+
+  #[test]
+  fn exponent_and_constant_e() {
+    assert_eq!(eval("2e3"), Ok(2000.));
+    assert_eq!(eval("2e-3"), Ok(0.002));
+    assert_eq!(eval("2*e"), Ok(2. * std::f64::consts::E));
+    assert_eq!(eval("2e"), Err("unexpected 'e' at position 1".to_string()));
+    assert_eq!(eval("2e+"), Err("unexpected 'e+' at position 1".to_string()));
+  }
+
+  #[test]
+  fn trailing_garbage() {
+    assert_eq!(eval("1 2"), Err("unexpected '2' at position 2".to_string()));
+    assert_eq!(eval("(1))"), Err("unexpected ')' at position 3".to_string()));
+    assert_eq!(eval("1 +"), Err("expected a number at position 3".to_string()));
+  }
+
+  #[test]
+  fn non_finite() {
+    assert!(eval("10**400").is_err());
+    assert!(eval("1e400").is_err());
+    assert!(eval("10**400 - 10**400").is_err());
+    assert!(eval("(-1)**0.5").is_err());
+  }
+}
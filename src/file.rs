@@ -0,0 +1,187 @@
+use crate::merge::merge;
+use crate::SerializationError;
+use pyo3::exceptions::ValueError;
+use pyo3::types::{PyAny, PyBytes};
+use pyo3::{PyErr, PyResult, Python};
+use std::path::{Path, PathBuf};
+
+/// Returns the filesystem path of a `str` or path-like object.
+pub fn fspath(py: Python, path: &PyAny) -> PyResult<PathBuf> {
+  Ok(PathBuf::from(py.import("os")?.call1("fspath", (path,))?.extract::<String>()?))
+}
+
+/// Returns the deprettified form of `s`.
+pub fn deprettify(s: &str) -> PyResult<String> {
+  match stringly::util::deprettify(s) {
+    Ok(v) => Ok(v),
+    Err(e) => Err(SerializationError::py_err(format!("{:?}", e))),
+  }
+}
+
+/// The action taken when the checksum footer of a file does not match.
+#[derive(Clone, Copy)]
+pub enum OnMismatch {
+  Error,
+  Warn,
+}
+
+impl OnMismatch {
+  pub fn from_str(s: &str) -> PyResult<Self> {
+    match s {
+      "error" => Ok(OnMismatch::Error),
+      "warn" => Ok(OnMismatch::Warn),
+      _ => Err(ValueError::py_err(format!("expected 'error' or 'warn' but got '{}'", s))),
+    }
+  }
+}
+
+const FOOTER: &str = "# sha256: ";
+
+/// Returns the hexadecimal SHA-256 digest of `s`.
+fn digest(py: Python, s: &str) -> PyResult<String> {
+  py.import("hashlib")?.call1("sha256", (PyBytes::new(py, s.as_bytes()),))?.call_method0("hexdigest")?.extract()
+}
+
+/// Appends a checksum footer to the contents of a file.
+pub fn add_footer(py: Python, mut contents: String) -> PyResult<String> {
+  if !contents.ends_with('\n') {
+    contents.push('\n');
+  }
+  let digest = digest(py, &contents)?;
+  contents.push_str(FOOTER);
+  contents.push_str(&digest);
+  contents.push('\n');
+  Ok(contents)
+}
+
+/// Reads a file and verifies and strips the optional checksum footer.
+///
+/// The footer is a last line `# sha256: digest` where `digest` is computed
+/// over all preceding lines.
+pub fn read_file(py: Python, path: &Path, on_mismatch: OnMismatch) -> PyResult<String> {
+  let contents = py.allow_threads(|| std::fs::read_to_string(path))?;
+  let trimmed = contents.trim_end_matches('\n');
+  let (body, footer) = match trimmed.rfind('\n') {
+    Some(index) => (&trimmed[..index + 1], &trimmed[index + 1..]),
+    None => ("", trimmed),
+  };
+  if !footer.starts_with(FOOTER) {
+    return Ok(contents);
+  }
+  if footer[FOOTER.len()..].trim() != digest(py, body)? {
+    let message = format!("checksum mismatch in {}, the file is truncated or was modified", path.display());
+    match on_mismatch {
+      OnMismatch::Error => return Err(SerializationError::py_err(message)),
+      OnMismatch::Warn => PyErr::warn(py, py.import("builtins")?.getattr("UserWarning")?, &message, 1)?,
+    }
+  }
+  Ok(body.to_string())
+}
+
+/// Splits an optional `@extends(parent)` directive from the document body.
+fn split_extends(contents: &str) -> PyResult<(Option<&str>, &str)> {
+  let contents = contents.trim_start();
+  if !contents.starts_with("@extends") {
+    return Ok((None, contents));
+  }
+  let (line, body) = match contents.find('\n') {
+    Some(index) => (contents[..index].trim_end(), &contents[index + 1..]),
+    None => (contents.trim_end(), ""),
+  };
+  if line.starts_with("@extends(") && line.ends_with(')') {
+    Ok((Some(line["@extends(".len()..line.len() - 1].trim()), body))
+  } else {
+    Err(SerializationError::py_err(format!("invalid directive '{}', expected @extends(path_or_name)", line)))
+  }
+}
+
+/// Resolves the parent of an `@extends` directive in file `path`.
+///
+/// The parent is resolved relative to the directory of `path`. If no such file
+/// exists and `parent` has no extension, the extension of `path` is tried.
+fn resolve_parent(path: &Path, parent: &str) -> PathBuf {
+  let resolved = path.parent().unwrap_or_else(|| Path::new("")).join(parent);
+  if resolved.exists() || resolved.extension().is_some() {
+    return resolved;
+  }
+  match path.extension() {
+    Some(extension) => resolved.with_extension(extension),
+    None => resolved,
+  }
+}
+
+/// Reads a configuration file and returns its deprettified contents.
+///
+/// A configuration file may start with a directive `@extends(path_or_name)`,
+/// in which case the contents of the parent file form the base that is
+/// overridden by the contents of this file, see `merge`.
+pub fn read_config(py: Python, path: &Path) -> PyResult<String> {
+  read_config_chain(py, path, &mut Vec::new())
+}
+
+fn read_config_chain(py: Python, path: &Path, chain: &mut Vec<PathBuf>) -> PyResult<String> {
+  let canonical = path.canonicalize()?;
+  if chain.contains(&canonical) {
+    return Err(SerializationError::py_err(format!("circular @extends directive in {}", path.display())));
+  }
+  chain.push(canonical);
+  let contents = read_file(py, path, OnMismatch::Error)?;
+  let (parent, body) = split_extends(&contents)?;
+  let body = if body.trim().is_empty() { String::new() } else { deprettify(body)? };
+  let config = match parent {
+    Some(parent) => merge(&read_config_chain(py, &resolve_parent(path, parent), chain)?, &body)?,
+    None => body,
+  };
+  chain.pop();
+  Ok(config)
+}
+
+/// Splits the contents of a multi-document file into named documents.
+///
+/// Documents are introduced by a separator line `--- name` and are returned
+/// deprettified, in order of appearance.
+pub fn split_documents(contents: &str) -> PyResult<Vec<(String, String)>> {
+  let mut documents: Vec<(String, String)> = Vec::new();
+  let mut name: Option<&str> = None;
+  let mut body = String::new();
+  for line in contents.split('\n') {
+    if line.starts_with("---") {
+      if let Some(name) = name {
+        documents.push((name.to_string(), if body.trim().is_empty() { String::new() } else { deprettify(&body)? }));
+      } else if !body.trim().is_empty() {
+        return Err(SerializationError::py_err("expected a separator '--- name' before the first document"));
+      }
+      let next = line[3..].trim();
+      if next.is_empty() {
+        return Err(SerializationError::py_err("document separator '---' has no name"));
+      }
+      if documents.iter().any(|(name, _)| name == next) {
+        return Err(SerializationError::py_err(format!("duplicate document '{}'", next)));
+      }
+      name = Some(next);
+      body.clear();
+    } else {
+      body.push_str(line);
+      body.push('\n');
+    }
+  }
+  match name {
+    Some(name) => documents.push((name.to_string(), if body.trim().is_empty() { String::new() } else { deprettify(&body)? })),
+    None if !body.trim().is_empty() => return Err(SerializationError::py_err("expected a separator '--- name' before the first document")),
+    None => (),
+  }
+  Ok(documents)
+}
+
+/// Joins named documents to the contents of a multi-document file.
+pub fn join_documents<'a>(documents: impl Iterator<Item = (&'a str, &'a str)>) -> String {
+  let mut contents = String::new();
+  for (name, document) in documents {
+    contents.push_str("--- ");
+    contents.push_str(name);
+    contents.push('\n');
+    contents.push_str(stringly::util::prettify(document).trim_end());
+    contents.push('\n');
+  }
+  contents
+}
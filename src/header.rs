@@ -0,0 +1,84 @@
+use crate::schema::{self, type_name};
+use crate::{registry, SerializationError};
+use pyo3::types::PyAny;
+use pyo3::{AsPyPointer, PyObject, PyResult, Python};
+
+/// The prefix of a header, followed by the version of the format.
+const PREFIX: &str = "!stringly/";
+
+/// The version of the format written by `dumps`.
+const VERSION: u32 = 1;
+
+/// Returns the name of annotation `ty` in a header.
+///
+/// The name is the short name registered by `stringly.alias`, else the import
+/// path `module:qualname` of a class, else the description of the annotation,
+/// e.g. `List[int]`; see `resolve` for the inverse.
+pub fn name(py: Python, ty: &PyAny) -> PyResult<String> {
+  if let Some(name) = registry::type_alias(ty) {
+    return Ok(name);
+  }
+  let builtins = py.import("builtins")?;
+  if builtins.call1("isinstance", (ty, builtins.getattr("type")?))?.is_true()? && ty.getattr("__module__")?.extract::<&str>()? != "builtins" {
+    return Ok(format!("{}:{}", ty.getattr("__module__")?.extract::<&str>()?, ty.getattr("__qualname__")?.extract::<&str>()?));
+  }
+  schema::build(py, ty)?.describe(py)
+}
+
+/// Returns the type registered by `stringly.alias` for header type `name`.
+///
+/// Names are never evaluated or imported, so that a header cannot run code:
+/// only registered types can be selected by a header.
+pub fn resolve(py: Python, name: &str) -> PyResult<PyObject> {
+  match registry::type_aliases(py).into_iter().find(|(alias, _)| alias == name) {
+    Some((_, ty)) => Ok(ty),
+    None => Err(SerializationError::py_err(format!("unknown type '{}' of header, expected a type registered by stringly.alias", name))),
+  }
+}
+
+/// Returns `s` prefixed by the header `!stringly/1 name` of annotation `ty`
+/// on a line of its own.
+pub fn prepend(py: Python, ty: &PyAny, s: &str) -> PyResult<String> {
+  Ok(format!("{}{} {}\n{}", PREFIX, VERSION, name(py, ty)?, s))
+}
+
+/// Returns the version and type name of the header of `s` and the remainder
+/// of `s`, or `None` if `s` has no header.
+pub fn split(s: &str) -> PyResult<Option<(u32, &str, &str)>> {
+  let rest = match s.trim_start().strip_prefix(PREFIX) {
+    Some(rest) => rest,
+    None => return Ok(None),
+  };
+  let (line, body) = match rest.find('\n') {
+    Some(index) => (&rest[..index], &rest[index + 1..]),
+    None => (rest, ""),
+  };
+  let mut words = line.trim().splitn(2, ' ');
+  let version = words.next().unwrap_or("");
+  let version = version.parse::<u32>().map_err(|_| SerializationError::py_err(format!("invalid header version '{}'", version)))?;
+  if version > VERSION {
+    return Err(SerializationError::py_err(format!("unsupported header version {}, expected at most {}", version, VERSION)));
+  }
+  match words.next().map(str::trim).filter(|name| !name.is_empty()) {
+    Some(name) => Ok(Some((version, name, body))),
+    None => Err(SerializationError::py_err("missing type in header")),
+  }
+}
+
+/// Returns the annotation to load a value of header type `name` as, given
+/// the requested annotation `ty`.
+///
+/// The header must declare `ty` itself or, for dispatch, a subclass of class
+/// `ty` registered by `stringly.alias`.
+pub fn dispatch(py: Python, ty: &PyAny, name: &str) -> PyResult<PyObject> {
+  if self::name(py, ty)? == name {
+    return Ok(ty.into());
+  }
+  let declared = resolve(py, name)?;
+  let builtins = py.import("builtins")?;
+  let is_class = |value: &PyAny| builtins.call1("isinstance", (value, builtins.getattr("type")?)).and_then(|result| result.is_true());
+  if declared.as_ptr() == ty.as_ptr() || (is_class(ty)? && is_class(declared.as_ref(py))? && builtins.call1("issubclass", (declared.as_ref(py), ty))?.is_true()?) {
+    return Ok(declared);
+  }
+  Err(SerializationError::py_err(format!("expected a value of {} but the header declares '{}'", type_name(ty)?, name)))
+}
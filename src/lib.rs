@@ -1,13 +1,41 @@
+mod aio;
+mod bench;
+mod cache;
+mod cli;
+mod components;
+mod convert;
+mod doc;
+mod expr;
+mod file;
+mod header;
+mod logging;
+mod markers;
+mod merge;
+mod messages;
+mod nodes;
+mod numpy;
+mod options;
+mod parsed;
+mod pydantic;
+mod raw;
+mod refs;
+mod registry;
+mod schema;
+mod sign;
+mod stats;
+mod stream;
+mod structs;
+mod testing;
 mod text;
+mod typing;
 
-use pyo3::exceptions::{Exception, NotImplementedError, ValueError};
-use pyo3::proc_macro::{pyclass, pymethods, pymodule};
+use pyo3::exceptions::{AttributeError, Exception, KeyError, NotImplementedError, UserWarning, ValueError};
+use pyo3::proc_macro::pymodule;
 use pyo3::type_object::PyTypeObject;
-use pyo3::types::{IntoPyDict, PyAny, PyDict, PyModule};
-use pyo3::{create_exception, wrap_pymodule, PyObject, PyResult, Python};
-use serde_python_typing::{DualError, Type};
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyModule, PyTuple};
+use pyo3::{create_exception, wrap_pymodule, PyErr, PyObject, PyRef, PyResult, Python, ToPyObject};
+use serde_python_typing::DualError;
 use std::fmt;
-use text::{split_and_dedent, IterLines, JoinLines, LineIter};
 
 fn str_to_char(s: &str) -> PyResult<char> {
   let mut chars = s.chars();
@@ -20,224 +48,676 @@ fn str_to_char(s: &str) -> PyResult<char> {
 create_exception!(stringly, StringlyError, Exception);
 create_exception!(stringly, SerializationError, StringlyError);
 create_exception!(stringly, ImportFunctionError, StringlyError);
+create_exception!(stringly, StringlyWarning, UserWarning);
 
 #[pymodule]
 fn error(py: Python, m: &PyModule) -> PyResult<()> {
   m.setattr("StringlyError", StringlyError::type_object(py))?;
   m.setattr("SerializationError", SerializationError::type_object(py))?;
   m.setattr("ImportFunctionError", ImportFunctionError::type_object(py))?;
+  m.setattr("StringlyWarning", StringlyWarning::type_object(py))?;
 
   Ok(())
 }
 
-#[pyclass]
-struct DocString {
-  doc: String,
-  #[pyo3(get)]
-  text: String,
-  defaults: Vec<(String, String)>,
-  argdocs: Vec<(String, String)>,
-  presets: Vec<(String, Vec<(String, String)>)>,
-}
-
-#[pymethods]
-impl DocString {
-  #[new]
-  fn new(f: &PyAny) -> PyResult<Self> {
-    let doc = if let Ok(doc) = f.getattr("__doc__") { doc.extract()? } else { "" };
-    let lines = split_and_dedent(doc);
-
-    let doc = lines.iter().copied().join_lines();
-
-    let mut text = String::new();
-    let mut defaults = Vec::new();
-    let mut argdocs = Vec::new();
-    let mut presets = Vec::new();
-    let mut lines = lines.iter_lines();
-    lines.gobble_empty_lines();
-    // Parse blocks separated by white lines.
-    while let Some(line) = lines.peek_unempty() {
-      // Gobble empty lines.
-      lines.gobble_empty_lines();
-      if line == ".. arguments::" {
-        lines.next();
-        let mut lines = lines.dedent(3);
-        while let Some(line) = lines.next() {
-          let arg = line.trim();
-          let arg = match (arg.find(" ["), arg.ends_with(']')) {
-            (Some(index), true) => {
-              defaults.push((arg[..index].to_string(), arg[index + 2..arg.len() - 1].to_string()));
-              &arg[..index]
-            }
-            _ => arg,
-          };
-          let description = lines.dedent(2).join_lines();
-          argdocs.push((arg.to_string(), description.trim_end().to_string()));
-        }
-      } else if line == ".. presets::" {
-        lines.next();
-        let mut lines = lines.dedent(3);
-        while let Some(line) = lines.next() {
-          let preset = line.trim();
-          let mut parameters = Vec::new();
-          use stringly::util::{deprettify, safesplit, safesplit_once, unprotect};
-          // FIXME: handle deprettify error
-          let parameter_string = deprettify(&lines.dedent(2).join_lines()).unwrap();
-          for si in safesplit(&parameter_string, ',') {
-            if let Ok((key, value)) = safesplit_once(si, '=') {
-              parameters.push((unprotect(key).to_string(), unprotect(value).to_string()));
-            } else {
-              return Err(SerializationError::py_err(format!("preset {} has no value for argument {}", preset, unprotect(si))));
-            }
-          }
-          presets.push((preset.to_string(), parameters));
-        }
-      } else {
-        // Insert a white line unless this is the first paragraph.
-        if !text.is_empty() {
-          text.push('\n');
-        }
-        // Copy a single paragraph.
-        while let Some(line) = lines.next_if_unempty() {
-          text.push_str(line);
-          text.push('\n');
-        }
-      }
-    }
-
-    Ok(DocString { doc, text: text.trim().to_string(), defaults, argdocs, presets })
-  }
-  fn __str__(&self) -> PyResult<&str> {
-    Ok(&self.doc)
-  }
-  #[getter]
-  fn defaults<'py>(&self, py: Python<'py>) -> &'py PyDict {
-    self.defaults[..].into_py_dict(py)
-  }
-  #[getter]
-  fn argdocs<'py>(&self, py: Python<'py>) -> &'py PyDict {
-    self.argdocs[..].into_py_dict(py)
-  }
-  #[getter]
-  fn presets<'py>(&self, py: Python<'py>) -> &'py PyDict {
-    let p: Vec<(&str, &PyDict)> = self.presets.iter().map(|(key, value)| (key.as_str(), value.into_py_dict(py))).collect();
-    p[..].into_py_dict(py)
-  }
-}
-
 #[pymodule]
 fn util(_py: Python, m: &PyModule) -> PyResult<()> {
   #[pyfn(m, "safesplit")]
   #[text_signature = "(s, sep, /)"]
   fn safesplit<'a>(s: &'a str, sep: &str) -> PyResult<Vec<&'a str>> {
-    let mut chars = sep.chars();
-    match (chars.next(), chars.next()) {
-      (Some(ch), None) => Ok(stringly::util::safesplit(s, ch).collect()),
-      _ => Err(ValueError::py_err("expected a separator of length 1")),
-    }
+    guard("stringly.util.safesplit", || {
+      let mut chars = sep.chars();
+      match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(stringly::util::safesplit(s, ch).collect()),
+        _ => Err(ValueError::py_err("expected a separator of length 1")),
+      }
+    })
   }
 
   #[pyfn(m, "safesplit_once")]
   #[text_signature = "(s, sep, /)"]
   fn safesplit_once<'a>(s: &'a str, sep: &str) -> PyResult<(&'a str, &'a str)> {
-    let mut chars = sep.chars();
-    match (chars.next(), chars.next()) {
-      (Some(ch), None) => match stringly::util::safesplit_once(s, ch) {
-        Ok((l, r)) => Ok((l, r)),
-        Err(e) => Err(ValueError::py_err(format!("{:?}", e))),
-      },
-      _ => Err(ValueError::py_err("expected a separator of length 1")),
-    }
+    guard("stringly.util.safesplit_once", || {
+      let mut chars = sep.chars();
+      match (chars.next(), chars.next()) {
+        (Some(ch), None) => match stringly::util::safesplit_once(s, ch) {
+          Ok((l, r)) => Ok((l, r)),
+          Err(e) => Err(ValueError::py_err(format!("{:?}", e))),
+        },
+        _ => Err(ValueError::py_err("expected a separator of length 1")),
+      }
+    })
   }
 
   #[pyfn(m, "protect_unconditionally")]
   #[text_signature = "(s, /)"]
-  fn protect_unconditionally(s: &str) -> String {
-    stringly::util::protect_unconditionally(s)
+  fn protect_unconditionally(s: &str) -> PyResult<String> {
+    guard("stringly.util.protect_unconditionally", || Ok(stringly::util::protect_unconditionally(s)))
   }
 
   #[pyfn(m, "protect_unbalanced")]
   #[text_signature = "(s, /)"]
-  fn protect_unbalanced(s: &str) -> String {
-    stringly::util::protect_unbalanced(s)
+  fn protect_unbalanced(s: &str) -> PyResult<String> {
+    guard("stringly.util.protect_unbalanced", || Ok(stringly::util::protect_unbalanced(s)))
   }
 
   #[pyfn(m, "protect_regex")]
   #[text_signature = "(s, sep, /)"]
   fn protect_regex<'a>(s: &str, sep: &str) -> PyResult<String> {
-    let items: Vec<&str> = sep.split('|').collect();
-    match items.len() {
-      1 => Ok(stringly::util::protect(s, str_to_char(items[0])?)),
-      2 => Ok(stringly::util::protect(s, [str_to_char(items[0])?, str_to_char(items[1])?])),
-      _ => Err(NotImplementedError::py_err(format!("only one or two characters are supported but got {}", items.len()))),
-    }
+    guard("stringly.util.protect_regex", || {
+      let items: Vec<&str> = sep.split('|').collect();
+      match items.len() {
+        1 => Ok(stringly::util::protect(s, str_to_char(items[0])?)),
+        2 => Ok(stringly::util::protect(s, [str_to_char(items[0])?, str_to_char(items[1])?])),
+        _ => Err(NotImplementedError::py_err(format!("only one or two characters are supported but got {}", items.len()))),
+      }
+    })
   }
 
   #[pyfn(m, "unprotect")]
   #[text_signature = "(s, /)"]
-  fn unprotect(s: &str) -> &str {
-    stringly::util::unprotect(s)
+  fn unprotect(s: &str) -> PyResult<&str> {
+    guard("stringly.util.unprotect", || Ok(stringly::util::unprotect(s)))
   }
 
   #[pyfn(m, "is_balanced")]
   #[text_signature = "(s, /)"]
-  fn is_balanced(s: &str) -> bool {
-    stringly::util::is_balanced(s)
+  fn is_balanced(s: &str) -> PyResult<bool> {
+    guard("stringly.util.is_balanced", || Ok(stringly::util::is_balanced(s)))
   }
 
   #[pyfn(m, "prettify")]
   #[text_signature = "(s, /)"]
-  fn prettify(s: &str) -> String {
-    stringly::util::prettify(s)
+  fn prettify(s: &str) -> PyResult<String> {
+    guard("stringly.util.prettify", || Ok(stringly::util::prettify(s)))
   }
 
   #[pyfn(m, "deprettify")]
   #[text_signature = "(s, /)"]
   fn deprettify(s: &str) -> PyResult<String> {
-    match stringly::util::deprettify(s) {
-      Ok(v) => Ok(v),
-      Err(e) => Err(ValueError::py_err(format!("{:?}", e))),
-    }
+    guard("stringly.util.deprettify", || {
+      match stringly::util::deprettify(s) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(ValueError::py_err(format!("{:?}", e))),
+      }
+    })
   }
 
-  m.add_class::<DocString>()?;
+  #[pyfn(m, "__getattr__")]
+  #[text_signature = "(name, /)"]
+  fn getattr(py: Python, name: &str) -> PyResult<PyObject> {
+    guard("stringly.util.__getattr__", || match name {
+      "DocString" => {
+        PyErr::warn(py, py.import("builtins")?.getattr("DeprecationWarning")?, "stringly.util.DocString is deprecated, use stringly.DocString instead", 1)?;
+        Ok(doc::DocString::type_object(py).to_object(py))
+      }
+      _ => Err(AttributeError::py_err(format!("module 'stringly.util' has no attribute '{}'", name))),
+    })
+  }
 
   Ok(())
 }
 
+#[pymodule]
+fn doc(py: Python, m: &PyModule) -> PyResult<()> {
+  doc::init(py, m)
+}
+
+#[pymodule]
+fn messages(py: Python, m: &PyModule) -> PyResult<()> {
+  messages::init(py, m)
+}
+
+#[pymodule]
+fn nodes(py: Python, m: &PyModule) -> PyResult<()> {
+  nodes::init(py, m)
+}
+
+#[pymodule]
+fn typing(py: Python, m: &PyModule) -> PyResult<()> {
+  typing::init(py, m)
+}
+
+#[pymodule]
+fn aio(py: Python, m: &PyModule) -> PyResult<()> {
+  aio::init(py, m)
+}
+
+#[pymodule]
+fn stream(py: Python, m: &PyModule) -> PyResult<()> {
+  stream::init(py, m)
+}
+
+#[pymodule]
+fn logging(py: Python, m: &PyModule) -> PyResult<()> {
+  logging::init(py, m)
+}
+
+#[pymodule]
+fn bench(py: Python, m: &PyModule) -> PyResult<()> {
+  bench::init(py, m)
+}
+
+#[pymodule]
+fn cli(py: Python, m: &PyModule) -> PyResult<()> {
+  cli::init(py, m)
+}
+
+#[pymodule]
+fn testing(py: Python, m: &PyModule) -> PyResult<()> {
+  testing::init(py, m)
+}
+
 #[pymodule]
 /// Stringly
 /// ========
 ///
 /// Human readable object serialization.
-fn stringly(_py: Python, m: &PyModule) -> PyResult<()> {
+fn stringly(py: Python, m: &PyModule) -> PyResult<()> {
   m.add_wrapped(wrap_pymodule!(error))?;
   m.add_wrapped(wrap_pymodule!(util))?;
+  m.add_wrapped(wrap_pymodule!(doc))?;
+  m.add_wrapped(wrap_pymodule!(typing))?;
+  m.add_wrapped(wrap_pymodule!(aio))?;
+  m.add_wrapped(wrap_pymodule!(stream))?;
+  m.add_wrapped(wrap_pymodule!(logging))?;
+  m.add_wrapped(wrap_pymodule!(cli))?;
+  m.add_wrapped(wrap_pymodule!(testing))?;
+  m.add_wrapped(wrap_pymodule!(messages))?;
+  m.add_wrapped(wrap_pymodule!(nodes))?;
+  m.add_wrapped(wrap_pymodule!(bench))?;
+  registry::init(py, m)?;
+  cache::init(py, m)?;
+  stats::init(py, m)?;
+  components::init(py, m)?;
+  convert::init(py, m)?;
+  options::init(py, m)?;
+  m.add_class::<doc::DocString>()?;
+  m.add_class::<structs::Report>()?;
+  m.add_class::<parsed::Parsed>()?;
+  m.add_class::<raw::Raw>()?;
+  m.add_class::<markers::Format>()?;
+  m.add_class::<markers::Percent>()?;
+  m.add_class::<markers::Duration>()?;
+  m.add_class::<markers::Size>()?;
+  m.add_class::<markers::Color>()?;
+  m.add_class::<markers::Lazy>()?;
+  m.add_class::<markers::FromFile>()?;
+  m.add_class::<markers::Glob>()?;
+  m.add_class::<markers::EnvDefault>()?;
+  m.add_class::<markers::ByValue>()?;
+  m.add_class::<markers::Codec>()?;
+  m.add_class::<markers::LazyValue>()?;
 
   #[pyfn(m, "get_type_str")]
   #[text_signature = "(type, /)"]
-  fn get_type_str(_py: Python, ty: &PyAny) -> PyResult<String> {
-    Ok(Type::from_python(ty)?.to_string())
+  fn get_type_str(py: Python, ty: &PyAny) -> PyResult<String> {
+    guard("stringly.get_type_str", || schema::build(py, ty)?.describe(py))
+  }
+
+  #[pyfn(m, "dumps", options = "**")]
+  #[text_signature = "(type, value, /, **options)"]
+  /// Serializes `value` according to annotation `type` and `options`.
+  ///
+  /// With the `header` option the result is prefixed by a line declaring the
+  /// format version and the type, see `loads_any`.
+  fn dumps(py: Python, ty: &PyAny, val: &PyAny, options: Option<&PyDict>) -> PyResult<String> {
+    guard("stringly.dumps", || {
+      let options = options::current().with(options)?;
+      let s = dumps_value_with(py, ty, val, &options)?;
+      if options.header {
+        header::prepend(py, ty, &s)
+      } else {
+        Ok(s)
+      }
+    })
+  }
+
+  #[pyfn(m, "loads", options = "**")]
+  #[text_signature = "(type, value, /, **options)"]
+  /// Deserializes `value` according to annotation `type` and `options`.
+  ///
+  /// Fields of structs that have a default, such as dataclass fields with a
+  /// `default` or `default_factory` and named tuple fields with a default, may
+  /// be omitted from `value`; they are filled in with their defaults. See
+  /// `loads_with_report` for which fields were defaulted.
+  fn loads(py: Python, ty: &PyAny, val: &str, options: Option<&PyDict>) -> PyResult<PyObject> {
+    guard("stringly.loads", || loads_value_with(py, ty, val, &options::current().with(options)?))
+  }
+
+  #[pyfn(m, "loads_any", types = "None", options = "**")]
+  #[text_signature = "(value, /, types=None, **options)"]
+  /// Deserializes `value` as the type declared by its header, which is written
+  /// by `dumps` with the `header` option.
+  ///
+  /// The type is looked up by name in `types`, a mapping of names to types,
+  /// or else among the short names registered by `stringly.alias`; names are
+  /// never evaluated or imported. Since the input selects which of these
+  /// types is constructed, `value` should nevertheless come from a trusted
+  /// source.
+  fn loads_any(py: Python, val: &str, types: Option<&PyAny>, options: Option<&PyDict>) -> PyResult<PyObject> {
+    guard("stringly.loads_any", || {
+      let (_, name, body) = header::split(val)?.ok_or_else(|| SerializationError::py_err("expected a header '!stringly/1 type' declaring the type"))?;
+      let ty: PyObject = match types {
+        Some(types) => match types.get_item(name) {
+          Ok(ty) => ty.into(),
+          Err(err) if err.is_instance::<KeyError>(py) => return Err(SerializationError::py_err(format!("unknown type '{}' of header, expected one of: {}", name, types.call_method0("keys")?.iter()?.map(|key| Ok(key?.str()?.to_str()?.to_string())).collect::<PyResult<Vec<_>>>()?.join(", ")))),
+          Err(err) => return Err(err),
+        },
+        None => header::resolve(py, name)?,
+      };
+      let options = options::Options { header: false, ..options::current().with(options)? };
+      loads_value_with(py, ty.as_ref(py), body, &options)
+    })
+  }
+
+  #[pyfn(m, "loads_with_report", options = "**")]
+  #[text_signature = "(type, value, /, **options)"]
+  /// Deserializes `value` like `loads` and returns the value and a `Report` of
+  /// the fields that were given explicitly and those filled from defaults.
+  fn loads_with_report(py: Python, ty: &PyAny, val: &str, options: Option<&PyDict>) -> PyResult<(PyObject, structs::Report)> {
+    guard("stringly.loads_with_report", || {
+      let options = options::current().with(options)?;
+      let value = loads_value_with(py, ty, val, &options)?;
+      let mut report = structs::Report::default();
+      report.add(schema::build_with(py, ty, &options)?.as_ref(), &preprocess(val, &options)?, "")?;
+      Ok((value, report))
+    })
+  }
+
+  #[pyfn(m, "parse", options = "**")]
+  #[text_signature = "(type, value, /, **options)"]
+  /// Returns `value` parsed for `type` as a `Parsed` handle, the first phase
+  /// of `loads`; see `construct`.
+  ///
+  /// Presets and references are handled by parsing, so the options
+  /// `presets` and `resolve_references` given here apply to all constructions.
+  fn parse(py: Python, ty: &PyAny, val: &str, options: Option<&PyDict>) -> PyResult<parsed::Parsed> {
+    guard("stringly.parse", || {
+      let options = options::current().with(options)?;
+      let schema = stats::timed(stats::Phase::Build, || schema::build_with(py, ty, &options))?;
+      let s = stats::timed(stats::Phase::Parse, || py.allow_threads(|| preprocess(val, &options)))?;
+      Ok(parsed::Parsed { ty: ty.into(), schema: schema.into(), s, options, root: None })
+    })
+  }
+
+  #[pyfn(m, "construct", options = "**")]
+  #[text_signature = "(parsed, /, **options)"]
+  /// Returns the value of `Parsed` handle `parsed`, the second phase of
+  /// `loads`, with the options of `parse` updated by `options`, e.g. to
+  /// construct a parsed document with different `coerce` hooks.
+  ///
+  /// Without `options` the schema built by `parse` is reused; otherwise the
+  /// schema is rebuilt with the updated options.
+  fn construct(py: Python, parsed: PyRef<parsed::Parsed>, options: Option<&PyDict>) -> PyResult<PyObject> {
+    guard("stringly.construct", || {
+      let s = parsed.text(py)?;
+      match options.filter(|options| options.len() > 0) {
+        Some(options) => construct_value_with(py, parsed.ty.as_ref(py), &s, &parsed.options.with(Some(options))?),
+        None => construct_schema_with(py, parsed.schema.as_ref(), &s, &parsed.options),
+      }
+    })
+  }
+
+  #[pyfn(m, "type_from_docstring")]
+  #[text_signature = "(func, /)"]
+  /// Returns a `TypedDict` of the arguments documented in the docstring of
+  /// `func`, typed by the `name: type [default]` lines of its `.. arguments::`
+  /// block, for use with `loads` on functions without annotations.
+  fn type_from_docstring(py: Python, func: &PyAny) -> PyResult<PyObject> {
+    guard("stringly.type_from_docstring", || doc::type_from_docstring(py, func))
+  }
+
+  #[pyfn(m, "kwargs_type")]
+  #[text_signature = "(func, /)"]
+  /// Returns a dataclass of the keyword parameters of `func`, with their
+  /// annotations and defaults, so that `dumps` can serialize the arguments of
+  /// a call.
+  fn kwargs_type(py: Python, func: &PyAny) -> PyResult<PyObject> {
+    guard("stringly.kwargs_type", || doc::kwargs_type(py, func))
+  }
+
+  #[pyfn(m, "record_call", args = "*", kwargs = "**")]
+  #[text_signature = "(func, /, *args, **kwargs)"]
+  /// Returns a record `('module:qualname', arguments)` of the call
+  /// `func(*args, **kwargs)`, with the arguments, including defaults,
+  /// serialized according to `kwargs_type(func)`. See `replay`.
+  fn record_call(py: Python, func: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<(String, String)> {
+    guard("stringly.record_call", || {
+      let bound = py.import("inspect")?.call1("signature", (func,))?.call_method("bind", args, kwargs)?;
+      let ty = doc::kwargs_type(py, func)?;
+      let value = ty.as_ref(py).call((), Some(bound.getattr("arguments")?.extract()?))?;
+      let name = format!("{}:{}", func.getattr("__module__")?.extract::<&str>()?, func.getattr("__qualname__")?.extract::<&str>()?);
+      Ok((name, dumps_value(py, ty.as_ref(py), value)?))
+    })
+  }
+
+  #[pyfn(m, "replay")]
+  #[text_signature = "(record, /)"]
+  /// Calls the function of `record`, as returned by `record_call`, with the
+  /// recorded arguments and returns the result.
+  fn replay(py: Python, record: (&str, &str)) -> PyResult<PyObject> {
+    guard("stringly.replay", || {
+      let (name, arguments) = record;
+      let (module, qualname) = match name.find(':') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => return Err(ValueError::py_err(format!("expected 'module:qualname' but got '{}'", name))),
+      };
+      let mut func: &PyAny = py.import(module)?;
+      for attr in qualname.split('.') {
+        func = func.getattr(attr)?;
+      }
+      let ty = doc::kwargs_type(py, func)?;
+      let value = loads_value(py, ty.as_ref(py), arguments)?;
+      let kwargs = PyDict::new(py);
+      for field in py.import("dataclasses")?.call1("fields", (value.as_ref(py),))?.iter()? {
+        let name = field?.getattr("name")?;
+        kwargs.set_item(name, value.as_ref(py).getattr(name)?)?;
+      }
+      Ok(func.call((), Some(kwargs))?.into())
+    })
   }
 
-  #[pyfn(m, "dumps")]
-  #[text_signature = "(type, value, /)"]
-  fn dumps(_py: Python, ty: &PyAny, val: &PyAny) -> PyResult<String> {
-    let ty = &Type::from_python(ty)?;
-    wrap_err(ty.serialize(stringly::Serializer, val))
+  #[pyfn(m, "dump_signed")]
+  #[text_signature = "(type, value, key, /)"]
+  /// Serializes `value` like `dumps` and appends a footer with the
+  /// HMAC-SHA256 signature of the result with `key`, `bytes` or a `str`.
+  fn dump_signed(py: Python, ty: &PyAny, val: &PyAny, key: &PyAny) -> PyResult<String> {
+    guard("stringly.dump_signed", || sign::sign(py, &dumps_value(py, ty, val)?, key))
   }
 
-  #[pyfn(m, "loads")]
-  #[text_signature = "(type, value, /)"]
-  fn loads(py: Python, ty: &PyAny, val: &str) -> PyResult<PyObject> {
-    let de = stringly::Deserializer::from_str(val);
-    let ty = &Type::from_python(ty)?;
-    wrap_err(ty.deserialize(de, py))
+  #[pyfn(m, "load_verified")]
+  #[text_signature = "(type, value, key, /)"]
+  /// Verifies the signature footer of `value`, as written by `dump_signed`,
+  /// with `key` and deserializes the body like `loads`.
+  ///
+  /// The signature covers the canonical form of the body, so that it
+  /// survives prettification. A missing or invalid signature raises
+  /// `SerializationError`.
+  fn load_verified(py: Python, ty: &PyAny, val: &str, key: &PyAny) -> PyResult<PyObject> {
+    guard("stringly.load_verified", || loads_value(py, ty, &sign::verify(py, val, key)?))
+  }
+
+  #[pyfn(m, "merge", overlays = "*", sparse = "false")]
+  #[text_signature = "(base, *overlays, sparse=False)"]
+  /// Merges the serialized structs `overlays` into `base`, in order, and
+  /// returns the result.
+  ///
+  /// Keys of an overlay replace those of the base and dotted keys `a.b=value`
+  /// merge into nested structs. With `sparse=True` an overlay may also append
+  /// to a list or dict by `key+=value`, remove items by `key-=value` and
+  /// remove a key by `!key`.
+  fn merge(base: &str, overlays: &PyTuple, sparse: bool) -> PyResult<String> {
+    guard("stringly.merge", || {
+      let mut merged = base.to_string();
+      for overlay in overlays.iter() {
+        merged = crate::merge::merge_with(&merged, overlay.extract()?, sparse)?;
+      }
+      Ok(merged)
+    })
+  }
+
+  #[pyfn(m, "dump", checksum = "false", options = "**")]
+  #[text_signature = "(type, value, path, checksum=False, **options)"]
+  /// Serializes `value` like `dumps` and writes it prettified to the file at
+  /// `path`, followed by a SHA-256 checksum footer if `checksum` is true.
+  fn dump(py: Python, ty: &PyAny, val: &PyAny, path: &PyAny, checksum: bool, options: Option<&PyDict>) -> PyResult<()> {
+    guard("stringly.dump", || {
+      let mut contents = stringly::util::prettify(&dumps_value_with(py, ty, val, &options::current().with(options)?)?);
+      if checksum {
+        contents = file::add_footer(py, contents)?;
+      }
+      let path = file::fspath(py, path)?;
+      py.allow_threads(|| std::fs::write(path, contents))?;
+      Ok(())
+    })
+  }
+
+  #[pyfn(m, "load", on_mismatch = "\"error\"", options = "**")]
+  #[text_signature = "(type, path, on_mismatch='error', **options)"]
+  /// Reads the file at `path`, as written by `dump`, and deserializes its
+  /// contents like `loads`.
+  ///
+  /// A checksum footer that does not match the contents raises
+  /// `SerializationError`, or emits a warning if `on_mismatch` is `'warn'`.
+  fn load(py: Python, ty: &PyAny, path: &PyAny, on_mismatch: &str, options: Option<&PyDict>) -> PyResult<PyObject> {
+    guard("stringly.load", || {
+      let contents = file::read_file(py, &file::fspath(py, path)?, file::OnMismatch::from_str(on_mismatch)?)?;
+      let s = py.allow_threads(|| stringly::util::deprettify(&contents).map_err(|e| format!("{:?}", e)));
+      loads_value_with(py, ty, &s.map_err(SerializationError::py_err)?, &options::current().with(options)?)
+    })
+  }
+
+  #[pyfn(m, "load_config", options = "**")]
+  #[text_signature = "(type, path, /, **options)"]
+  /// Reads the configuration file at `path` and deserializes it like `load`.
+  ///
+  /// A file that starts with `@extends(path_or_name)` overrides the contents
+  /// of its parent file, which may extend another file in turn, see `merge`.
+  fn load_config(py: Python, ty: &PyAny, path: &PyAny, options: Option<&PyDict>) -> PyResult<PyObject> {
+    guard("stringly.load_config", || loads_value_with(py, ty, &file::read_config(py, &file::fspath(py, path)?)?, &options::current().with(options)?))
+  }
+
+  #[pyfn(m, "load_all", on_mismatch = "\"error\"", options = "**")]
+  #[text_signature = "(path, on_mismatch='error', **options)"]
+  /// Reads the multi-document file at `path`, as written by `dump_all`, and
+  /// returns a dict of the names and serialized strings of its documents, in
+  /// order, for use with `loads`.
+  ///
+  /// The `presets` and `resolve_references` options are applied to each
+  /// document; `on_mismatch` is as for `load`.
+  fn load_all<'py>(py: Python<'py>, path: &PyAny, on_mismatch: &str, options: Option<&PyDict>) -> PyResult<&'py PyDict> {
+    guard("stringly.load_all", || {
+      let options = options::current().with(options)?;
+      let documents = file::split_documents(&file::read_file(py, &file::fspath(py, path)?, file::OnMismatch::from_str(on_mismatch)?)?)?;
+      let documents = documents.into_iter().map(|(name, s)| Ok((name, preprocess(&s, &options)?))).collect::<PyResult<Vec<_>>>()?;
+      Ok(documents[..].into_py_dict(py))
+    })
+  }
+
+  #[pyfn(m, "load_document", on_mismatch = "\"error\"", options = "**")]
+  #[text_signature = "(type, path, name, on_mismatch='error', **options)"]
+  /// Deserializes the document `name` of the multi-document file at `path`
+  /// like `load`; a missing document raises `KeyError`.
+  fn load_document(py: Python, ty: &PyAny, path: &PyAny, name: &str, on_mismatch: &str, options: Option<&PyDict>) -> PyResult<PyObject> {
+    guard("stringly.load_document", || {
+      let documents = file::split_documents(&file::read_file(py, &file::fspath(py, path)?, file::OnMismatch::from_str(on_mismatch)?)?)?;
+      match documents.iter().find(|(key, _)| key == name) {
+        Some((_, s)) => loads_value_with(py, ty, s, &options::current().with(options)?),
+        None => Err(KeyError::py_err(format!("no document named '{}'", name))),
+      }
+    })
+  }
+
+  #[pyfn(m, "_fuzz_loads")]
+  #[text_signature = "(type, data, /)"]
+  /// Fuzzing entry point that deserializes `data` according to `type`.
+  ///
+  /// The `type` is an annotation or a type string, e.g.
+  /// `'Dict[str, List[int]]'`, see `stringly.typing.parse_type_str`.
+  fn fuzz_loads(py: Python, ty: &PyAny, data: &[u8]) -> PyResult<()> {
+    guard("stringly._fuzz_loads", || {
+      let ty = match ty.extract::<&str>() {
+        Ok(s) => typing::parse(py, s)?,
+        Err(_) => ty.into(),
+      };
+      fuzz(py, data, |s| loads_value(py, ty.as_ref(py), s).map(drop))
+    })
+  }
+
+  #[pyfn(m, "_fuzz_parse")]
+  #[text_signature = "(data, /)"]
+  /// Fuzzing entry point for the parsers that do not depend on a type:
+  /// deprettification, sparse merging, references and expressions.
+  fn fuzz_parse(py: Python, data: &[u8]) -> PyResult<()> {
+    guard("stringly._fuzz_parse", || fuzz_parsers(py, data))
+  }
+
+  #[pyfn(m, "_panic")]
+  #[text_signature = "(message, /)"]
+  /// Panics with `message`, for testing the conversion of panics to
+  /// `StringlyError`.
+  fn panic(message: &str) -> PyResult<()> {
+    guard("stringly._panic", || panic!("{}", message))
+  }
+
+  #[pyfn(m, "dump_all", checksum = "false")]
+  #[text_signature = "(documents, path, checksum=False)"]
+  /// Writes `documents`, a mapping of names to serialized strings, to a
+  /// multi-document file at `path`, each document introduced by a separator
+  /// line `--- name`, followed by a checksum footer if `checksum` is true.
+  fn dump_all(py: Python, documents: &PyDict, path: &PyAny, checksum: bool) -> PyResult<()> {
+    guard("stringly.dump_all", || {
+      let documents: Vec<(String, String)> = documents.iter().map(|(name, document)| Ok((name.extract()?, document.extract()?))).collect::<PyResult<_>>()?;
+      let mut contents = file::join_documents(documents.iter().map(|(name, document)| (name.as_str(), document.as_str())));
+      if checksum {
+        contents = file::add_footer(py, contents)?;
+      }
+      let path = file::fspath(py, path)?;
+      py.allow_threads(|| std::fs::write(path, contents))?;
+      Ok(())
+    })
   }
 
   Ok(())
 }
 
+/// Returns serialized string `s` with the compact forms of the `presets`
+/// option expanded and, if `resolve_references` is set, references resolved.
+fn preprocess(s: &str, options: &options::Options) -> PyResult<String> {
+  let s = match &options.presets {
+    Some(presets) => merge::expand(s, presets)?,
+    None => s.to_string(),
+  };
+  if options.resolve_references {
+    refs::resolve(&s)
+  } else {
+    Ok(s)
+  }
+}
+
+/// Runs `f` on fuzzer input `data` and discards the errors that are expected
+/// for invalid input, i.e. `StringlyError` and `ValueError`.
+///
+/// Input that is not valid UTF-8 is ignored. Any other exception, as well as a
+/// panic, indicates a bug.
+fn fuzz(py: Python, data: &[u8], f: impl FnOnce(&str) -> PyResult<()>) -> PyResult<()> {
+  let s = match std::str::from_utf8(data) {
+    Ok(s) => s,
+    Err(_) => return Ok(()),
+  };
+  match f(s) {
+    Err(err) if err.is_instance::<StringlyError>(py) || err.is_instance::<ValueError>(py) => Ok(()),
+    result => result,
+  }
+}
+
+/// Runs the parsers that do not depend on a type on fuzzer input `data`, see
+/// `fuzz`.
+fn fuzz_parsers(py: Python, data: &[u8]) -> PyResult<()> {
+  fuzz(py, data, |s| file::deprettify(s).map(drop))?;
+  fuzz(py, data, |s| merge::merge_with(s, s, true).map(drop))?;
+  fuzz(py, data, |s| refs::resolve(s).map(drop))?;
+  let constants: Vec<(String, f64)> = expr::CONSTANTS.iter().map(|&(name, value)| (name.to_string(), value)).collect();
+  fuzz(py, data, |s| expr::evaluate(s, &constants).map(drop).map_err(ValueError::py_err))
+}
+
+/// Fuzzing entry point of the cargo-fuzz targets in `fuzz/`, see
+/// `fuzz_parsers`.
+///
+/// Panics on any error that indicates a bug. Requires the crate to be built
+/// without the default `extension-module` feature, so that the fuzzer binary
+/// links against Python.
+#[doc(hidden)]
+pub fn fuzz_parse(data: &[u8]) {
+  let gil = Python::acquire_gil();
+  let py = gil.python();
+  if let Err(err) = fuzz_parsers(py, data) {
+    err.print(py);
+    panic!("unexpected error for input {:?}", String::from_utf8_lossy(data));
+  }
+}
+
+/// Serializes `val` according to Python type annotation `ty` with the options
+/// in effect in the current thread.
+fn dumps_value(py: Python, ty: &PyAny, val: &PyAny) -> PyResult<String> {
+  dumps_value_with(py, ty, val, &options::current())
+}
+
+/// Serializes `val` according to Python type annotation `ty` and `options`.
+///
+/// A struct that matches one of the `presets` option is serialized in the
+/// compact form `preset{overrides}`.
+fn dumps_value_with(py: Python, ty: &PyAny, val: &PyAny, options: &options::Options) -> PyResult<String> {
+  let schema = stats::timed(stats::Phase::Build, || schema::build_with(py, ty, options))?;
+  let s = stats::timed(stats::Phase::Serialize, || schema::dumps(py, schema.as_ref(), val))?;
+  match &options.presets {
+    Some(presets) if schema.as_struct().is_some() => merge::compact(&s, presets),
+    _ => Ok(s),
+  }
+}
+
+/// Deserializes `s` according to Python type annotation `ty` with the options
+/// in effect in the current thread.
+///
+/// If `ty` has a finite set of values and `s` is not one of them, the error
+/// message lists the valid values. The detail of the error message follows
+/// the `verbose_errors` option.
+fn loads_value(py: Python, ty: &PyAny, s: &str) -> PyResult<PyObject> {
+  loads_value_with(py, ty, s, &options::current())
+}
+
+/// Deserializes `s` according to Python type annotation `ty` and `options`,
+/// see `loads_value`.
+///
+/// With the `header` option a header written by `dumps` is validated and, if
+/// it declares a subclass of `ty`, selects the subclass.
+fn loads_value_with(py: Python, ty: &PyAny, s: &str, options: &options::Options) -> PyResult<PyObject> {
+  if options.header {
+    let options = options::Options { header: false, ..options.clone() };
+    return match header::split(s)? {
+      Some((_, name, body)) => loads_value_with(py, header::dispatch(py, ty, name)?.as_ref(py), body, &options),
+      None => loads_value_with(py, ty, s, &options),
+    };
+  }
+  let (schema, s) = cache::prepared(py, ty, s, options)?;
+  construct_schema_with(py, schema.as_ref(), &s, options)
+}
+
+/// Deserializes preprocessed string `s`, see `preprocess`, according to Python
+/// type annotation `ty` and `options`.
+fn construct_value_with(py: Python, ty: &PyAny, s: &str, options: &options::Options) -> PyResult<PyObject> {
+  let schema = stats::timed(stats::Phase::Build, || schema::build_with(py, ty, options))?;
+  construct_schema_with(py, schema.as_ref(), s, options)
+}
+
+/// Deserializes preprocessed string `s` according to `schema` and `options`.
+fn construct_schema_with(py: Python, schema: &dyn schema::Schema, s: &str, options: &options::Options) -> PyResult<PyObject> {
+  match stats::timed(stats::Phase::Construct, || schema::loads(py, schema, s)) {
+    Err(err) if err.is_instance::<SerializationError>(py) => Err(messages::error(py, err, s, &schema.describe(py)?, schema.choices(py)?, options.verbose_errors)?),
+    result => result,
+  }
+}
+
+/// Runs the body `f` of entry point `name` and converts a Rust panic into a
+/// `StringlyError`, so that a bug does not abort the interpreter.
+///
+/// The message of a `SerializationError` is translated to the selected
+/// language, see `stringly.messages.register_catalog`.
+fn guard<T>(name: &str, f: impl FnOnce() -> PyResult<T>) -> PyResult<T> {
+  match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+    Ok(result) => result.map_err(|err| messages::localize(Python::acquire_gil().python(), err)),
+    Err(payload) => {
+      let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+      };
+      Err(StringlyError::py_err(format!("internal error in {}: {}; please report this as a bug", name, message)))
+    }
+  }
+}
+
 fn wrap_err<T, E: fmt::Display>(r: Result<T, DualError<E>>) -> PyResult<T> {
   match r {
     Ok(v) => Ok(v),
@@ -0,0 +1,132 @@
+use crate::{dumps_value, guard, options};
+use pyo3::proc_macro::{pyclass, pyfunction, pymethods};
+use pyo3::types::{PyAny, PyDict, PyModule, PyTuple};
+use pyo3::{wrap_pyfunction, PyObject, PyResult, Python, ToPyObject};
+use stringly::util::{protect, safesplit, safesplit_once, unprotect};
+
+/// Key fragments that mark a value as secret unless specified otherwise.
+const SECRETS: &[&str] = &["password", "passwd", "secret", "token", "apikey", "api_key", "credential"];
+
+/// Returns the canonical form of serialized string `s`.
+///
+/// Items of structs are sorted by key, recursively. Values of items with a key
+/// that contains one of `secrets` (case insensitive) are replaced by `***`.
+fn canonicalize(s: &str, secrets: &[String]) -> String {
+  let items: Vec<&str> = safesplit(s, ',').collect();
+  if s.is_empty() || (items.len() == 1 && safesplit_once(items[0], '=').is_err()) {
+    return s.to_string();
+  }
+  let pairs: Option<Vec<(&str, &str)>> = items.iter().map(|&item| safesplit_once(item, '=').ok()).collect();
+  match pairs {
+    Some(mut pairs) => {
+      pairs.sort_by_key(|&(key, _)| unprotect(key));
+      let pairs: Vec<String> = pairs
+        .into_iter()
+        .map(|(key, value)| {
+          let lower = unprotect(key).to_lowercase();
+          if secrets.iter().any(|secret| lower.contains(secret.as_str())) {
+            format!("{}=***", key)
+          } else {
+            format!("{}={}", key, protect(&canonicalize(unprotect(value), secrets), ','))
+          }
+        })
+        .collect();
+      pairs.join(",")
+    }
+    None => items.into_iter().map(|item| protect(&canonicalize(unprotect(item), secrets), ',')).collect::<Vec<_>>().join(","),
+  }
+}
+
+/// Returns the single line, canonical, secret-redacted log rendering of `value`.
+///
+/// If `secrets` is `None` the `secrets` option is used, or `SECRETS` if unset.
+fn format(py: Python, ty: &PyAny, val: &PyAny, secrets: Option<Vec<String>>) -> PyResult<String> {
+  let secrets = secrets.or_else(|| options::current().secrets).unwrap_or_else(|| SECRETS.iter().map(|secret| secret.to_string()).collect());
+  let secrets: Vec<String> = secrets.iter().map(|secret| secret.to_lowercase()).collect();
+  Ok(canonicalize(&dumps_value(py, ty, val)?, &secrets).replace('\n', "\\n"))
+}
+
+/// Renders `value` for a log record.
+///
+/// Struct items are ordered by key and values of keys containing one of
+/// `secrets` are redacted. By default `secrets` is taken from the options, see
+/// `stringly.configure`, or covers common names like `password` and `token`.
+#[pyfunction(secrets = "None")]
+#[text_signature = "(type, value, secrets=None)"]
+fn format_params(py: Python, ty: &PyAny, val: &PyAny, secrets: Option<Vec<String>>) -> PyResult<String> {
+  guard("stringly.logging.format_params", || format(py, ty, val, secrets))
+}
+
+/// A logger adapter that adds the rendered parameters to every record.
+///
+/// The rendering of `format_params` is available to formatters as
+/// `%(params)s`.
+#[pyclass]
+struct LoggerAdapter {
+  logger: PyObject,
+  #[pyo3(get)]
+  params: String,
+}
+
+#[pymethods]
+impl LoggerAdapter {
+  #[new]
+  #[args(secrets = "None")]
+  fn new(py: Python, logger: &PyAny, ty: &PyAny, val: &PyAny, secrets: Option<Vec<String>>) -> PyResult<Self> {
+    guard("stringly.logging.LoggerAdapter", || Ok(LoggerAdapter { logger: logger.into(), params: format(py, ty, val, secrets)? }))
+  }
+  #[args(args = "*", kwargs = "**")]
+  fn log(&self, py: Python, level: i32, msg: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+    guard("stringly.logging.LoggerAdapter.log", || {
+      let kwargs = match kwargs {
+        Some(kwargs) => kwargs.copy()?,
+        None => PyDict::new(py),
+      };
+      let extra = match kwargs.get_item("extra") {
+        Some(extra) => extra.extract::<&PyDict>()?.copy()?,
+        None => PyDict::new(py),
+      };
+      extra.set_item("params", &self.params)?;
+      kwargs.set_item("extra", extra)?;
+      let mut items = vec![level.to_object(py), msg.to_object(py)];
+      items.extend(args.iter().map(|arg| arg.to_object(py)));
+      self.logger.as_ref(py).call_method("log", PyTuple::new(py, items), Some(kwargs))?;
+      Ok(())
+    })
+  }
+  #[args(args = "*", kwargs = "**")]
+  fn debug(&self, py: Python, msg: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+    self.log(py, 10, msg, args, kwargs)
+  }
+  #[args(args = "*", kwargs = "**")]
+  fn info(&self, py: Python, msg: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+    self.log(py, 20, msg, args, kwargs)
+  }
+  #[args(args = "*", kwargs = "**")]
+  fn warning(&self, py: Python, msg: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+    self.log(py, 30, msg, args, kwargs)
+  }
+  #[args(args = "*", kwargs = "**")]
+  fn error(&self, py: Python, msg: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+    self.log(py, 40, msg, args, kwargs)
+  }
+  #[args(args = "*", kwargs = "**")]
+  fn critical(&self, py: Python, msg: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+    self.log(py, 50, msg, args, kwargs)
+  }
+  /// Logs the rendered parameters as a single record.
+  #[args(level = "20")]
+  fn log_params(&self, py: Python, level: i32) -> PyResult<()> {
+    guard("stringly.logging.LoggerAdapter.log_params", || {
+      let msg = format!("parameters: {}", self.params);
+      self.log(py, level, msg.to_object(py).as_ref(py), PyTuple::empty(py), None)
+    })
+  }
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(format_params))?;
+  m.add_class::<LoggerAdapter>()?;
+
+  Ok(())
+}
@@ -0,0 +1,1003 @@
+use crate::options::Options;
+use crate::schema::{build_with, dumps, enumeration, loads, type_name, Schema, Text};
+use crate::{dumps_value_with, file, guard, loads_value_with, SerializationError};
+use pyo3::class::basic::{CompareOp, PyObjectProtocol};
+use pyo3::class::iter::PyIterProtocol;
+use pyo3::class::mapping::PyMappingProtocol;
+use pyo3::class::sequence::PySequenceProtocol;
+use pyo3::exceptions::{OverflowError, TypeError, ValueError};
+use pyo3::proc_macro::{pyclass, pymethods, pyproto};
+use pyo3::types::{IntoPyDict, PyAny, PyList, PyTuple, PyType};
+use pyo3::type_object::PyTypeObject;
+use pyo3::{AsPyPointer, Py, PyObject, PyRef, PyResult, Python, ToPyObject};
+use std::cell::OnceCell;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+
+/// Returns an error for a marker that does not support annotation `ty`.
+fn unsupported(marker: &str, ty: &PyAny) -> PyResult<Box<dyn Schema>> {
+  Err(TypeError::py_err(format!("{} is not supported for {}", marker, type_name(ty)?)))
+}
+
+/// Returns the shortest string of float `value` in format `spec` that parses
+/// back to `value`, trying precisions 12 to 17.
+fn shortest(py: Python, value: f64, spec: &str) -> PyResult<String> {
+  let format = py.import("builtins")?.getattr("format")?;
+  for precision in 12..=17 {
+    let s: String = format.call1((value, format!(".{}{}", precision, spec)))?.extract()?;
+    if s.parse::<f64>().ok() == Some(value) {
+      return Ok(s);
+    }
+  }
+  format.call1((value, format!(".17{}", spec)))?.extract()
+}
+
+/// Returns the schema of `ty` with the `typing.Annotated` metadata applied.
+///
+/// Besides the markers of this module, metadata that define the methods
+/// `__stringly_dumps__(value, codec)` and `__stringly_loads__(s, codec)` take
+/// over serialization, see `Codec`. Classes in the metadata are ignored, as
+/// their classmethods of these names serialize values of the class itself.
+/// Other metadata are ignored. The schema of `ty` itself is only built if a
+/// marker builds on it or none replaces it, so that metadata can take over
+/// the serialization of types that are not supported otherwise.
+pub fn annotate(py: Python, ty: &PyAny, metadata: &[&PyAny], options: &Options) -> PyResult<Box<dyn Schema>> {
+  let base = |schema: Option<Box<dyn Schema>>| schema.map_or_else(|| build_with(py, ty, options), Ok);
+  let mut schema = None;
+  for (index, item) in metadata.iter().enumerate() {
+    let next: Box<dyn Schema> = if let Ok(format) = item.extract::<PyRef<Format>>() {
+      if Encoded::supports(py, ty)? {
+        Box::new(Encoded::new(py, ty, &format.spec)?)
+      } else {
+        Box::new(Formatted::new(py, ty, &format.spec)?)
+      }
+    } else if item.extract::<PyRef<Percent>>().is_ok() || item.as_ptr() == Percent::type_object(py).as_ptr() {
+      Box::new(Percentage::new(py, ty)?)
+    } else if item.extract::<PyRef<Duration>>().is_ok() || item.as_ptr() == Duration::type_object(py).as_ptr() {
+      Box::new(Durational::new(py, ty)?)
+    } else if item.extract::<PyRef<Size>>().is_ok() || item.as_ptr() == Size::type_object(py).as_ptr() {
+      Box::new(ByteSize::new(py, ty)?)
+    } else if item.extract::<PyRef<Color>>().is_ok() || item.as_ptr() == Color::type_object(py).as_ptr() {
+      Box::new(Colored::new(py, ty)?)
+    } else if item.extract::<PyRef<Glob>>().is_ok() || item.as_ptr() == Glob::type_object(py).as_ptr() {
+      Box::new(Globbed::new(py, ty, base(schema)?)?)
+    } else if item.extract::<PyRef<ByValue>>().is_ok() || item.as_ptr() == ByValue::type_object(py).as_ptr() {
+      let builtins = py.import("builtins")?;
+      if !builtins.call1("isinstance", (ty, builtins.getattr("type")?))?.is_true()? || !builtins.call1("issubclass", (ty, py.import("enum")?.getattr("Enum")?))?.is_true()? {
+        return unsupported("ByValue", ty);
+      }
+      enumeration(py, ty, true)?
+    } else if !item.is_instance::<PyType>()? && item.hasattr("__stringly_dumps__")? && item.hasattr("__stringly_loads__")? {
+      let inner = if index == 0 { ty } else { py.import("typing")?.getattr("Annotated")?.get_item(PyTuple::new(py, std::iter::once(ty).chain(metadata[..index].iter().copied())))? };
+      Box::new(Custom { marker: item.into(), ty: inner.into(), options: options.clone() })
+    } else {
+      continue;
+    };
+    schema = Some(next);
+  }
+  base(schema)
+}
+
+/// Annotated marker that sets the textual format of a scalar.
+///
+/// For `datetime`, `date` and `time` the format is a `strftime` format, for
+/// `int` and `float` a format specification as understood by `format`, e.g.
+/// `Annotated[datetime, Format('%Y%m%d')]` or `Annotated[float, Format('.3e')]`,
+/// and for `bytes` and `bytearray` the encoding, `hex` or `base64`.
+#[pyclass]
+pub struct Format {
+  #[pyo3(get)]
+  spec: String,
+}
+
+#[pymethods]
+impl Format {
+  #[new]
+  fn new(spec: String) -> Self {
+    Format { spec }
+  }
+  fn __repr__(&self) -> String {
+    format!("Format({:?})", self.spec)
+  }
+}
+
+/// A scalar with a `Format`.
+pub struct Formatted {
+  ty: PyObject,
+  spec: String,
+  /// For `datetime`, `date` and `time` the name of the class.
+  time: Option<&'static str>,
+}
+
+impl Formatted {
+  pub fn new(py: Python, ty: &PyAny, spec: &str) -> PyResult<Self> {
+    let datetime = py.import("datetime")?;
+    let builtins = py.import("builtins")?;
+    let mut time = None;
+    for name in &["datetime", "date", "time"] {
+      if ty.as_ptr() == datetime.getattr(name)?.as_ptr() {
+        time = Some(*name);
+      }
+    }
+    if time.is_none() && ty.as_ptr() != builtins.getattr("int")?.as_ptr() && ty.as_ptr() != builtins.getattr("float")?.as_ptr() {
+      return Err(TypeError::py_err(format!("Format is not supported for {}", type_name(ty)?)));
+    }
+    Ok(Formatted { ty: ty.into(), spec: spec.to_string(), time })
+  }
+}
+
+impl Text for Formatted {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    match self.time {
+      Some(_) => value.call_method1("strftime", (self.spec.as_str(),))?.extract(),
+      None => py.import("builtins")?.call1("format", (value, self.spec.as_str()))?.extract(),
+    }
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let result = match self.time {
+      Some(name) => py.import("datetime")?.getattr("datetime")?.call_method1("strptime", (s, self.spec.as_str())).and_then(|value| if name == "datetime" { Ok(value) } else { value.call_method0(name) }),
+      None if self.spec.ends_with('%') => s.trim_end_matches('%').replace(',', "").replace('_', "").parse::<f64>().map_err(|_| ValueError::py_err("")).and_then(|value| self.ty.as_ref(py).call1((value / 100.,))),
+      None => self.ty.as_ref(py).call1((s.replace(',', "").replace('_', ""),)),
+    };
+    match result {
+      Ok(value) => Ok(value.into()),
+      Err(err) if err.is_instance::<ValueError>(py) => Err(SerializationError::py_err(format!("expected a value in format '{}' but got '{}'", self.spec, s))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Annotated[{}, Format({:?})]", type_name(self.ty.as_ref(py))?, self.spec))
+  }
+}
+
+/// A `bytes` or `bytearray` in a text encoding, `hex` or `base64`.
+pub struct Encoded {
+  ty: PyObject,
+  base64: bool,
+}
+
+impl Encoded {
+  /// Returns whether annotation `ty` is `bytes` or `bytearray`.
+  pub fn supports(py: Python, ty: &PyAny) -> PyResult<bool> {
+    let builtins = py.import("builtins")?;
+    Ok(ty.as_ptr() == builtins.getattr("bytes")?.as_ptr() || ty.as_ptr() == builtins.getattr("bytearray")?.as_ptr())
+  }
+  pub fn new(py: Python, ty: &PyAny, encoding: &str) -> PyResult<Self> {
+    if !Encoded::supports(py, ty)? {
+      return Err(TypeError::py_err(format!("an encoding is not supported for {}", type_name(ty)?)));
+    }
+    let base64 = match encoding {
+      "hex" => false,
+      "base64" => true,
+      _ => return Err(ValueError::py_err(format!("expected encoding 'hex' or 'base64' but got '{}'", encoding))),
+    };
+    Ok(Encoded { ty: ty.into(), base64 })
+  }
+}
+
+impl Text for Encoded {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    if self.base64 {
+      py.import("base64")?.call1("b64encode", (value,))?.call_method1("decode", ("ascii",))?.extract()
+    } else {
+      value.call_method0("hex")?.extract()
+    }
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let result = if self.base64 {
+      py.import("base64")?.call("b64decode", (s.trim(),), Some([("validate", true)].into_py_dict(py)))
+    } else {
+      py.import("builtins")?.getattr("bytes")?.call_method1("fromhex", (s,))
+    };
+    match result {
+      Ok(value) => Ok(self.ty.as_ref(py).call1((value,))?.into()),
+      Err(err) if err.is_instance::<ValueError>(py) => Err(SerializationError::py_err(format!("expected {} encoded bytes but got '{}'", if self.base64 { "base64" } else { "hex" }, s))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Annotated[{}, Format({:?})]", type_name(self.ty.as_ref(py))?, if self.base64 { "base64" } else { "hex" }))
+  }
+}
+
+/// Annotated marker for a fraction that is written as a percentage.
+///
+/// `Annotated[float, Percent]` loads `50%` as `0.5` and dumps `0.5` as `50%`.
+/// Values without a percent sign are loaded as fractions.
+#[pyclass]
+pub struct Percent {}
+
+#[pymethods]
+impl Percent {
+  #[new]
+  fn new() -> Self {
+    Percent {}
+  }
+  fn __repr__(&self) -> &str {
+    "Percent()"
+  }
+}
+
+/// Annotated marker for an enumeration that is serialized by member value
+/// instead of name, e.g. `Annotated[Color, ByValue]` dumps `Color.RED` as `1`
+/// and loads both `1` and `RED`.
+///
+/// Supported for enumerations with `int` or `str` values, such as `IntEnum`
+/// and `StrEnum`. A class selects this for all its annotations by the class
+/// attribute `__stringly_by_value__ = True`.
+#[pyclass]
+pub struct ByValue {}
+
+#[pymethods]
+impl ByValue {
+  #[new]
+  fn new() -> Self {
+    ByValue {}
+  }
+  fn __repr__(&self) -> &str {
+    "ByValue()"
+  }
+}
+
+/// A `float` with a `Percent` marker.
+struct Percentage {}
+
+impl Percentage {
+  fn new(py: Python, ty: &PyAny) -> PyResult<Self> {
+    if ty.as_ptr() != py.import("builtins")?.getattr("float")?.as_ptr() {
+      unsupported("Percent", ty)?;
+    }
+    Ok(Percentage {})
+  }
+}
+
+impl Text for Percentage {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    let value: f64 = value.extract()?;
+    let percentage = shortest(py, value * 100., "g")?;
+    // Fall back to the fraction if the percentage does not round trip.
+    if percentage.parse::<f64>().ok().map(|percentage| percentage / 100.) == Some(value) {
+      Ok(format!("{}%", percentage))
+    } else {
+      shortest(py, value, "g")
+    }
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let trimmed = s.trim();
+    let value = match trimmed.strip_suffix('%') {
+      Some(percentage) => percentage.trim_end().parse::<f64>().map(|percentage| percentage / 100.),
+      None => trimmed.parse::<f64>(),
+    };
+    match value {
+      Ok(value) => Ok(value.to_object(py)),
+      Err(_) => Err(SerializationError::py_err(format!("expected a percentage like '50%' but got '{}'", s))),
+    }
+  }
+  fn describe(&self, _py: Python) -> PyResult<String> {
+    Ok("Annotated[float, Percent]".to_string())
+  }
+}
+
+/// Units of durations in microseconds, from large to small.
+const DURATION_UNITS: &[(&str, i128)] = &[("d", 86_400_000_000), ("h", 3_600_000_000), ("m", 60_000_000), ("s", 1_000_000), ("ms", 1_000), ("us", 1)];
+
+/// Returns the compact form of a duration in microseconds, e.g. `1h30m`.
+pub fn format_duration(microseconds: i128) -> String {
+  if microseconds == 0 {
+    return "0s".to_string();
+  }
+  let mut s = if microseconds < 0 { "-".to_string() } else { String::new() };
+  let mut remainder = microseconds.abs();
+  for (unit, size) in DURATION_UNITS {
+    if remainder >= *size {
+      s.push_str(&format!("{}{}", remainder / size, unit));
+      remainder %= size;
+    }
+  }
+  s
+}
+
+/// Returns the total number of microseconds of `datetime.timedelta` `value`.
+///
+/// The total of `timedelta.max` exceeds the range of `i64`, hence `i128`.
+pub fn timedelta_microseconds(value: &PyAny) -> PyResult<i128> {
+  let days: i64 = value.getattr("days")?.extract()?;
+  let seconds: i64 = value.getattr("seconds")?.extract()?;
+  let microseconds: i64 = value.getattr("microseconds")?.extract()?;
+  (days as i128).checked_mul(86_400).and_then(|total| total.checked_add(seconds as i128)).and_then(|total| total.checked_mul(1_000_000)).and_then(|total| total.checked_add(microseconds as i128)).ok_or_else(|| SerializationError::py_err("duration is out of range"))
+}
+
+/// Returns the number of microseconds of a duration like `1h30m` or `250ms`.
+///
+/// A number without unit is interpreted as seconds. Returns `None` if `s` is
+/// not a valid duration, including infinite and `nan` durations.
+pub fn parse_duration(s: &str) -> Option<f64> {
+  let s = s.trim();
+  let (sign, mut rest) = match s.strip_prefix('-') {
+    Some(rest) => (-1., rest),
+    None => (1., s),
+  };
+  if let Ok(seconds) = rest.parse::<f64>() {
+    return Some(sign * seconds * 1e6).filter(|microseconds| microseconds.is_finite());
+  }
+  let mut total = 0.;
+  while !rest.is_empty() {
+    let number_end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+    let unit_end = number_end + rest[number_end..].find(|c: char| c.is_ascii_digit() || c == '.' || c == ' ').unwrap_or(rest.len() - number_end);
+    let number: f64 = rest[..number_end].parse().ok()?;
+    let unit = &rest[number_end..unit_end];
+    let (_, size) = DURATION_UNITS.iter().find(|(name, _)| *name == unit)?;
+    total += number * *size as f64;
+    rest = rest[unit_end..].trim_start();
+  }
+  Some(sign * total).filter(|microseconds| microseconds.is_finite())
+}
+
+/// Annotated marker for a human readable duration like `1h30m` or `250ms`.
+///
+/// Supported for `datetime.timedelta` and for `float` or `int` seconds. The
+/// units are `d`, `h`, `m`, `s`, `ms` and `us`; a number without unit is
+/// interpreted as seconds.
+#[pyclass]
+pub struct Duration {}
+
+#[pymethods]
+impl Duration {
+  #[new]
+  fn new() -> Self {
+    Duration {}
+  }
+  fn __repr__(&self) -> &str {
+    "Duration()"
+  }
+}
+
+/// A `timedelta`, `float` or `int` with a `Duration` marker.
+pub struct Durational {
+  ty: PyObject,
+  timedelta: bool,
+}
+
+impl Durational {
+  pub fn new(py: Python, ty: &PyAny) -> PyResult<Self> {
+    let timedelta = ty.as_ptr() == py.import("datetime")?.getattr("timedelta")?.as_ptr();
+    let builtins = py.import("builtins")?;
+    if !timedelta && ty.as_ptr() != builtins.getattr("float")?.as_ptr() && ty.as_ptr() != builtins.getattr("int")?.as_ptr() {
+      unsupported("Duration", ty)?;
+    }
+    Ok(Durational { ty: ty.into(), timedelta })
+  }
+}
+
+impl Text for Durational {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    if self.timedelta {
+      return Ok(format_duration(timedelta_microseconds(value)?));
+    }
+    let seconds: f64 = value.extract()?;
+    let microseconds = seconds * 1e6;
+    if microseconds.fract() == 0. && microseconds.abs() < 9e15 {
+      Ok(format_duration(microseconds as i128))
+    } else {
+      Ok(format!("{}s", shortest(py, seconds, "g")?))
+    }
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let microseconds = match parse_duration(s) {
+      Some(microseconds) => microseconds,
+      None => return Err(SerializationError::py_err(format!("expected a duration like '1h30m' or '250ms' but got '{}'", s))),
+    };
+    if self.timedelta {
+      let kwargs = [("microseconds", microseconds.round())].into_py_dict(py);
+      match py.import("datetime")?.getattr("timedelta")?.call((), Some(kwargs)) {
+        Ok(value) => Ok(value.into()),
+        Err(err) if err.is_instance::<OverflowError>(py) => Err(SerializationError::py_err(format!("duration '{}' is out of range", s))),
+        Err(err) => Err(err),
+      }
+    } else if self.ty.as_ref(py).as_ptr() == py.import("builtins")?.getattr("int")?.as_ptr() {
+      if microseconds % 1e6 != 0. {
+        return Err(SerializationError::py_err(format!("expected a whole number of seconds but got '{}'", s)));
+      }
+      Ok(((microseconds / 1e6) as i64).to_object(py))
+    } else {
+      Ok((microseconds / 1e6).to_object(py))
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Annotated[{}, Duration]", type_name(self.ty.as_ref(py))?))
+  }
+}
+
+/// Units of sizes in bytes.
+const SIZE_UNITS: &[(&str, u64)] = &[
+  ("B", 1),
+  ("kB", 1_000),
+  ("KiB", 1 << 10),
+  ("MB", 1_000_000),
+  ("MiB", 1 << 20),
+  ("GB", 1_000_000_000),
+  ("GiB", 1 << 30),
+  ("TB", 1_000_000_000_000),
+  ("TiB", 1 << 40),
+  ("PB", 1_000_000_000_000_000),
+  ("PiB", 1 << 50),
+];
+
+/// Annotated marker for a size in bytes like `512MiB` or `2GB`.
+///
+/// `Annotated[int, Size]` loads sizes with decimal (`kB`, `MB`, ...) or binary
+/// (`KiB`, `MiB`, ...) units to bytes and dumps bytes in the largest unit that
+/// represents the size exactly. A number without unit is in bytes.
+#[pyclass]
+pub struct Size {}
+
+#[pymethods]
+impl Size {
+  #[new]
+  fn new() -> Self {
+    Size {}
+  }
+  fn __repr__(&self) -> &str {
+    "Size()"
+  }
+}
+
+/// An `int` with a `Size` marker.
+struct ByteSize {}
+
+impl ByteSize {
+  fn new(py: Python, ty: &PyAny) -> PyResult<Self> {
+    if ty.as_ptr() != py.import("builtins")?.getattr("int")?.as_ptr() {
+      unsupported("Size", ty)?;
+    }
+    Ok(ByteSize {})
+  }
+}
+
+impl Text for ByteSize {
+  fn dumps(&self, _py: Python, value: &PyAny) -> PyResult<String> {
+    let bytes: u64 = match value.extract() {
+      Ok(bytes) => bytes,
+      Err(_) => return Err(SerializationError::py_err(format!("expected a non-negative int of bytes but got {}", value.repr()?))),
+    };
+    if bytes == 0 {
+      return Ok("0B".to_string());
+    }
+    match SIZE_UNITS.iter().filter(|(_, size)| bytes % size == 0).max_by_key(|(_, size)| *size) {
+      Some((unit, size)) => Ok(format!("{}{}", bytes / size, unit)),
+      None => Ok(format!("{}B", bytes)),
+    }
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let trimmed = s.trim();
+    let index = trimmed.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(trimmed.len());
+    let unit = trimmed[index..].trim_start();
+    let size = if unit.is_empty() { Some(1) } else { SIZE_UNITS.iter().find(|(name, _)| name.eq_ignore_ascii_case(unit)).map(|(_, size)| *size) };
+    let (whole, fraction) = match trimmed[..index].find('.') {
+      Some(dot) => (&trimmed[..dot], &trimmed[dot + 1..index]),
+      None => (&trimmed[..index], ""),
+    };
+    let size = match size {
+      Some(size) if !fraction.contains('.') && whole.len() + fraction.len() > 0 => size,
+      _ => return Err(SerializationError::py_err(format!("expected a size like '512MiB' or '2GB' but got '{}'", s))),
+    };
+    // The number is scaled by `10^fraction.len()` to compute in integers.
+    let (whole, fraction) = (whole.trim_start_matches('0'), fraction.trim_end_matches('0'));
+    let scaled = whole.chars().chain(fraction.chars()).try_fold(0u128, |number, digit| number.checked_mul(10)?.checked_add(digit.to_digit(10)? as u128));
+    let scale = 10u128.checked_pow(fraction.len() as u32);
+    let bytes = match (scaled.and_then(|scaled| scaled.checked_mul(size as u128)), scale) {
+      (Some(bytes), Some(scale)) if bytes % scale != 0 => return Err(SerializationError::py_err(format!("expected a whole number of bytes but got '{}'", s))),
+      (Some(bytes), Some(scale)) => bytes / scale,
+      _ => u128::MAX,
+    };
+    match u64::try_from(bytes) {
+      Ok(bytes) => Ok(bytes.to_object(py)),
+      Err(_) => Err(SerializationError::py_err(format!("size '{}' exceeds the maximum of {} bytes", s, u64::MAX))),
+    }
+  }
+  fn describe(&self, _py: Python) -> PyResult<String> {
+    Ok("Annotated[int, Size]".to_string())
+  }
+}
+
+/// Named colors supported by `Color`.
+const COLORS: &[(&str, [u8; 3])] = &[
+  ("black", [0, 0, 0]),
+  ("white", [255, 255, 255]),
+  ("red", [255, 0, 0]),
+  ("green", [0, 128, 0]),
+  ("lime", [0, 255, 0]),
+  ("blue", [0, 0, 255]),
+  ("yellow", [255, 255, 0]),
+  ("cyan", [0, 255, 255]),
+  ("aqua", [0, 255, 255]),
+  ("magenta", [255, 0, 255]),
+  ("fuchsia", [255, 0, 255]),
+  ("gray", [128, 128, 128]),
+  ("grey", [128, 128, 128]),
+  ("silver", [192, 192, 192]),
+  ("maroon", [128, 0, 0]),
+  ("olive", [128, 128, 0]),
+  ("navy", [0, 0, 128]),
+  ("purple", [128, 0, 128]),
+  ("teal", [0, 128, 128]),
+  ("orange", [255, 165, 0]),
+];
+
+/// Returns the components of a color `#rrggbb`, `#rgb`, `rgb(r, g, b)` or
+/// a named color.
+fn parse_color(s: &str) -> Option<[u8; 3]> {
+  let s = s.trim();
+  if let Some(hex) = s.strip_prefix('#') {
+    let digits: Vec<u8> = hex.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect::<Option<_>>()?;
+    return match digits.len() {
+      3 => Some([digits[0] * 17, digits[1] * 17, digits[2] * 17]),
+      6 => Some([digits[0] * 16 + digits[1], digits[2] * 16 + digits[3], digits[4] * 16 + digits[5]]),
+      _ => None,
+    };
+  }
+  if let Some(components) = s.strip_prefix("rgb(").and_then(|s| s.strip_suffix(')')) {
+    let components: Vec<u8> = components.split(',').map(|c| c.trim().parse().ok()).collect::<Option<_>>()?;
+    return if components.len() == 3 { Some([components[0], components[1], components[2]]) } else { None };
+  }
+  COLORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)).map(|(_, rgb)| *rgb)
+}
+
+/// Annotated marker for a color.
+///
+/// `Annotated[Tuple[int, int, int], Color]` loads `#rrggbb`, `#rgb`,
+/// `rgb(r, g, b)` and basic named colors like `navy` to an `(r, g, b)` tuple
+/// and dumps a tuple as `#rrggbb`. `Annotated[RGB, Color]` loads an instance
+/// of the named tuple `RGB` of this module, or of any named tuple with fields
+/// `r`, `g` and `b`, instead.
+#[pyclass]
+pub struct Color {}
+
+#[pymethods]
+impl Color {
+  #[new]
+  fn new() -> Self {
+    Color {}
+  }
+  fn __repr__(&self) -> &str {
+    "Color()"
+  }
+}
+
+/// Returns the named tuple class `RGB` of fields `r`, `g` and `b`, the value
+/// class of `Color`.
+pub fn rgb_class(py: Python) -> PyResult<&PyAny> {
+  let rgb = py.import("collections")?.call1("namedtuple", ("RGB", ("r", "g", "b")))?;
+  rgb.setattr("__module__", "stringly.typing")?;
+  Ok(rgb)
+}
+
+/// An `(r, g, b)` tuple, or a named tuple of fields `r`, `g` and `b`, with a
+/// `Color` marker.
+struct Colored {
+  /// The named tuple class, if any.
+  class: Option<PyObject>,
+}
+
+impl Colored {
+  fn new(py: Python, ty: &PyAny) -> PyResult<Self> {
+    let typing = py.import("typing")?;
+    let builtins = py.import("builtins")?;
+    let (int, tuple) = (builtins.getattr("int")?, builtins.getattr("tuple")?);
+    let args: Vec<&PyAny> = typing.call1("get_args", (ty,))?.extract()?;
+    if ty.as_ptr() == tuple.as_ptr() || (typing.call1("get_origin", (ty,))?.as_ptr() == tuple.as_ptr() && args.len() == 3 && args.iter().all(|arg| arg.as_ptr() == int.as_ptr())) {
+      return Ok(Colored { class: None });
+    }
+    let is_rgb = builtins.call1("isinstance", (ty, builtins.getattr("type")?))?.is_true()? && builtins.call1("issubclass", (ty, tuple))?.is_true()? && ty.getattr("_fields").map_or(false, |fields| fields.extract::<Vec<String>>().map_or(false, |fields| fields == ["r", "g", "b"]));
+    if !is_rgb {
+      unsupported("Color", ty)?;
+    }
+    Ok(Colored { class: Some(ty.into()) })
+  }
+}
+
+impl Text for Colored {
+  fn dumps(&self, _py: Python, value: &PyAny) -> PyResult<String> {
+    let (r, g, b): (u8, u8, u8) = match value.extract() {
+      Ok(rgb) => rgb,
+      Err(_) => return Err(SerializationError::py_err(format!("expected an (r, g, b) tuple of ints from 0 to 255 but got {}", value.repr()?))),
+    };
+    Ok(format!("#{:02x}{:02x}{:02x}", r, g, b))
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    match (parse_color(s), &self.class) {
+      (Some([r, g, b]), Some(class)) => Ok(class.as_ref(py).call1((r, g, b))?.into()),
+      (Some([r, g, b]), None) => Ok((r, g, b).to_object(py)),
+      (None, _) => Err(SerializationError::py_err(format!("expected a color like '#ff8000', 'rgb(255, 128, 0)' or 'orange' but got '{}'", s))),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    match &self.class {
+      Some(class) => Ok(format!("Annotated[{}, Color]", type_name(class.as_ref(py))?)),
+      None => Ok("Annotated[Tuple[int, int, int], Color]".to_string()),
+    }
+  }
+}
+
+/// Annotated marker for a value that is loaded on first use.
+///
+/// `loads` returns a `LazyValue` proxy for a value annotated as
+/// `Annotated[T, Lazy]`, which parses the serialized string only when the
+/// value is first accessed, e.g. for large arrays in configurations of which
+/// most fields go unused.
+#[pyclass]
+pub struct Lazy {}
+
+#[pymethods]
+impl Lazy {
+  #[new]
+  fn new() -> Self {
+    Lazy {}
+  }
+  fn __repr__(&self) -> &str {
+    "Lazy()"
+  }
+}
+
+/// Returns whether annotation metadata `item` is a `Lazy` marker.
+pub fn is_lazy(py: Python, item: &PyAny) -> bool {
+  item.extract::<PyRef<Lazy>>().is_ok() || item.as_ptr() == Lazy::type_object(py).as_ptr()
+}
+
+/// A value of annotation `ty` with a `Lazy` marker.
+pub struct Deferred {
+  ty: PyObject,
+  options: Options,
+}
+
+impl Deferred {
+  pub fn new(ty: &PyAny, options: &Options) -> Self {
+    Deferred { ty: ty.into(), options: options.clone() }
+  }
+}
+
+impl Text for Deferred {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    if let Ok(lazy) = value.extract::<PyRef<LazyValue>>() {
+      match lazy.value.get() {
+        Some(value) => dumps_value_with(py, self.ty.as_ref(py), value.as_ref(py), &self.options),
+        None => Ok(lazy.s.clone()),
+      }
+    } else {
+      dumps_value_with(py, self.ty.as_ref(py), value, &self.options)
+    }
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    Ok(Py::new(py, LazyValue { ty: self.ty.clone_ref(py), s: s.to_string(), options: self.options.clone(), value: OnceCell::new() })?.into())
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Annotated[{}, Lazy]", build_with(py, self.ty.as_ref(py), &self.options)?.describe(py)?))
+  }
+}
+
+/// A proxy of a value with a `Lazy` marker that is loaded on first access.
+///
+/// Attribute access, `len`, indexing, iteration, `in`, comparisons, `bool` and
+/// `str` are forwarded to the loaded value; `resolve` returns the loaded value
+/// itself, e.g. for `isinstance` checks or to pass it to code that requires
+/// the exact type.
+#[pyclass]
+pub struct LazyValue {
+  ty: PyObject,
+  s: String,
+  options: Options,
+  value: OnceCell<PyObject>,
+}
+
+impl LazyValue {
+  /// Returns the loaded value, loading it if this is the first access.
+  fn loaded<'p>(&'p self, py: Python<'p>) -> PyResult<&'p PyAny> {
+    if let Some(value) = self.value.get() {
+      return Ok(value.as_ref(py));
+    }
+    let value = loads_value_with(py, self.ty.as_ref(py), &self.s, &self.options)?;
+    Ok(self.value.get_or_init(|| value).as_ref(py))
+  }
+}
+
+#[pymethods]
+impl LazyValue {
+  /// Returns the loaded value, loading it if this is the first access.
+  #[text_signature = "($self)"]
+  fn resolve(&self, py: Python) -> PyResult<PyObject> {
+    guard("stringly.LazyValue.resolve", || Ok(self.loaded(py)?.into()))
+  }
+  /// Whether the value has been loaded.
+  #[getter]
+  fn get_resolved(&self) -> bool {
+    self.value.get().is_some()
+  }
+}
+
+#[pyproto]
+impl PyObjectProtocol for LazyValue {
+  fn __getattr__(&self, name: &str) -> PyResult<PyObject> {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    guard("stringly.LazyValue.__getattr__", || Ok(self.loaded(py)?.getattr(name)?.into()))
+  }
+  fn __repr__(&self) -> PyResult<String> {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    match self.value.get() {
+      Some(value) => Ok(format!("LazyValue({})", value.as_ref(py).repr()?)),
+      None => Ok(format!("LazyValue(<unloaded {}>)", type_name(self.ty.as_ref(py))?)),
+    }
+  }
+  fn __str__(&self) -> PyResult<String> {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    guard("stringly.LazyValue.__str__", || Ok(self.loaded(py)?.str()?.to_string()))
+  }
+  fn __richcmp__(&self, other: &PyAny, op: CompareOp) -> PyResult<PyObject> {
+    let py = other.py();
+    guard("stringly.LazyValue.__richcmp__", || Ok(self.loaded(py)?.rich_compare(other, op)?.into()))
+  }
+  fn __bool__(&self) -> PyResult<bool> {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    guard("stringly.LazyValue.__bool__", || self.loaded(py)?.is_true())
+  }
+}
+
+#[pyproto]
+impl PyMappingProtocol for LazyValue {
+  fn __len__(&self) -> PyResult<usize> {
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+    guard("stringly.LazyValue.__len__", || self.loaded(py)?.len())
+  }
+  fn __getitem__(&self, key: &PyAny) -> PyResult<PyObject> {
+    let py = key.py();
+    guard("stringly.LazyValue.__getitem__", || Ok(self.loaded(py)?.get_item(key)?.into()))
+  }
+}
+
+#[pyproto]
+impl PySequenceProtocol for LazyValue {
+  fn __contains__(&self, item: &PyAny) -> PyResult<bool> {
+    let py = item.py();
+    guard("stringly.LazyValue.__contains__", || py.import("operator")?.call1("contains", (self.loaded(py)?, item))?.is_true())
+  }
+}
+
+#[pyproto]
+impl PyIterProtocol for LazyValue {
+  fn __iter__(slf: PyRef<Self>) -> PyResult<PyObject> {
+    let py = slf.py();
+    guard("stringly.LazyValue.__iter__", || Ok(py.import("builtins")?.call1("iter", (slf.loaded(py)?,))?.into()))
+  }
+}
+
+/// Annotated marker for a value that is read from a file.
+///
+/// The serialized value of `Annotated[T, FromFile]` is the path of a file of
+/// which `loads` parses the contents as `T`. Relative paths are resolved
+/// against `base_dir`, or else the `base_dir` option, or else the working
+/// directory. Such values cannot be serialized.
+#[pyclass]
+pub struct FromFile {
+  #[pyo3(get)]
+  base_dir: Option<String>,
+}
+
+#[pymethods]
+impl FromFile {
+  #[new]
+  #[args(base_dir = "None")]
+  fn new(py: Python, base_dir: Option<&PyAny>) -> PyResult<Self> {
+    Ok(FromFile { base_dir: base_dir.map(|base_dir| file::fspath(py, base_dir).map(|path| path.to_string_lossy().into_owned())).transpose()? })
+  }
+  fn __repr__(&self) -> String {
+    match &self.base_dir {
+      Some(base_dir) => format!("FromFile({:?})", base_dir),
+      None => "FromFile()".to_string(),
+    }
+  }
+}
+
+/// Returns the base directory of annotation metadata `item` if it is a
+/// `FromFile` marker, as `Some(None)` if the marker has no base directory.
+pub fn from_file(py: Python, item: &PyAny) -> Option<Option<String>> {
+  if let Ok(marker) = item.extract::<PyRef<FromFile>>() {
+    Some(marker.base_dir.clone())
+  } else if item.as_ptr() == FromFile::type_object(py).as_ptr() {
+    Some(None)
+  } else {
+    None
+  }
+}
+
+/// A value with a `FromFile` marker.
+pub struct FileContents {
+  schema: Box<dyn Schema>,
+  base_dir: Option<PathBuf>,
+}
+
+impl FileContents {
+  pub fn new(schema: Box<dyn Schema>, base_dir: Option<&str>) -> Self {
+    FileContents { schema, base_dir: base_dir.map(PathBuf::from) }
+  }
+}
+
+impl Text for FileContents {
+  fn dumps(&self, py: Python, _value: &PyAny) -> PyResult<String> {
+    Err(SerializationError::py_err(format!("a value of {} is read from a file and cannot be serialized", Text::describe(self, py)?)))
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let path = match &self.base_dir {
+      Some(base_dir) => base_dir.join(s.trim()),
+      None => PathBuf::from(s.trim()),
+    };
+    let contents = file::deprettify(&file::read_file(py, &path, file::OnMismatch::Error)?)?;
+    loads(py, self.schema.as_ref(), &contents)
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Annotated[{}, FromFile]", self.schema.describe(py)?))
+  }
+}
+
+/// Annotated marker for a list of paths that accepts glob patterns.
+///
+/// `loads` expands items of `Annotated[List[Path], Glob]` that contain `*`,
+/// `?` or `[` to the matching paths, sorted, and fails if a pattern matches
+/// nothing. `**` matches any number of directories. Also supported for lists
+/// of `str`.
+#[pyclass]
+pub struct Glob {}
+
+#[pymethods]
+impl Glob {
+  #[new]
+  fn new() -> Self {
+    Glob {}
+  }
+  fn __repr__(&self) -> &str {
+    "Glob()"
+  }
+}
+
+/// A list of paths with a `Glob` marker.
+struct Globbed {
+  /// The class of the items.
+  item: PyObject,
+  schema: Box<dyn Schema>,
+}
+
+impl Globbed {
+  fn new(py: Python, ty: &PyAny, schema: Box<dyn Schema>) -> PyResult<Self> {
+    let typing = py.import("typing")?;
+    let args: Vec<&PyAny> = typing.call1("get_args", (ty,))?.extract()?;
+    if typing.call1("get_origin", (ty,))?.as_ptr() != py.import("builtins")?.getattr("list")?.as_ptr() || args.len() != 1 {
+      unsupported("Glob", ty)?;
+    }
+    Ok(Globbed { item: args[0].into(), schema })
+  }
+}
+
+impl Text for Globbed {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    dumps(py, self.schema.as_ref(), value)
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let glob = py.import("glob")?;
+    let mut items = Vec::new();
+    for item in loads(py, self.schema.as_ref(), s)?.as_ref(py).iter()? {
+      let item = item?;
+      let pattern: String = py.import("os")?.call1("fspath", (item,))?.extract()?;
+      if !pattern.contains(|c| c == '*' || c == '?' || c == '[') {
+        items.push(item.to_object(py));
+        continue;
+      }
+      let mut paths: Vec<String> = glob.call("glob", (pattern.as_str(),), Some([("recursive", true)].into_py_dict(py)))?.extract()?;
+      if paths.is_empty() {
+        return Err(SerializationError::py_err(format!("pattern '{}' matches no files", pattern)));
+      }
+      paths.sort();
+      for path in paths {
+        items.push(self.item.as_ref(py).call1((path,))?.into());
+      }
+    }
+    Ok(PyList::new(py, items).into())
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Annotated[{}, Glob]", self.schema.describe(py)?))
+  }
+}
+
+/// Annotated marker for a field of a struct that, if absent, is loaded from
+/// environment variable `name`, e.g. `Annotated[str, EnvDefault('MY_TOKEN')]`.
+///
+/// The environment variable takes precedence over the default of the field.
+#[pyclass]
+pub struct EnvDefault {
+  #[pyo3(get)]
+  name: String,
+}
+
+#[pymethods]
+impl EnvDefault {
+  #[new]
+  fn new(name: String) -> Self {
+    EnvDefault { name }
+  }
+  fn __repr__(&self) -> String {
+    format!("EnvDefault({:?})", self.name)
+  }
+}
+
+/// Returns the name of the environment variable of the `EnvDefault` marker of
+/// annotation `ty`, if any.
+pub fn env_default(py: Python, ty: &PyAny) -> PyResult<Option<String>> {
+  let typing = py.import("typing")?;
+  if typing.call1("get_origin", (ty,))?.as_ptr() != typing.getattr("Annotated")?.as_ptr() {
+    return Ok(None);
+  }
+  let args: Vec<&PyAny> = typing.call1("get_args", (ty,))?.extract()?;
+  Ok(args[1..].iter().find_map(|item| item.extract::<PyRef<EnvDefault>>().ok().map(|marker| marker.name.clone())))
+}
+
+/// Serializes values of an annotation, passed to the methods
+/// `__stringly_dumps__` and `__stringly_loads__` of custom `Annotated`
+/// metadata.
+///
+/// The annotation is that of the field with the metadata up to, excluding,
+/// the custom metadata, so that the custom metadata can, for example, add a
+/// unit to the default serialization: `codec.dumps(value) + ' m'`.
+#[pyclass]
+pub struct Codec {
+  ty: PyObject,
+  options: Options,
+}
+
+#[pymethods]
+impl Codec {
+  /// Returns the serialized form of `value`.
+  #[text_signature = "($self, value, /)"]
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    guard("stringly.Codec.dumps", || dumps_value_with(py, self.ty.as_ref(py), value, &self.options))
+  }
+  /// Returns the value of serialized string `s`.
+  #[text_signature = "($self, s, /)"]
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    guard("stringly.Codec.loads", || loads_value_with(py, self.ty.as_ref(py), s, &self.options))
+  }
+  #[getter]
+  fn get_type(&self, py: Python) -> PyObject {
+    self.ty.clone_ref(py)
+  }
+}
+
+/// A value with custom `Annotated` metadata, see `annotate`.
+struct Custom {
+  marker: PyObject,
+  ty: PyObject,
+  options: Options,
+}
+
+impl Custom {
+  fn codec(&self, py: Python) -> PyResult<Py<Codec>> {
+    Py::new(py, Codec { ty: self.ty.clone_ref(py), options: self.options.clone() })
+  }
+}
+
+impl Text for Custom {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    self.marker.as_ref(py).call_method1("__stringly_dumps__", (value, self.codec(py)?))?.extract()
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    match self.marker.as_ref(py).call_method1("__stringly_loads__", (s, self.codec(py)?)) {
+      Ok(value) => Ok(value.into()),
+      Err(err) if err.is_instance::<ValueError>(py) => Err(SerializationError::py_err(format!("invalid value '{}': {}", s, err.to_object(py).as_ref(py)))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Annotated[{}, {}]", type_name(self.ty.as_ref(py))?, self.marker.as_ref(py).repr()?))
+  }
+}
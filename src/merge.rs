@@ -0,0 +1,185 @@
+use crate::SerializationError;
+use pyo3::PyResult;
+use stringly::util::{protect, safesplit, safesplit_once, unprotect};
+
+/// Returns the unprotected `key=value` items of a struct string.
+pub fn items(s: &str) -> PyResult<Vec<(&str, &str)>> {
+  if s.is_empty() {
+    return Ok(Vec::new());
+  }
+  safesplit(s, ',')
+    .map(|item| match safesplit_once(item, '=') {
+      Ok((key, value)) => Ok((unprotect(key), unprotect(value))),
+      Err(_) => Err(SerializationError::py_err(format!("expected key=value but got '{}'", item))),
+    })
+    .collect()
+}
+
+/// Joins `key=value` items to a struct string.
+pub fn join(items: &[(String, String)]) -> String {
+  items.iter().map(|(key, value)| format!("{}={}", key, protect(value, ','))).collect::<Vec<_>>().join(",")
+}
+
+/// Returns whether all items of `s` are of the form `key=value`.
+pub fn is_struct(s: &str) -> bool {
+  safesplit(s, ',').all(|item| safesplit_once(item, '=').is_ok())
+}
+
+/// Appends serialized list or dict `value` to `base`.
+///
+/// If both are of the form `key=value,...` the keys of `value` are merged into
+/// `base`, otherwise the items of `value` are appended to those of `base`.
+fn append(base: &str, value: &str) -> PyResult<String> {
+  if base.is_empty() || value.is_empty() {
+    Ok(format!("{}{}", base, value))
+  } else if is_struct(base) && is_struct(value) {
+    merge(base, value)
+  } else {
+    Ok(format!("{},{}", base, value))
+  }
+}
+
+/// Merges struct string `overlay` into struct string `base`.
+///
+/// Values of keys present in both strings are replaced by the values of
+/// `overlay`, remaining keys of `overlay` are appended in order. A dotted key
+/// `a.b=value` in `overlay` merges `b=value` into the value of `a` in `base`.
+pub fn merge(base: &str, overlay: &str) -> PyResult<String> {
+  merge_with(base, overlay, false)
+}
+
+/// Removes the items of serialized list `value` from list or dict `base`.
+///
+/// If `base` is of the form `key=value,...` the items of `value` are removed
+/// as keys, otherwise all equal items of `base` are removed.
+fn remove(base: &str, value: &str) -> String {
+  let removed: Vec<&str> = safesplit(value, ',').map(|item| unprotect(item.trim())).collect();
+  let struct_ = is_struct(base);
+  safesplit(base, ',')
+    .filter(|item| {
+      let item = match safesplit_once(item, '=') {
+        Ok((key, _)) if struct_ => key,
+        _ => item,
+      };
+      !removed.contains(&unprotect(item.trim()))
+    })
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+/// An operation of an overlay item.
+enum Op<'a> {
+  Set(&'a str),
+  Append(&'a str),
+  Remove(&'a str),
+  Delete,
+}
+
+/// Returns the key and operation of every item of overlay `s`.
+///
+/// Unless `sparse` is true, all items are of the form `key=value`.
+fn ops(s: &str, sparse: bool) -> PyResult<Vec<(&str, Op)>> {
+  if !sparse {
+    return Ok(items(s)?.into_iter().map(|(key, value)| (key.trim(), Op::Set(value))).collect());
+  }
+  if s.is_empty() {
+    return Ok(Vec::new());
+  }
+  safesplit(s, ',')
+    .map(|item| match safesplit_once(item, '=') {
+      Ok((key, value)) => {
+        let (key, value) = (unprotect(key).trim(), unprotect(value));
+        Ok(if let Some(key) = key.strip_suffix('+') {
+          (key.trim_end(), Op::Append(value))
+        } else if let Some(key) = key.strip_suffix('-') {
+          (key.trim_end(), Op::Remove(value))
+        } else {
+          (key, Op::Set(value))
+        })
+      }
+      Err(_) if item.trim().starts_with('!') => Ok((unprotect(item.trim())[1..].trim_start(), Op::Delete)),
+      Err(_) => Err(SerializationError::py_err(format!("expected key=value, key+=value, key-=value or !key but got '{}'", item))),
+    })
+    .collect()
+}
+
+/// Merges struct string `overlay` into struct string `base`, see `merge`.
+///
+/// If `sparse` is true, an item `key+=value` in `overlay` appends `value` to
+/// the list or dict of `key` in `base` rather than replacing it, an item
+/// `key-=value` removes the items of `value` from the list or dict of `key`
+/// and an item `!key` removes `key` from `base` altogether.
+pub fn merge_with(base: &str, overlay: &str, sparse: bool) -> PyResult<String> {
+  let mut merged: Vec<(String, String)> = items(base)?.into_iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+  for (key, op) in ops(overlay, sparse)? {
+    let (head, tail) = match key.find('.') {
+      Some(index) => (&key[..index], Some(&key[index + 1..])),
+      None => (key, None),
+    };
+    let position = merged.iter().position(|(key, _)| key == head);
+    let current = position.map_or("", |index| merged[index].1.as_str());
+    let value = match (tail, op) {
+      (Some(tail), op) => {
+        let item = match op {
+          Op::Set(value) => format!("{}={}", tail, protect(value, ',')),
+          Op::Append(value) => format!("{}+={}", tail, protect(value, ',')),
+          Op::Remove(value) => format!("{}-={}", tail, protect(value, ',')),
+          Op::Delete => format!("!{}", protect(tail, ',')),
+        };
+        merge_with(current, &item, sparse)?
+      }
+      (None, Op::Set(value)) => value.to_string(),
+      (None, Op::Append(value)) => append(current, value)?,
+      (None, Op::Remove(value)) => remove(current, value),
+      (None, Op::Delete) => {
+        if let Some(index) = position {
+          merged.remove(index);
+        }
+        continue;
+      }
+    };
+    match position {
+      Some(index) => merged[index].1 = value,
+      None => merged.push((head.to_string(), value)),
+    }
+  }
+  Ok(join(&merged))
+}
+
+/// Returns the compact form `preset{overrides}` of struct string `s` for the
+/// preset of `presets` that `s` matches with the fewest overrides, or `s` if
+/// it matches none.
+///
+/// A struct string matches a preset if it has all keys of the preset. The
+/// overrides are the items of `s` that differ from the preset.
+pub fn compact(s: &str, presets: &[(String, Vec<(String, String)>)]) -> PyResult<String> {
+  let items = match items(s) {
+    Ok(items) if !items.is_empty() => items,
+    _ => return Ok(s.to_string()),
+  };
+  let mut best: Option<(&str, Vec<(String, String)>)> = None;
+  for (name, parameters) in presets {
+    if !parameters.iter().all(|(key, _)| items.iter().any(|(k, _)| k.trim() == key)) {
+      continue;
+    }
+    let overrides: Vec<(String, String)> = items.iter().filter(|(key, value)| !parameters.iter().any(|(k, v)| k == key.trim() && v == value)).map(|(key, value)| (key.to_string(), value.to_string())).collect();
+    if best.as_ref().map_or(true, |(_, best)| overrides.len() < best.len()) {
+      best = Some((name, overrides));
+    }
+  }
+  Ok(match best {
+    Some((name, overrides)) => format!("{}{{{}}}", name, join(&overrides)),
+    None => s.to_string(),
+  })
+}
+
+/// Returns the struct string of compact form `preset{overrides}`, see
+/// `compact`, or `s` if it is not of this form for any of `presets`.
+pub fn expand(s: &str, presets: &[(String, Vec<(String, String)>)]) -> PyResult<String> {
+  if let (Some(index), true) = (s.find('{'), s.ends_with('}')) {
+    if let Some((_, parameters)) = presets.iter().find(|(name, _)| name == s[..index].trim()) {
+      return merge(&join(parameters), &s[index + 1..s.len() - 1]);
+    }
+  }
+  Ok(s.to_string())
+}
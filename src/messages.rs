@@ -0,0 +1,302 @@
+use crate::options::{self, Verbosity};
+use crate::{guard, SerializationError};
+use pyo3::proc_macro::pyfunction;
+use pyo3::type_object::PyTypeObject;
+use pyo3::types::{PyAny, PyDict, PyModule};
+use pyo3::{wrap_pyfunction, PyErr, PyResult, Python};
+use std::sync::RwLock;
+
+/// The environment variable that selects the language of error messages if
+/// the `language` option is not set.
+const LANGUAGE_VARIABLE: &str = "STRINGLY_LANG";
+
+/// The message catalogs per language, as pairs of an English message template
+/// and its translation.
+static CATALOGS: RwLock<Vec<(String, Vec<(String, String)>)>> = RwLock::new(Vec::new());
+
+/// Returns the language of error messages, if any.
+fn language() -> Option<String> {
+  options::current().language.or_else(|| std::env::var(LANGUAGE_VARIABLE).ok()).filter(|language| !language.is_empty())
+}
+
+/// Returns the arguments of `message` if it matches `template`, in which every
+/// `{}` stands for an argument.
+fn matches<'a>(template: &str, message: &'a str) -> Option<Vec<&'a str>> {
+  let pieces: Vec<&str> = template.split("{}").collect();
+  let mut rest = message.strip_prefix(pieces[0])?;
+  let mut args = Vec::new();
+  for (i, piece) in pieces.iter().enumerate().skip(1) {
+    let index = if i == pieces.len() - 1 {
+      if !rest.ends_with(piece) {
+        return None;
+      }
+      rest.len() - piece.len()
+    } else {
+      rest.find(piece)?
+    };
+    args.push(&rest[..index]);
+    rest = &rest[index + piece.len()..];
+  }
+  if rest.is_empty() {
+    Some(args)
+  } else {
+    None
+  }
+}
+
+/// Returns `translation` with `{}` replaced by the consecutive `args` and
+/// `{0}`, `{1}`, ... by the argument of that index.
+fn fill(translation: &str, args: &[&str]) -> String {
+  let mut s = String::new();
+  let mut next = 0;
+  let mut rest = translation;
+  while let Some(start) = rest.find('{') {
+    s.push_str(&rest[..start]);
+    let end = match rest[start..].find('}') {
+      Some(end) => start + end,
+      None => break,
+    };
+    let index = match &rest[start + 1..end] {
+      "" => {
+        next += 1;
+        Some(next - 1)
+      }
+      digits => digits.parse::<usize>().ok(),
+    };
+    match index.and_then(|index| args.get(index)) {
+      Some(arg) => s.push_str(arg),
+      None => s.push_str(&rest[start..=end]),
+    }
+    rest = &rest[end + 1..];
+  }
+  s.push_str(rest);
+  s
+}
+
+/// Returns `message` translated to `language`.
+///
+/// The message is translated per `; ` separated part; parts without a
+/// matching template in the catalog are kept.
+pub fn translate(message: &str, language: &str) -> String {
+  let catalogs = CATALOGS.read().unwrap_or_else(|err| err.into_inner());
+  let catalog = match catalogs.iter().find(|(name, _)| name == language) {
+    Some((_, catalog)) => catalog,
+    None => return message.to_string(),
+  };
+  message
+    .split("; ")
+    .map(|part| catalog.iter().find_map(|(template, translation)| matches(template, part).map(|args| fill(translation, &args))).unwrap_or_else(|| part.to_string()))
+    .collect::<Vec<_>>()
+    .join("; ")
+}
+
+/// Returns `err` with its message translated to the selected language if it
+/// is a `SerializationError`.
+pub fn localize(py: Python, err: PyErr) -> PyErr {
+  if !err.is_instance::<SerializationError>(py) {
+    return err;
+  }
+  let language = match language() {
+    Some(language) => language,
+    None => return err,
+  };
+  let value = err.to_object(py);
+  let message = translate(&value.as_ref(py).to_string(), &language);
+  match value.as_ref(py).setattr("args", (message,)) {
+    Ok(()) => PyErr::from_instance(value.as_ref(py)),
+    Err(err) => err,
+  }
+}
+
+/// Returns `err` with field `name` prepended to its `path` if it is a
+/// `SerializationError`.
+pub fn with_field(py: Python, err: PyErr, name: &str) -> PyErr {
+  if !err.is_instance::<SerializationError>(py) {
+    return err;
+  }
+  let value = err.to_object(py);
+  let mut path: Vec<String> = value.as_ref(py).getattr("path").and_then(|path| path.extract()).unwrap_or_default();
+  path.insert(0, name.to_string());
+  match value.as_ref(py).setattr("path", path) {
+    Ok(()) => PyErr::from_instance(value.as_ref(py)),
+    Err(err) => err,
+  }
+}
+
+/// Returns the offset in `input` of the failure reported by `message`, if
+/// known.
+///
+/// The failure is located at the last quoted fragment of the message that
+/// occurs in the input.
+fn locate(message: &str, input: &str) -> Option<usize> {
+  let quoted: Vec<&str> = message.split('\'').skip(1).step_by(2).collect();
+  quoted.into_iter().rev().filter(|fragment| !fragment.is_empty()).find_map(|fragment| input.find(fragment))
+}
+
+/// The maximum number of characters of an excerpt of the input.
+const EXCERPT_WIDTH: usize = 60;
+
+/// Returns the characters `start..end` of `chars` with ellipses where
+/// characters are left out.
+fn window(chars: &[char], start: usize, end: usize) -> String {
+  let mut s = String::new();
+  if start > 0 {
+    s.push_str("...");
+  }
+  s.extend(chars[start..end].iter().map(|&c| if c == '\n' { ' ' } else { c }));
+  if end < chars.len() {
+    s.push_str("...");
+  }
+  s
+}
+
+/// Returns the excerpt of at most `EXCERPT_WIDTH` characters of `input` around
+/// `offset` with a caret under `offset` and the absolute character offset, or
+/// the start of the input if the offset is unknown.
+fn excerpt(input: &str, offset: Option<usize>) -> String {
+  let chars: Vec<char> = input.chars().collect();
+  let offset = match offset {
+    Some(offset) => input[..offset].chars().count(),
+    None => return format!("  {}", window(&chars, 0, chars.len().min(EXCERPT_WIDTH))),
+  };
+  let start = offset.saturating_sub(EXCERPT_WIDTH / 2).min(chars.len().saturating_sub(EXCERPT_WIDTH));
+  let end = (start + EXCERPT_WIDTH).min(chars.len());
+  let indent = (if start > 0 { 3 } else { 0 }) + offset - start;
+  format!("  {}\n  {}^ at offset {}", window(&chars, start, end), " ".repeat(indent), offset)
+}
+
+/// Returns `message` with quoted fragments longer than `EXCERPT_WIDTH`
+/// characters shortened to their start and end.
+fn abbreviate(message: &str) -> String {
+  let mut s = String::new();
+  let mut length = None;
+  for (i, part) in message.split('\'').enumerate() {
+    if i > 0 {
+      s.push('\'');
+    }
+    if let Some(length) = length.take() {
+      s.push_str(&format!(" ({} characters)", length));
+    }
+    let chars: Vec<char> = part.chars().collect();
+    if i % 2 == 1 && chars.len() > EXCERPT_WIDTH {
+      s.extend(&chars[..EXCERPT_WIDTH / 2]);
+      s.push_str("...");
+      s.extend(&chars[chars.len() - EXCERPT_WIDTH / 4..]);
+      length = Some(chars.len());
+    } else {
+      s.push_str(part);
+    }
+  }
+  s
+}
+
+/// Returns error `message` for loading `input` of type `expected` with the
+/// detail of `verbosity`.
+///
+/// Long quoted fragments of the message, typically the input, are shortened,
+/// so that the message of a huge input stays readable.
+fn detail(message: &str, input: &str, expected: &str, verbosity: Verbosity) -> String {
+  let offset = locate(message, input);
+  let message = abbreviate(message);
+  match verbosity {
+    Verbosity::Terse => message.lines().next().unwrap_or("").split("; ").next().unwrap_or("").to_string(),
+    Verbosity::Normal => message,
+    Verbosity::Verbose => format!("{}\ninput:\n{}\nexpected: {}", message, excerpt(input, offset), expected),
+  }
+}
+
+/// Returns `SerializationError` `err` of loading `input` of type `expected`
+/// with the valid values `choices`, if any, and the detail of `verbosity`.
+///
+/// The error gets the attributes `offset`, `expected`, `got` and
+/// `suggestions` in addition to the `path` of the field, see `error_to_dict`.
+pub fn error(py: Python, err: PyErr, input: &str, expected: &str, choices: Option<Vec<String>>, verbosity: Verbosity) -> PyResult<PyErr> {
+  let original = err.to_object(py);
+  let mut message = original.as_ref(py).to_string();
+  if let Some(choices) = &choices {
+    message = format!("{}; valid values are: {}", message, choices.join(", "));
+  }
+  let offset = locate(&message, input);
+  let value = SerializationError::py_err(detail(&message, input, expected, verbosity)).to_object(py);
+  let value = value.as_ref(py);
+  value.setattr("path", original.as_ref(py).getattr("path").and_then(|path| path.extract()).unwrap_or_else(|_| Vec::<String>::new()))?;
+  value.setattr("offset", offset.map(|offset| input[..offset].chars().count()))?;
+  value.setattr("expected", expected)?;
+  value.setattr("got", abbreviate(&format!("'{}'", input)).trim_matches('\''))?;
+  value.setattr("suggestions", choices.unwrap_or_default())?;
+  Ok(PyErr::from_instance(value))
+}
+
+/// Returns the details of `SerializationError` `err` as a JSON compatible
+/// dict with the keys `message`, `path`, `offset`, `expected`, `got` and
+/// `suggestions`; available as `SerializationError.to_dict`.
+///
+/// The `path` is the dotted path of the field that failed to load, or `None`
+/// for the value itself. Details that are unknown are `None`.
+#[pyfunction]
+#[text_signature = "(err, /)"]
+fn error_to_dict<'py>(py: Python<'py>, err: &PyAny) -> PyResult<&'py PyDict> {
+  guard("stringly.SerializationError.to_dict", || {
+    let dict = PyDict::new(py);
+    dict.set_item("message", err.str()?)?;
+    let path: Vec<String> = err.getattr("path").and_then(|path| path.extract()).unwrap_or_default();
+    dict.set_item("path", if path.is_empty() { None } else { Some(path.join(".")) })?;
+    for key in &["offset", "expected", "got"] {
+      match err.getattr(key) {
+        Ok(value) => dict.set_item(key, value)?,
+        Err(_) => dict.set_item(key, py.None())?,
+      }
+    }
+    dict.set_item("suggestions", err.getattr("suggestions").and_then(|suggestions| suggestions.extract()).unwrap_or_else(|_| Vec::<String>::new()))?;
+    Ok(dict)
+  })
+}
+
+/// Registers message `catalog` for `language`, a mapping of English message
+/// templates to translations.
+///
+/// In a template `{}` stands for a part of the message that varies, e.g.
+/// `"expected a duration like '1h30m' or '250ms' but got '{}'"`. A translation
+/// refers to these parts in order by `{}` or by position as `{0}`, `{1}`, ...
+/// The language of messages is selected by the `language` option or else the
+/// `STRINGLY_LANG` environment variable.
+#[pyfunction]
+#[text_signature = "(language, catalog, /)"]
+fn register_catalog(language: String, catalog: &PyAny) -> PyResult<()> {
+  guard("stringly.messages.register_catalog", || {
+    let new = catalog.call_method0("items")?.iter()?.map(|item| item?.extract()).collect::<PyResult<Vec<(String, String)>>>()?;
+    let mut catalogs = CATALOGS.write().unwrap_or_else(|err| err.into_inner());
+    match catalogs.iter_mut().find(|(name, _)| *name == language) {
+      Some((_, catalog)) => {
+        for (template, translation) in new {
+          catalog.retain(|(item, _)| *item != template);
+          catalog.push((template, translation));
+        }
+      }
+      None => catalogs.push((language, new)),
+    }
+    Ok(())
+  })
+}
+
+/// Returns `message` translated to `language`, or to the selected language if
+/// `language` is `None`.
+#[pyfunction(language = "None")]
+#[text_signature = "(message, language=None)"]
+fn translate_message(message: &str, language: Option<String>) -> PyResult<String> {
+  guard("stringly.messages.translate", || {
+    Ok(match language.or_else(self::language) {
+      Some(language) => translate(message, &language),
+      None => message.to_string(),
+    })
+  })
+}
+
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(register_catalog))?;
+  m.add("translate", wrap_pyfunction!(translate_message)(py))?;
+  let to_dict = py.import("functools")?.call1("partialmethod", (wrap_pyfunction!(error_to_dict)(py),))?;
+  SerializationError::type_object(py).setattr("to_dict", to_dict)?;
+
+  Ok(())
+}
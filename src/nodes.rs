@@ -0,0 +1,197 @@
+use crate::guard;
+use crate::merge::{is_struct, items, join};
+use pyo3::exceptions::{IndexError, KeyError, TypeError};
+use pyo3::proc_macro::{pyclass, pyfunction, pymethods};
+use pyo3::types::{PyAny, PyDict, PyList, PyModule};
+use pyo3::{wrap_pyfunction, Py, PyObject, PyRef, PyResult, Python};
+use stringly::util::{protect, safesplit, unprotect};
+
+/// A single value of a serialized string, e.g. `1.5`.
+#[pyclass]
+pub struct Scalar {
+  #[pyo3(get, set)]
+  text: String,
+}
+
+#[pymethods]
+impl Scalar {
+  #[new]
+  fn new(text: String) -> Self {
+    Scalar { text }
+  }
+  fn __repr__(&self) -> String {
+    format!("Scalar({:?})", self.text)
+  }
+}
+
+/// A sequence of `,` separated nodes of a serialized string, e.g. `1,2,3`.
+///
+/// The `items` are a mutable list of nodes.
+#[pyclass]
+pub struct Seq {
+  items: Py<PyList>,
+}
+
+#[pymethods]
+impl Seq {
+  #[new]
+  fn new(items: &PyList) -> Self {
+    Seq { items: items.into() }
+  }
+  #[getter]
+  fn get_items(&self, py: Python) -> Py<PyList> {
+    self.items.clone_ref(py)
+  }
+  fn __repr__(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Seq({})", self.items.as_ref(py).repr()?))
+  }
+}
+
+/// A struct of `key=value` items of a serialized string, e.g. `a=1,b=x`.
+///
+/// The `items` are a mutable dict of keys to nodes. Nested nodes are looked up
+/// and replaced by dotted paths, with indices for the items of a `Seq`, e.g.
+/// `node.set('solver.tolerances.0', '1e-6')`.
+#[pyclass]
+pub struct Struct {
+  items: Py<PyDict>,
+}
+
+#[pymethods]
+impl Struct {
+  #[new]
+  fn new(items: &PyDict) -> Self {
+    Struct { items: items.into() }
+  }
+  #[getter]
+  fn get_items(&self, py: Python) -> Py<PyDict> {
+    self.items.clone_ref(py)
+  }
+  /// Returns the node at dotted `path`.
+  #[text_signature = "($self, path, /)"]
+  fn get(slf: PyRef<Self>, py: Python, path: &str) -> PyResult<PyObject> {
+    guard("stringly.nodes.Struct.get", || {
+      let mut node: &PyAny = slf.items.as_ref(py).get_item(path.split('.').next().unwrap_or("")).ok_or_else(|| KeyError::py_err(path.to_string()))?;
+      for key in path.split('.').skip(1) {
+        node = child(py, node, key)?;
+      }
+      Ok(node.into())
+    })
+  }
+  /// Replaces the node at dotted `path` by `value`, a node or a serialized
+  /// string; the parent of the node must exist.
+  #[text_signature = "($self, path, value, /)"]
+  fn set(slf: PyRef<Self>, py: Python, path: &str, value: &PyAny) -> PyResult<()> {
+    guard("stringly.nodes.Struct.set", || {
+      let value = match value.extract::<&str>() {
+        Ok(s) => parse(py, s)?,
+        Err(_) => value.into(),
+      };
+      let (parent, key) = match path.rfind('.') {
+        Some(index) => (Some(&path[..index]), &path[index + 1..]),
+        None => (None, path),
+      };
+      let parent = match parent {
+        Some(parent) => Struct::get(slf, py, parent)?,
+        None => return slf.items.as_ref(py).set_item(key, value),
+      };
+      let parent = parent.as_ref(py);
+      if let Ok(parent) = parent.extract::<PyRef<Struct>>() {
+        parent.items.as_ref(py).set_item(key, value)
+      } else if let Ok(parent) = parent.extract::<PyRef<Seq>>() {
+        let items = parent.items.as_ref(py);
+        items.set_item(index(key, items.len())? as isize, value)
+      } else {
+        Err(TypeError::py_err(format!("cannot set '{}' of a scalar", key)))
+      }
+    })
+  }
+  fn __repr__(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Struct({})", self.items.as_ref(py).repr()?))
+  }
+}
+
+/// Returns index `key` of a sequence of `length` items.
+fn index(key: &str, length: usize) -> PyResult<usize> {
+  match key.parse::<usize>() {
+    Ok(index) if index < length => Ok(index),
+    _ => Err(IndexError::py_err(format!("invalid index '{}' of a sequence of {} items", key, length))),
+  }
+}
+
+/// Returns the child `key` of `node`.
+fn child<'py>(py: Python<'py>, node: &'py PyAny, key: &str) -> PyResult<&'py PyAny> {
+  if let Ok(node) = node.extract::<PyRef<Struct>>() {
+    node.items.as_ref(py).get_item(key).ok_or_else(|| KeyError::py_err(key.to_string()))
+  } else if let Ok(node) = node.extract::<PyRef<Seq>>() {
+    let items = node.items.as_ref(py);
+    Ok(items.get_item(index(key, items.len())? as isize))
+  } else {
+    Err(KeyError::py_err(format!("a scalar has no item '{}'", key)))
+  }
+}
+
+/// Returns the node graph of serialized string `s`.
+///
+/// A string of which all items are `key=value` is a `Struct`, a string of
+/// multiple items is a `Seq` and anything else is a `Scalar`. The graph is
+/// syntactic: a list of a single item is a `Scalar`, which serializes to the
+/// same string.
+pub fn parse(py: Python, s: &str) -> PyResult<PyObject> {
+  if !s.is_empty() && is_struct(s) {
+    let dict = PyDict::new(py);
+    for (key, value) in items(s)? {
+      dict.set_item(key.trim(), parse(py, value)?)?;
+    }
+    return Ok(Py::new(py, Struct { items: dict.into() })?.into());
+  }
+  let parts: Vec<&str> = safesplit(s, ',').collect();
+  if parts.len() > 1 {
+    let list = PyList::empty(py);
+    for part in parts {
+      list.append(parse(py, unprotect(part))?)?;
+    }
+    return Ok(Py::new(py, Seq { items: list.into() })?.into());
+  }
+  Ok(Py::new(py, Scalar { text: s.to_string() })?.into())
+}
+
+/// Returns the serialized string of node graph `node`.
+pub fn render(py: Python, node: &PyAny) -> PyResult<String> {
+  if let Ok(node) = node.extract::<PyRef<Scalar>>() {
+    Ok(node.text.clone())
+  } else if let Ok(node) = node.extract::<PyRef<Seq>>() {
+    let items = node.items.as_ref(py).iter().map(|item| Ok(protect(&render(py, item)?, ','))).collect::<PyResult<Vec<_>>>()?;
+    Ok(items.join(","))
+  } else if let Ok(node) = node.extract::<PyRef<Struct>>() {
+    let items = node.items.as_ref(py).iter().map(|(key, value)| Ok((key.extract()?, render(py, value)?))).collect::<PyResult<Vec<_>>>()?;
+    Ok(join(&items))
+  } else {
+    Err(TypeError::py_err(format!("expected a Struct, Seq or Scalar node but got {}", node.repr()?)))
+  }
+}
+
+/// Returns the node graph of serialized string `s`, see `Struct`, `Seq` and
+/// `Scalar`.
+#[pyfunction]
+#[text_signature = "(s, /)"]
+fn parse_node(py: Python, s: &str) -> PyResult<PyObject> {
+  guard("stringly.nodes.parse", || parse(py, s))
+}
+
+/// Returns the serialized string of node graph `node`.
+#[pyfunction]
+#[text_signature = "(node, /)"]
+fn dumps_node(py: Python, node: &PyAny) -> PyResult<String> {
+  guard("stringly.nodes.dumps", || render(py, node))
+}
+
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_class::<Scalar>()?;
+  m.add_class::<Seq>()?;
+  m.add_class::<Struct>()?;
+  m.add("parse", wrap_pyfunction!(parse_node)(py))?;
+  m.add("dumps", wrap_pyfunction!(dumps_node)(py))?;
+
+  Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::merge::{items, join};
+use crate::options::Options;
+use crate::schema::{build_with, dumps, loads, Schema, Text};
+use crate::SerializationError;
+use pyo3::types::{IntoPyDict, PyAny, PyTuple};
+use pyo3::{AsPyPointer, PyObject, PyResult, Python, ToPyObject};
+
+/// Returns the schema of `numpy.ndarray` if `ty` is that class, or `None`.
+///
+/// Numpy is an optional dependency: it is not imported by this function, so
+/// `ty` can only be `numpy.ndarray` if numpy was imported before.
+pub fn build(py: Python, ty: &PyAny, options: &Options) -> PyResult<Option<Box<dyn Schema>>> {
+  let numpy = match py.import("sys")?.getattr("modules")?.get_item("numpy") {
+    Ok(numpy) => numpy,
+    Err(_) => return Ok(None),
+  };
+  if ty.as_ptr() != numpy.getattr("ndarray")?.as_ptr() {
+    return Ok(None);
+  }
+  Ok(Some(Box::new(NdArray { numpy: numpy.into(), options: options.clone() })))
+}
+
+/// A `numpy.ndarray`, serialized as a struct of the `shape`, the `dtype` and
+/// the flattened `values`, e.g. `shape=2,2,dtype=float64,values=1.0,0.0,0.0,1.0`.
+struct NdArray {
+  numpy: PyObject,
+  options: Options,
+}
+
+impl NdArray {
+  /// Returns the schema of the flattened values of an array of `dtype`.
+  fn values(&self, py: Python, dtype: &PyAny) -> PyResult<Box<dyn Schema>> {
+    let builtins = py.import("builtins")?;
+    let item = match dtype.getattr("kind")?.extract::<&str>()? {
+      "b" => builtins.getattr("bool")?,
+      "i" | "u" => builtins.getattr("int")?,
+      "f" => builtins.getattr("float")?,
+      "c" => builtins.getattr("complex")?,
+      _ => builtins.getattr("str")?,
+    };
+    build_with(py, py.import("typing")?.getattr("List")?.get_item(item)?, &self.options)
+  }
+}
+
+impl Text for NdArray {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    let dtype = value.getattr("dtype")?;
+    let shape: Vec<usize> = value.getattr("shape")?.extract()?;
+    let values = dumps(py, self.values(py, dtype)?.as_ref(), value.call_method0("ravel")?.call_method0("tolist")?)?;
+    let shape = shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(",");
+    Ok(join(&[("shape".to_string(), shape), ("dtype".to_string(), dtype.str()?.to_str()?.to_string()), ("values".to_string(), values)]))
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let (mut shape, mut dtype, mut values) = (None, None, None);
+    for (key, value) in items(s)? {
+      match key.trim() {
+        "shape" => shape = Some(value),
+        "dtype" => dtype = Some(value.trim()),
+        "values" => values = Some(value),
+        key => return Err(SerializationError::py_err(format!("unknown field '{}' of ndarray, expected one of: shape, dtype, values", key))),
+      }
+    }
+    let (shape, dtype, values) = match (shape, dtype, values) {
+      (Some(shape), Some(dtype), Some(values)) => (shape, dtype, values),
+      _ => return Err(SerializationError::py_err(format!("expected shape, dtype and values of ndarray but got '{}'", s))),
+    };
+    let shape = shape.split(',').filter(|n| !n.trim().is_empty()).map(|n| n.trim().parse::<usize>()).collect::<Result<Vec<_>, _>>().map_err(|_| SerializationError::py_err(format!("invalid shape '{}'", shape)))?;
+    let numpy = self.numpy.as_ref(py);
+    let dtype = match numpy.call_method1("dtype", (dtype,)) {
+      Ok(dtype) => dtype,
+      Err(_) => return Err(SerializationError::py_err(format!("invalid dtype '{}'", dtype))),
+    };
+    let values = loads(py, self.values(py, dtype)?.as_ref(), values)?;
+    if values.as_ref(py).len()? != shape.iter().product::<usize>() {
+      return Err(SerializationError::py_err(format!("expected {} values for shape {:?} but got {}", shape.iter().product::<usize>(), shape, values.as_ref(py).len()?)));
+    }
+    let array = numpy.call_method("array", (values,), Some([("dtype", dtype)].into_py_dict(py)))?;
+    Ok(array.call_method1("reshape", (PyTuple::new(py, shape),))?.to_object(py))
+  }
+  fn describe(&self, _py: Python) -> PyResult<String> {
+    Ok("ndarray".to_string())
+  }
+}
@@ -0,0 +1,375 @@
+use crate::{expr, file, guard};
+use pyo3::exceptions::{TypeError, ValueError};
+use pyo3::proc_macro::{pyclass, pyfunction, pymethods};
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyModule, PyTuple};
+use pyo3::{wrap_pyfunction, Py, PyObject, PyResult, Python};
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock};
+
+/// The detail of error messages of `loads`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Verbosity {
+  /// Only the first line of the message, without suggestions.
+  Terse,
+  /// The message with suggestions.
+  Normal,
+  /// The message with an excerpt of the input that marks the failure and the
+  /// expected type.
+  Verbose,
+}
+
+impl Default for Verbosity {
+  fn default() -> Self {
+    Verbosity::Normal
+  }
+}
+
+impl Verbosity {
+  fn name(self) -> &'static str {
+    match self {
+      Verbosity::Terse => "terse",
+      Verbosity::Normal => "normal",
+      Verbosity::Verbose => "verbose",
+    }
+  }
+}
+
+/// Options that affect how values are serialized and deserialized.
+///
+/// Instances are immutable; `replace` returns a copy with some options
+/// replaced, e.g. `stringly.Options(tolerant=True).replace(sort_keys=True)`.
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct Options {
+  /// Accept constant arithmetic expressions for `int` and `float`.
+  pub allow_expressions: bool,
+  /// Named constants accepted for `int` and `float`, by themselves or in
+  /// expressions.
+  pub constants: Option<Vec<(String, f64)>>,
+  /// Resolve `$key` references between fields before deserializing.
+  pub resolve_references: bool,
+  /// Serialize the items of dicts sorted by key.
+  pub sort_keys: bool,
+  /// Accept common alternative spellings of values, e.g. `yes` for `true`.
+  pub tolerant: bool,
+  /// The format specification of floats, e.g. `.6g`.
+  pub float_format: Option<String>,
+  /// Key fragments that mark a value as secret in log renderings, see
+  /// `stringly.logging.format_params`.
+  pub secrets: Option<Vec<String>>,
+  /// Named parameter sets, see `stringly.DocString.presets`. Structs that
+  /// match a preset are serialized as `preset{overrides}`.
+  pub presets: Option<Vec<(String, Vec<(String, String)>)>>,
+  /// The language of error messages, see
+  /// `stringly.messages.register_catalog`.
+  pub language: Option<String>,
+  /// The detail of error messages, `terse`, `normal` or `verbose`.
+  pub verbose_errors: Verbosity,
+  /// The text encoding of `bytes` and `bytearray`, `hex` (default) or
+  /// `base64`.
+  pub bytes_encoding: Option<String>,
+  /// Hooks per field name that load the field from its serialized string
+  /// instead of the schema of its type, e.g. to open a file.
+  pub coerce: Option<Vec<(String, Arc<PyObject>)>>,
+  /// The directory against which relative paths of `FromFile` values are
+  /// resolved.
+  pub base_dir: Option<String>,
+  /// Remember the original text of the numeric and boolean fields of loaded
+  /// structs and dump unchanged values in that form, e.g. `1e-8` rather than
+  /// `1e-08`.
+  pub preserve_scalars: bool,
+  /// Prefix the output of `dumps` with the header `!stringly/1 name` of the
+  /// format version and the type, and validate such a header in `loads`.
+  pub header: bool,
+  /// Warn with `StringlyWarning` about directives of docstrings that are not
+  /// recognized, e.g. a misspelled `.. argument::`.
+  pub warn_unknown_directives: bool,
+}
+
+impl Options {
+  /// Returns a copy of the options with the options in `kwargs` replaced.
+  ///
+  /// The `constants` are either `None` or `False`, `True` for the predefined
+  /// constants or a mapping of names to numbers that extends the predefined
+  /// constants.
+  pub fn with(&self, kwargs: Option<&PyDict>) -> PyResult<Self> {
+    let mut options = self.clone();
+    for (key, value) in kwargs.into_iter().flat_map(|kwargs| kwargs.iter()) {
+      match key.extract::<&str>()? {
+        "allow_expressions" => options.allow_expressions = value.extract()?,
+        "constants" => options.constants = constants(value)?,
+        "resolve_references" => options.resolve_references = value.extract()?,
+        "sort_keys" => options.sort_keys = value.extract()?,
+        "tolerant" => options.tolerant = value.extract()?,
+        "float_format" => options.float_format = value.extract()?,
+        "secrets" => options.secrets = value.extract()?,
+        "presets" => options.presets = presets(value)?,
+        "language" => options.language = value.extract()?,
+        "bytes_encoding" => {
+          options.bytes_encoding = value.extract()?;
+          if let Some(encoding) = options.bytes_encoding.as_deref().filter(|&encoding| encoding != "hex" && encoding != "base64") {
+            return Err(ValueError::py_err(format!("bytes_encoding must be 'hex' or 'base64' but got '{}'", encoding)));
+          }
+        }
+        "coerce" if value.is_none() => options.coerce = None,
+        "coerce" => options.coerce = Some(value.call_method0("items")?.iter()?.map(|item| item?.extract().map(|(name, hook): (String, &PyAny)| (name, Arc::new(hook.into())))).collect::<PyResult<_>>()?),
+        "base_dir" if value.is_none() => options.base_dir = None,
+        "base_dir" => options.base_dir = Some(file::fspath(value.py(), value)?.to_string_lossy().into_owned()),
+        "preserve_scalars" => options.preserve_scalars = value.extract()?,
+        "header" => options.header = value.extract()?,
+        "warn_unknown_directives" => options.warn_unknown_directives = value.extract()?,
+        "verbose_errors" => {
+          options.verbose_errors = match value.extract::<&str>()? {
+            "terse" => Verbosity::Terse,
+            "normal" => Verbosity::Normal,
+            "verbose" => Verbosity::Verbose,
+            value => return Err(ValueError::py_err(format!("verbose_errors must be 'terse', 'normal' or 'verbose' but got '{}'", value))),
+          }
+        }
+        key => return Err(TypeError::py_err(format!("unknown option '{}'", key))),
+      }
+    }
+    Ok(options)
+  }
+}
+
+#[pymethods]
+impl Options {
+  #[new]
+  #[args(options = "**")]
+  fn new(options: Option<&PyDict>) -> PyResult<Self> {
+    guard("stringly.Options", || Options::default().with(options))
+  }
+  /// Returns a copy with the options in `changes` replaced.
+  #[args(changes = "**")]
+  #[text_signature = "($self, **changes)"]
+  fn replace(&self, changes: Option<&PyDict>) -> PyResult<Self> {
+    guard("stringly.Options.replace", || self.with(changes))
+  }
+  /// Returns the options as keyword arguments.
+  #[text_signature = "($self)"]
+  pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("allow_expressions", self.allow_expressions)?;
+    match &self.constants {
+      Some(constants) => dict.set_item("constants", constants[..].into_py_dict(py))?,
+      None => dict.set_item("constants", py.None())?,
+    }
+    dict.set_item("resolve_references", self.resolve_references)?;
+    dict.set_item("sort_keys", self.sort_keys)?;
+    dict.set_item("tolerant", self.tolerant)?;
+    dict.set_item("float_format", &self.float_format)?;
+    dict.set_item("secrets", &self.secrets)?;
+    dict.set_item("presets", self.get_presets(py))?;
+    dict.set_item("language", &self.language)?;
+    dict.set_item("verbose_errors", self.verbose_errors.name())?;
+    dict.set_item("bytes_encoding", &self.bytes_encoding)?;
+    dict.set_item("coerce", self.get_coerce(py))?;
+    dict.set_item("base_dir", &self.base_dir)?;
+    dict.set_item("preserve_scalars", self.preserve_scalars)?;
+    dict.set_item("header", self.header)?;
+    dict.set_item("warn_unknown_directives", self.warn_unknown_directives)?;
+    Ok(dict)
+  }
+  fn __repr__(&self, py: Python) -> PyResult<String> {
+    let items = self.to_dict(py)?.iter().map(|(key, value)| Ok(format!("{}={}", key, value.repr()?))).collect::<PyResult<Vec<_>>>()?;
+    Ok(format!("Options({})", items.join(", ")))
+  }
+  #[getter]
+  fn get_allow_expressions(&self) -> bool {
+    self.allow_expressions
+  }
+  #[getter]
+  fn get_constants<'py>(&self, py: Python<'py>) -> Option<&'py PyDict> {
+    self.constants.as_ref().map(|constants| constants[..].into_py_dict(py))
+  }
+  #[getter]
+  fn get_resolve_references(&self) -> bool {
+    self.resolve_references
+  }
+  #[getter]
+  fn get_sort_keys(&self) -> bool {
+    self.sort_keys
+  }
+  #[getter]
+  fn get_tolerant(&self) -> bool {
+    self.tolerant
+  }
+  #[getter]
+  fn get_float_format(&self) -> Option<String> {
+    self.float_format.clone()
+  }
+  #[getter]
+  fn get_secrets(&self) -> Option<Vec<String>> {
+    self.secrets.clone()
+  }
+  #[getter]
+  fn get_base_dir(&self) -> Option<String> {
+    self.base_dir.clone()
+  }
+  #[getter]
+  fn get_preserve_scalars(&self) -> bool {
+    self.preserve_scalars
+  }
+  #[getter]
+  fn get_header(&self) -> bool {
+    self.header
+  }
+  #[getter]
+  fn get_warn_unknown_directives(&self) -> bool {
+    self.warn_unknown_directives
+  }
+  #[getter]
+  fn get_coerce<'py>(&self, py: Python<'py>) -> Option<&'py PyDict> {
+    self.coerce.as_ref().map(|coerce| coerce.iter().map(|(name, hook)| (name.as_str(), hook.as_ref().clone_ref(py))).collect::<Vec<_>>()[..].into_py_dict(py))
+  }
+  #[getter]
+  fn get_bytes_encoding(&self) -> Option<String> {
+    self.bytes_encoding.clone()
+  }
+  #[getter]
+  fn get_verbose_errors(&self) -> &'static str {
+    self.verbose_errors.name()
+  }
+  #[getter]
+  fn get_language(&self) -> Option<String> {
+    self.language.clone()
+  }
+  #[getter]
+  fn get_presets<'py>(&self, py: Python<'py>) -> Option<&'py PyDict> {
+    self.presets.as_ref().map(|presets| presets.iter().map(|(name, parameters)| (name.as_str(), parameters[..].into_py_dict(py))).collect::<Vec<_>>()[..].into_py_dict(py))
+  }
+}
+
+/// Returns the named constants of option value `constants`, see `Options::with`.
+fn constants(constants: &PyAny) -> PyResult<Option<Vec<(String, f64)>>> {
+  if constants.is_none() || matches!(constants.extract::<bool>(), Ok(false)) {
+    return Ok(None);
+  }
+  let mut table: Vec<(String, f64)> = expr::CONSTANTS.iter().map(|&(name, value)| (name.to_string(), value)).collect();
+  if constants.extract::<bool>().is_err() {
+    for item in constants.call_method0("items")?.iter()? {
+      let (name, value): (String, f64) = item?.extract()?;
+      table.retain(|(constant, _)| *constant != name);
+      table.push((name, value));
+    }
+  }
+  Ok(Some(table))
+}
+
+/// Returns the presets of option value `presets`, a mapping of names to
+/// mappings of keys to serialized values, see `Options::with`.
+fn presets(presets: &PyAny) -> PyResult<Option<Vec<(String, Vec<(String, String)>)>>> {
+  if presets.is_none() {
+    return Ok(None);
+  }
+  let mut table = Vec::new();
+  for item in presets.call_method0("items")?.iter()? {
+    let (name, parameters): (String, &PyAny) = item?.extract()?;
+    let parameters = parameters.call_method0("items")?.iter()?.map(|item| item?.extract()).collect::<PyResult<Vec<(String, String)>>>()?;
+    table.push((name, parameters));
+  }
+  Ok(Some(table))
+}
+
+/// The process wide defaults set by `configure`.
+static DEFAULTS: RwLock<Option<Options>> = RwLock::new(None);
+
+/// Returns the process wide defaults.
+fn defaults() -> Options {
+  DEFAULTS.read().unwrap_or_else(|err| err.into_inner()).clone().unwrap_or_default()
+}
+
+thread_local! {
+  /// The stack of options set by the `options` context manager in this thread.
+  static STACK: RefCell<Vec<Options>> = RefCell::new(Vec::new());
+}
+
+/// Returns the options in effect in the current thread.
+pub fn current() -> Options {
+  STACK.with(|stack| stack.borrow().last().cloned()).unwrap_or_else(defaults)
+}
+
+/// Context manager that sets the options of the current thread, see `options`.
+#[pyclass]
+struct Scope {
+  base: Option<Options>,
+  kwargs: Option<Py<PyDict>>,
+}
+
+#[pymethods]
+impl Scope {
+  fn __enter__(&self, py: Python) -> PyResult<()> {
+    guard("stringly.options", || {
+      let options = self.base.clone().unwrap_or_else(current).with(self.kwargs.as_ref().map(|kwargs| kwargs.as_ref(py)))?;
+      STACK.with(|stack| stack.borrow_mut().push(options));
+      Ok(())
+    })
+  }
+  #[args(_args = "*")]
+  fn __exit__(&self, _args: &PyTuple) -> PyResult<bool> {
+    guard("stringly.options", || {
+      STACK.with(|stack| stack.borrow_mut().pop());
+      Ok(false)
+    })
+  }
+}
+
+/// Returns a context manager that sets default options for all `dumps` and
+/// `loads` calls in the current thread inside the block, e.g.
+/// `with stringly.options(sort_keys=True, tolerant=True):`.
+///
+/// The options in `overrides` replace those of `options`, an `Options`
+/// instance, or of the options in effect if `options` is `None`. Blocks can
+/// be nested; options passed to a call take precedence.
+#[pyfunction(options = "None", overrides = "**")]
+#[text_signature = "(options=None, /, **overrides)"]
+fn options(options: Option<Options>, overrides: Option<&PyDict>) -> PyResult<Scope> {
+  guard("stringly.options", || {
+    // Validate the options early.
+    options.clone().unwrap_or_default().with(overrides)?;
+    Ok(Scope { base: options, kwargs: overrides.map(|overrides| overrides.copy()).transpose()?.map(Into::into) })
+  })
+}
+
+/// Sets process wide defaults of options, consulted by every function that
+/// serializes or deserializes values, e.g.
+/// `stringly.configure(tolerant=True, float_format='.6g')`.
+///
+/// The options in `overrides` replace those of `options`, an `Options`
+/// instance, or of the current defaults if `options` is `None`. Options set
+/// with `options` or passed to a call take precedence.
+#[pyfunction(options = "None", overrides = "**")]
+#[text_signature = "(options=None, /, **overrides)"]
+fn configure(options: Option<Options>, overrides: Option<&PyDict>) -> PyResult<()> {
+  guard("stringly.configure", || {
+    let mut lock = DEFAULTS.write().unwrap_or_else(|err| err.into_inner());
+    *lock = Some(options.or_else(|| lock.clone()).unwrap_or_default().with(overrides)?);
+    Ok(())
+  })
+}
+
+/// Returns the process wide defaults of options, see `configure`.
+#[pyfunction]
+#[text_signature = "()"]
+fn get_configuration() -> PyResult<Options> {
+  guard("stringly.get_configuration", || Ok(defaults()))
+}
+
+/// Returns the options in effect in the current thread.
+#[pyfunction]
+#[text_signature = "()"]
+fn current_options() -> PyResult<Options> {
+  guard("stringly.current_options", || Ok(current()))
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(options))?;
+  m.add_wrapped(wrap_pyfunction!(configure))?;
+  m.add_wrapped(wrap_pyfunction!(get_configuration))?;
+  m.add_wrapped(wrap_pyfunction!(current_options))?;
+  m.add_class::<Options>()?;
+  m.add_class::<Scope>()?;
+
+  Ok(())
+}
@@ -0,0 +1,63 @@
+use crate::options::Options;
+use crate::schema::Schema;
+use crate::{guard, nodes};
+use pyo3::proc_macro::{pyclass, pymethods};
+use pyo3::{PyObject, PyResult, Python};
+use std::rc::Rc;
+
+/// A serialized string parsed for a type by `stringly.parse`, ready to be
+/// constructed by `stringly.construct`.
+///
+/// Parsing builds the schema of the type, i.e. introspects its annotations,
+/// and expands the compact forms of the `presets` option and resolves
+/// references, the latter without holding the GIL. Construction only
+/// deserializes the string with this schema, so that a parsed document can be
+/// constructed multiple times at little cost. Before construction the
+/// document can be queried and modified through its node graph `root`, see
+/// `stringly.nodes`. A handle can only be used by the thread that created it.
+#[pyclass(unsendable)]
+pub struct Parsed {
+  pub ty: PyObject,
+  /// The schema of `ty`, built with `options`.
+  pub schema: Rc<dyn Schema>,
+  /// The serialized string with presets expanded and references resolved.
+  pub s: String,
+  /// The options the string was parsed with.
+  pub options: Options,
+  /// The node graph of the string, once requested.
+  pub root: Option<PyObject>,
+}
+
+impl Parsed {
+  /// Returns the serialized string, as modified through the node graph.
+  pub fn text(&self, py: Python) -> PyResult<String> {
+    match &self.root {
+      Some(root) => nodes::render(py, root.as_ref(py)),
+      None => Ok(self.s.clone()),
+    }
+  }
+}
+
+#[pymethods]
+impl Parsed {
+  #[getter]
+  fn get_type(&self, py: Python) -> PyObject {
+    self.ty.clone_ref(py)
+  }
+  #[getter]
+  fn get_text(&self, py: Python) -> PyResult<String> {
+    guard("stringly.Parsed.text", || self.text(py))
+  }
+  #[getter]
+  fn get_root(&mut self, py: Python) -> PyResult<PyObject> {
+    guard("stringly.Parsed.root", || {
+      if self.root.is_none() {
+        self.root = Some(nodes::parse(py, &self.s)?);
+      }
+      Ok(self.root.as_ref().map(|root| root.clone_ref(py)).unwrap_or_else(|| py.None()))
+    })
+  }
+  fn __repr__(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Parsed({:?})", self.text(py)?))
+  }
+}
@@ -0,0 +1,46 @@
+use crate::options::Options;
+use crate::schema::Schema;
+use crate::structs::{Field, Struct};
+use pyo3::types::{PyAny, PyTuple};
+use pyo3::{PyResult, Python};
+
+/// Returns the struct schema of `ty` if it is a subclass of pydantic's
+/// `BaseModel`, or `None`.
+///
+/// Pydantic is an optional dependency: it is not imported by this function,
+/// so `ty` can only be a model if pydantic was imported before. The fields
+/// are those of the model, with the `typing.Annotated` metadata of their
+/// annotations, for both pydantic 1 and 2. Values are loaded by calling the
+/// model with the fields as keyword arguments, which runs model validation; a
+/// `ValidationError` is reported as `SerializationError`.
+pub fn build(py: Python, ty: &PyAny, options: &Options) -> PyResult<Option<Box<dyn Schema>>> {
+  let pydantic = match py.import("sys")?.getattr("modules")?.get_item("pydantic") {
+    Ok(pydantic) => pydantic,
+    Err(_) => return Ok(None),
+  };
+  let builtins = py.import("builtins")?;
+  if !builtins.call1("isinstance", (ty, builtins.getattr("type")?))?.is_true()? || !builtins.call1("issubclass", (ty, pydantic.getattr("BaseModel")?))?.is_true()? {
+    return Ok(None);
+  }
+  let mut fields = Vec::new();
+  if let Ok(model_fields) = ty.getattr("model_fields") {
+    for item in model_fields.call_method0("items")?.iter()? {
+      let (name, info): (String, &PyAny) = item?.extract()?;
+      let mut annotation = info.getattr("annotation")?;
+      let metadata: Vec<&PyAny> = info.getattr("metadata")?.extract()?;
+      if !metadata.is_empty() {
+        let args: Vec<&PyAny> = std::iter::once(annotation).chain(metadata).collect();
+        annotation = py.import("typing")?.getattr("Annotated")?.get_item(PyTuple::new(py, args))?;
+      }
+      let required = info.call_method0("is_required")?.is_true()?;
+      fields.push(Field::new(py, name, annotation, required, options)?);
+    }
+  } else {
+    for item in ty.getattr("__fields__")?.call_method0("items")?.iter()? {
+      let (name, field): (String, &PyAny) = item?.extract()?;
+      let required = field.getattr("required")?.is_true()?;
+      fields.push(Field::new(py, name, field.getattr("outer_type_")?, required, options)?);
+    }
+  }
+  Ok(Some(Box::new(Struct { ty: ty.into(), fields, mapping: false, preserve: options.preserve_scalars, positional: false })))
+}
@@ -0,0 +1,40 @@
+use crate::guard;
+use pyo3::exceptions::ValueError;
+use pyo3::proc_macro::{pyclass, pymethods};
+use pyo3::types::PyAny;
+use pyo3::{PyRef, PyResult};
+
+/// An already serialized string that `dumps` embeds verbatim in place of a
+/// value, e.g. `stringly.dumps(Config, Config(solver=stringly.Raw(cached)))`.
+///
+/// The string must have balanced braces, so that it cannot break the
+/// structure of the document it is embedded in. Raw strings are embedded at
+/// the top level, as fields of structs and as items of containers of which the
+/// items are serialized by stringly itself rather than by the core
+/// serializer, e.g. lists of structs but not `List[int]`.
+#[pyclass]
+pub struct Raw {
+  #[pyo3(get)]
+  text: String,
+}
+
+#[pymethods]
+impl Raw {
+  #[new]
+  fn new(text: String) -> PyResult<Self> {
+    guard("stringly.Raw", || {
+      if !stringly::util::is_balanced(&text) {
+        return Err(ValueError::py_err(format!("unbalanced braces in raw string '{}'", text)));
+      }
+      Ok(Raw { text })
+    })
+  }
+  fn __repr__(&self) -> String {
+    format!("Raw({:?})", self.text)
+  }
+}
+
+/// Returns the text of `value` if it is a `Raw` string.
+pub fn text(value: &PyAny) -> Option<String> {
+  value.extract::<PyRef<Raw>>().ok().map(|raw| raw.text.clone())
+}
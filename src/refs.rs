@@ -0,0 +1,61 @@
+use crate::merge::{is_struct, items, join};
+use crate::SerializationError;
+use pyo3::PyResult;
+
+/// Resolves references between the items of struct string `s`.
+///
+/// An item `key=$path` takes the value of the item referenced by `path`,
+/// which is a key of `s` optionally followed by dotted keys into the nested
+/// struct value, e.g. `ny=$nx` or `dt=$solver.dt`. References are resolved
+/// recursively; a value starting with a literal `$` is written as `$$`. A
+/// string that is not a struct is returned unchanged.
+pub fn resolve(s: &str) -> PyResult<String> {
+  if !is_struct(s) {
+    return Ok(s.to_string());
+  }
+  let items = items(s)?;
+  let mut resolved = vec![None; items.len()];
+  for index in 0..items.len() {
+    resolve_item(&items, index, &mut resolved, &mut Vec::new())?;
+  }
+  Ok(join(&items.iter().zip(resolved).map(|((key, _), value)| (key.to_string(), value.unwrap_or_default())).collect::<Vec<_>>()))
+}
+
+/// Returns the resolved value of item `index`, see `resolve`.
+///
+/// The `chain` holds the indices of the items that are being resolved and is
+/// used to detect circular references.
+fn resolve_item(items: &[(&str, &str)], index: usize, resolved: &mut Vec<Option<String>>, chain: &mut Vec<usize>) -> PyResult<String> {
+  if let Some(value) = &resolved[index] {
+    return Ok(value.clone());
+  }
+  if chain.contains(&index) {
+    let cycle: Vec<&str> = chain.iter().chain(Some(&index)).map(|&index| items[index].0.trim()).collect();
+    return Err(SerializationError::py_err(format!("circular reference {}", cycle.join(" -> "))));
+  }
+  let value = items[index].1;
+  let value = if let Some(value) = value.strip_prefix("$$") {
+    format!("${}", value)
+  } else if let Some(path) = value.strip_prefix('$') {
+    let mut keys = path.split('.');
+    let head = keys.next().unwrap_or_default();
+    let target = match items.iter().position(|(key, _)| key.trim() == head) {
+      Some(target) => target,
+      None => return Err(SerializationError::py_err(format!("{} references unknown key '{}'", items[index].0.trim(), head))),
+    };
+    chain.push(index);
+    let mut value = resolve_item(items, target, resolved, chain)?;
+    chain.pop();
+    for key in keys {
+      value = match crate::merge::items(&value)?.into_iter().find(|(item, _)| item.trim() == key) {
+        Some((_, value)) => value.to_string(),
+        None => return Err(SerializationError::py_err(format!("{} references unknown key '{}'", items[index].0.trim(), path))),
+      };
+    }
+    value
+  } else {
+    value.to_string()
+  };
+  resolved[index] = Some(value.clone());
+  Ok(value)
+}
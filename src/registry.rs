@@ -0,0 +1,357 @@
+use crate::schema::{dumps, loads, type_name, Schema, Text};
+use crate::{guard, SerializationError};
+use pyo3::exceptions::{TypeError, ValueError};
+use pyo3::proc_macro::{pyclass, pyfunction, pymethods};
+use pyo3::types::{PyAny, PyModule, PyTuple};
+use pyo3::{wrap_pyfunction, AsPyPointer, PyObject, PyResult, Python};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A custom adapter that serializes values of a type with Python functions.
+pub struct Adapter {
+  ty: PyObject,
+  dumps: PyObject,
+  loads: PyObject,
+  /// Whether the adapter also serializes values of subclasses of `ty`, see
+  /// `register_type`.
+  subclasses: bool,
+}
+
+impl Adapter {
+  fn clone_ref(&self, py: Python) -> Self {
+    Adapter { ty: self.ty.clone_ref(py), dumps: self.dumps.clone_ref(py), loads: self.loads.clone_ref(py), subclasses: self.subclasses }
+  }
+}
+
+/// The registered adapters.
+///
+/// The lock is only held while the list is read or modified and never while
+/// Python code runs, so concurrent `dumps` and `loads` calls from multiple
+/// threads see a consistent registry.
+static ADAPTERS: RwLock<Vec<Adapter>> = RwLock::new(Vec::new());
+
+fn read() -> RwLockReadGuard<'static, Vec<Adapter>> {
+  ADAPTERS.read().unwrap_or_else(|err| err.into_inner())
+}
+
+fn write() -> RwLockWriteGuard<'static, Vec<Adapter>> {
+  ADAPTERS.write().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns the adapter registered for type `ty`, if any, or else the adapter
+/// of the nearest base class of `ty` registered for subclasses.
+pub fn lookup(py: Python, ty: &PyAny) -> Option<Adapter> {
+  if let Some(adapter) = read().iter().find(|adapter| adapter.ty.as_ptr() == ty.as_ptr()) {
+    return Some(adapter.clone_ref(py));
+  }
+  let mro = ty.getattr("__mro__").and_then(|mro| mro.iter()).ok()?;
+  // The lock is released while iterating the bases, which may run Python code.
+  let bases: Vec<&PyAny> = mro.skip(1).filter_map(|base| base.ok()).collect();
+  let adapters = read();
+  bases.iter().find_map(|base| adapters.iter().find(|adapter| adapter.subclasses && adapter.ty.as_ptr() == base.as_ptr())).map(|adapter| adapter.clone_ref(py))
+}
+
+/// Registers `adapter` and returns the adapter it replaces, if any.
+fn insert(adapter: Adapter) -> Option<Adapter> {
+  let mut adapters = write();
+  match adapters.iter().position(|item| item.ty.as_ptr() == adapter.ty.as_ptr()) {
+    Some(index) => Some(std::mem::replace(&mut adapters[index], adapter)),
+    None => {
+      adapters.push(adapter);
+      None
+    }
+  }
+}
+
+/// Unregisters the adapter of `ty` and returns it, if any.
+fn remove(ty: &PyAny) -> Option<Adapter> {
+  let mut adapters = write();
+  let index = adapters.iter().position(|item| item.ty.as_ptr() == ty.as_ptr())?;
+  Some(adapters.remove(index))
+}
+
+impl Text for Adapter {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    self.dumps.as_ref(py).call1((value,))?.extract()
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    match self.loads.as_ref(py).call1((s,)) {
+      Ok(value) => Ok(value.into()),
+      Err(err) if err.is_instance::<ValueError>(py) => Err(SerializationError::py_err(format!("invalid {} '{}': {}", type_name(self.ty.as_ref(py))?, s, err.to_object(py).as_ref(py)))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    type_name(self.ty.as_ref(py))
+  }
+}
+
+/// Registers functions `dumps` and `loads` that serialize values of `type` to
+/// and from `str`, replacing any adapter registered for `type` before.
+///
+/// A `ValueError` raised by `loads` is reported as `SerializationError`.
+#[pyfunction]
+#[text_signature = "(type, dumps, loads, /)"]
+fn register(ty: &PyAny, dumps: &PyAny, loads: &PyAny) -> PyResult<()> {
+  guard("stringly.register", || {
+    insert(Adapter { ty: ty.into(), dumps: dumps.into(), loads: loads.into(), subclasses: false });
+    Ok(())
+  })
+}
+
+/// Registers functions `dump_fn` and `load_fn` that serialize values of
+/// `py_type` and of its subclasses to and from `str`, for plugins that add
+/// support for third-party types at import time, e.g. quantities with units.
+///
+/// An adapter registered for a subclass itself, by `register` or
+/// `register_type`, takes precedence. As with `register`, a `ValueError`
+/// raised by `load_fn` is reported as `SerializationError`.
+#[pyfunction]
+#[text_signature = "(py_type, dump_fn, load_fn, /)"]
+fn register_type(py_type: &PyAny, dump_fn: &PyAny, load_fn: &PyAny) -> PyResult<()> {
+  guard("stringly.register_type", || {
+    insert(Adapter { ty: py_type.into(), dumps: dump_fn.into(), loads: load_fn.into(), subclasses: true });
+    Ok(())
+  })
+}
+
+/// Unregisters the adapter of `type`; does nothing if there is none.
+#[pyfunction]
+#[text_signature = "(type, /)"]
+fn unregister(ty: &PyAny) -> PyResult<()> {
+  guard("stringly.unregister", || {
+    remove(ty);
+    Ok(())
+  })
+}
+
+/// Value aliases per type, as pairs of an alias and the serialized value it
+/// stands for.
+static ALIASES: RwLock<Vec<(PyObject, Vec<(String, String)>)>> = RwLock::new(Vec::new());
+
+/// Returns the value aliases registered for type `ty`, if any.
+pub fn aliases(ty: &PyAny) -> Option<Vec<(String, String)>> {
+  let aliases = ALIASES.read().unwrap_or_else(|err| err.into_inner());
+  aliases.iter().find(|(item, _)| item.as_ptr() == ty.as_ptr()).map(|(_, aliases)| aliases.clone())
+}
+
+/// A schema of a type with value aliases.
+pub struct Aliased {
+  pub aliases: Vec<(String, String)>,
+  pub schema: Box<dyn Schema>,
+}
+
+impl Text for Aliased {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    dumps(py, self.schema.as_ref(), value)
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    if let Some((_, value)) = self.aliases.iter().find(|(alias, _)| alias == s.trim()) {
+      return loads(py, self.schema.as_ref(), value);
+    }
+    match loads(py, self.schema.as_ref(), s) {
+      Err(err) if err.is_instance::<SerializationError>(py) => {
+        let aliases = self.aliases.iter().map(|(alias, value)| format!("{} for {}", alias, value)).collect::<Vec<_>>().join(", ");
+        Err(SerializationError::py_err(format!("{}; aliases are: {}", err.to_object(py).as_ref(py), aliases)))
+      }
+      result => result,
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    self.schema.describe(py)
+  }
+  fn choices(&self, py: Python) -> PyResult<Option<Vec<String>>> {
+    Ok(self.schema.choices(py)?.map(|mut choices| {
+      choices.extend(self.aliases.iter().map(|(alias, _)| alias.clone()));
+      choices
+    }))
+  }
+}
+
+/// Registers value aliases for `type`, a mapping of aliases to the serialized
+/// values they stand for, e.g. `{'linear': 'p1'}`.
+///
+/// Aliases are accepted by `loads` in place of their values and listed when a
+/// value fails to load. Aliases registered for `type` before are kept unless
+/// redefined.
+#[pyfunction]
+#[text_signature = "(type, aliases, /)"]
+fn register_aliases(ty: &PyAny, aliases: &PyAny) -> PyResult<()> {
+  guard("stringly.register_aliases", || {
+    let new = aliases.call_method0("items")?.iter()?.map(|item| item?.extract()).collect::<PyResult<Vec<(String, String)>>>()?;
+    let mut table = ALIASES.write().unwrap_or_else(|err| err.into_inner());
+    let index = match table.iter().position(|(item, _)| item.as_ptr() == ty.as_ptr()) {
+      Some(index) => index,
+      None => {
+        table.push((ty.into(), Vec::new()));
+        table.len() - 1
+      }
+    };
+    for (alias, value) in new {
+      let aliases = &mut table[index].1;
+      aliases.retain(|(item, _)| *item != alias);
+      aliases.push((alias, value));
+    }
+    Ok(())
+  })
+}
+
+/// Short names of types, see `alias`.
+static TYPE_NAMES: RwLock<Vec<(String, PyObject)>> = RwLock::new(Vec::new());
+
+/// Returns the short name registered for type `ty`, if any.
+pub fn type_alias(ty: &PyAny) -> Option<String> {
+  let names = TYPE_NAMES.read().unwrap_or_else(|err| err.into_inner());
+  names.iter().find(|(_, item)| item.as_ptr() == ty.as_ptr()).map(|(name, _)| name.clone())
+}
+
+/// Returns the registered short names and their types.
+pub fn type_aliases(py: Python) -> Vec<(String, PyObject)> {
+  let names = TYPE_NAMES.read().unwrap_or_else(|err| err.into_inner());
+  names.iter().map(|(name, ty)| (name.clone(), ty.clone_ref(py))).collect()
+}
+
+/// Registers short `name` for `type`, e.g. `stringly.alias('Mesh',
+/// my.pkg.MeshConfig)`, and returns `type`.
+///
+/// The name is used for the type in descriptions and error messages, as the
+/// tag of the type in unions and may be used in type strings, see
+/// `stringly.typing.parse_type_str`. A name is registered for one type and a
+/// type has one name; registering either again replaces the previous alias.
+#[pyfunction]
+#[text_signature = "(name, type, /)"]
+fn alias<'py>(name: String, ty: &'py PyAny) -> PyResult<&'py PyAny> {
+  guard("stringly.alias", || {
+    let mut names = TYPE_NAMES.write().unwrap_or_else(|err| err.into_inner());
+    names.retain(|(item, other)| *item != name && other.as_ptr() != ty.as_ptr());
+    names.push((name, ty.into()));
+    Ok(ty)
+  })
+}
+
+/// Named subclasses per base class, see `register_subclasses`.
+static SUBCLASSES: RwLock<Vec<(PyObject, Vec<(String, PyObject)>)>> = RwLock::new(Vec::new());
+
+/// Returns the named subclasses registered for base class `ty`, if any.
+pub fn subclasses(py: Python, ty: &PyAny) -> Option<Vec<(String, PyObject)>> {
+  let table = SUBCLASSES.read().unwrap_or_else(|err| err.into_inner());
+  table.iter().find(|(item, _)| item.as_ptr() == ty.as_ptr()).map(|(_, subclasses)| subclasses.iter().map(|(name, subclass)| (name.clone(), subclass.clone_ref(py))).collect())
+}
+
+/// Registers named subclasses of `base`, a mapping of names to subclasses,
+/// e.g. `{'circle': Circle, 'square': Square}`.
+///
+/// A value of type `base` is serialized as `name{value}` by the subclass of
+/// which it is an instance, e.g. `circle{radius=2}`, and loaded as an instance
+/// of the subclass of the name. Subclasses registered for `base` before are
+/// kept unless their name is redefined.
+#[pyfunction]
+#[text_signature = "(base, subclasses, /)"]
+fn register_subclasses(py: Python, base: &PyAny, subclasses: &PyAny) -> PyResult<()> {
+  guard("stringly.register_subclasses", || {
+    let new = subclasses.call_method0("items")?.iter()?.map(|item| item?.extract()).collect::<PyResult<Vec<(String, &PyAny)>>>()?;
+    for (name, subclass) in &new {
+      if !py.import("builtins")?.call1("issubclass", (*subclass, base))?.is_true()? {
+        return Err(TypeError::py_err(format!("'{}' is not a subclass of {}", name, type_name(base)?)));
+      }
+    }
+    let mut table = SUBCLASSES.write().unwrap_or_else(|err| err.into_inner());
+    let index = match table.iter().position(|(item, _)| item.as_ptr() == base.as_ptr()) {
+      Some(index) => index,
+      None => {
+        table.push((base.into(), Vec::new()));
+        table.len() - 1
+      }
+    };
+    for (name, subclass) in new {
+      let subclasses = &mut table[index].1;
+      subclasses.retain(|(item, _)| *item != name);
+      subclasses.push((name, subclass.into()));
+    }
+    Ok(())
+  })
+}
+
+/// Default factories of `defaultdict` per value type, see
+/// `register_default_factory`.
+static DEFAULT_FACTORIES: RwLock<Vec<(PyObject, PyObject)>> = RwLock::new(Vec::new());
+
+/// Returns the default factory registered for value annotation `ty`, if any.
+pub fn default_factory(py: Python, ty: &PyAny) -> Option<PyObject> {
+  let table = DEFAULT_FACTORIES.read().unwrap_or_else(|err| err.into_inner());
+  table.iter().find(|(item, _)| item.as_ptr() == ty.as_ptr()).map(|(_, factory)| factory.clone_ref(py))
+}
+
+/// Registers `factory` as the default factory of a loaded
+/// `DefaultDict[key, type]`, e.g. `stringly.register_default_factory(Point,
+/// Point.origin)`.
+///
+/// Without a registered factory the value type is used if it is a class, or
+/// the origin of a generic value type, e.g. `list` for `List[int]`.
+#[pyfunction]
+#[text_signature = "(type, factory, /)"]
+fn register_default_factory(py: Python, ty: &PyAny, factory: &PyAny) -> PyResult<()> {
+  guard("stringly.register_default_factory", || {
+    if !py.import("builtins")?.call1("callable", (factory,))?.is_true()? {
+      return Err(TypeError::py_err(format!("default factory {} is not callable", factory.repr()?)));
+    }
+    let mut table = DEFAULT_FACTORIES.write().unwrap_or_else(|err| err.into_inner());
+    table.retain(|(item, _)| item.as_ptr() != ty.as_ptr());
+    table.push((ty.into(), factory.into()));
+    Ok(())
+  })
+}
+
+/// Context manager that registers an adapter on entering and restores the
+/// previous adapter of the type on exit, see `registered`.
+#[pyclass]
+struct Registration {
+  adapter: Adapter,
+  previous: Option<Option<Adapter>>,
+}
+
+#[pymethods]
+impl Registration {
+  fn __enter__(&mut self, py: Python) -> PyResult<()> {
+    guard("stringly.registered", || {
+      self.previous = Some(insert(self.adapter.clone_ref(py)));
+      Ok(())
+    })
+  }
+  #[args(_args = "*")]
+  fn __exit__(&mut self, py: Python, _args: &PyTuple) -> PyResult<bool> {
+    guard("stringly.registered", || {
+      match self.previous.take() {
+        Some(Some(previous)) => {
+          insert(previous);
+        }
+        Some(None) => {
+          remove(self.adapter.ty.as_ref(py));
+        }
+        None => (),
+      }
+      Ok(false)
+    })
+  }
+}
+
+/// Returns a context manager that temporarily registers `dumps` and `loads`
+/// for `type`, e.g. in tests: `with stringly.registered(Point, str, parse):`.
+#[pyfunction]
+#[text_signature = "(type, dumps, loads, /)"]
+fn registered(ty: &PyAny, dumps: &PyAny, loads: &PyAny) -> Registration {
+  Registration { adapter: Adapter { ty: ty.into(), dumps: dumps.into(), loads: loads.into(), subclasses: false }, previous: None }
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(register))?;
+  m.add_wrapped(wrap_pyfunction!(register_type))?;
+  m.add_wrapped(wrap_pyfunction!(unregister))?;
+  m.add_wrapped(wrap_pyfunction!(registered))?;
+  m.add_wrapped(wrap_pyfunction!(register_aliases))?;
+  m.add_wrapped(wrap_pyfunction!(register_subclasses))?;
+  m.add_wrapped(wrap_pyfunction!(alias))?;
+  m.add_wrapped(wrap_pyfunction!(register_default_factory))?;
+  m.add_class::<Registration>()?;
+
+  Ok(())
+}
@@ -0,0 +1,1101 @@
+use crate::options::{self, Options};
+use crate::doc::DocString;
+use crate::structs::{self, Struct};
+use crate::{expr, markers, numpy, pydantic, raw, registry, wrap_err, ImportFunctionError, SerializationError};
+use pyo3::class::basic::CompareOp;
+use pyo3::exceptions::{ArithmeticError, NameError, OverflowError, TypeError, ValueError};
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyList, PyTuple};
+use pyo3::{AsPyPointer, PyObject, PyResult, Python, ToPyObject};
+use serde_python_typing::Type;
+
+/// The (de)serialization strategy for a Python type annotation.
+///
+/// Annotations that are understood by `serde_python_typing` are handled by
+/// `Core`. All other schemas convert values from and to values of a *core*
+/// annotation: textual schemas (see `Text`) use `str`, containers use the
+/// container of the core annotations of their items.
+pub trait Schema {
+  /// Returns the core annotation.
+  fn core(&self, py: Python) -> PyResult<PyObject>;
+  /// Converts `value` to a value of the core annotation.
+  fn to_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject>;
+  /// Converts a value of the core annotation to a value.
+  fn from_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject>;
+  /// Returns a human readable description of the type.
+  fn describe(&self, py: Python) -> PyResult<String>;
+  /// Returns whether the schema is handled by `serde_python_typing` entirely.
+  fn is_core(&self) -> bool {
+    false
+  }
+  /// Returns the textual schema, if any.
+  fn as_text(&self) -> Option<&dyn Text> {
+    None
+  }
+  /// Returns the serialized values if the type has a finite set of values.
+  fn choices(&self, _py: Python) -> PyResult<Option<Vec<String>>> {
+    Ok(None)
+  }
+  /// Returns the struct schema, if any.
+  fn as_struct(&self) -> Option<&Struct> {
+    None
+  }
+  /// Returns the schema of the items, if the type is a list.
+  fn as_list(&self) -> Option<&dyn Schema> {
+    None
+  }
+  /// Returns the schema of the value, if the type is optional.
+  fn as_optional(&self) -> Option<&dyn Schema> {
+    None
+  }
+}
+
+/// A schema that serializes values to text by itself.
+pub trait Text {
+  /// Returns the serialized form of `value`.
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String>;
+  /// Returns the value of serialized string `s`.
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject>;
+  /// Returns a human readable description of the type.
+  fn describe(&self, py: Python) -> PyResult<String>;
+  /// Returns the serialized values if the type has a finite set of values.
+  fn choices(&self, _py: Python) -> PyResult<Option<Vec<String>>> {
+    Ok(None)
+  }
+  /// Returns the struct schema, if any.
+  fn as_struct(&self) -> Option<&Struct> {
+    None
+  }
+  /// Returns the schema of the value, if the type is optional.
+  fn as_optional(&self) -> Option<&dyn Schema> {
+    None
+  }
+}
+
+impl<T: Text> Schema for T {
+  fn core(&self, py: Python) -> PyResult<PyObject> {
+    Ok(py.import("builtins")?.getattr("str")?.into())
+  }
+  fn to_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    if let Some(text) = raw::text(value) {
+      return Ok(text.to_object(py));
+    }
+    Ok(self.dumps(py, value)?.to_object(py))
+  }
+  fn from_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    self.loads(py, value.extract()?)
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Text::describe(self, py)
+  }
+  fn as_text(&self) -> Option<&dyn Text> {
+    Some(self)
+  }
+  fn choices(&self, py: Python) -> PyResult<Option<Vec<String>>> {
+    Text::choices(self, py)
+  }
+  fn as_struct(&self) -> Option<&Struct> {
+    Text::as_struct(self)
+  }
+  fn as_optional(&self) -> Option<&dyn Schema> {
+    Text::as_optional(self)
+  }
+}
+
+/// Serializes `value` according to `schema`.
+///
+/// A `raw::Raw` value is embedded verbatim.
+pub fn dumps(py: Python, schema: &dyn Schema, value: &PyAny) -> PyResult<String> {
+  if let Some(text) = raw::text(value) {
+    return Ok(text);
+  }
+  if let Some(text) = schema.as_text() {
+    return text.dumps(py, value);
+  }
+  let core = schema.to_core(py, value)?;
+  wrap_err(Type::from_python(schema.core(py)?.as_ref(py))?.serialize(stringly::Serializer, core.as_ref(py)))
+}
+
+/// Deserializes `s` according to `schema`.
+pub fn loads(py: Python, schema: &dyn Schema, s: &str) -> PyResult<PyObject> {
+  if let Some(text) = schema.as_text() {
+    return text.loads(py, s);
+  }
+  let de = stringly::Deserializer::from_str(s);
+  let core = wrap_err(Type::from_python(schema.core(py)?.as_ref(py))?.deserialize(de, py))?;
+  schema.from_core(py, core.as_ref(py))
+}
+
+/// Returns the name of a Python type or the representation of an annotation.
+///
+/// A type with a short name registered by `stringly.alias` goes by that name.
+pub fn type_name(ty: &PyAny) -> PyResult<String> {
+  if let Some(name) = registry::type_alias(ty) {
+    return Ok(name);
+  }
+  match ty.getattr("__name__") {
+    Ok(name) if ty.getattr("__origin__").is_err() => name.extract(),
+    _ => Ok(ty.repr()?.to_string()),
+  }
+}
+
+/// Returns whether `ty` is a class and a subclass of `base`.
+fn is_subclass(py: Python, ty: &PyAny, base: &PyAny) -> PyResult<bool> {
+  let builtins = py.import("builtins")?;
+  Ok(builtins.call1("isinstance", (ty, builtins.getattr("type")?))?.is_true()? && builtins.call1("issubclass", (ty, base))?.is_true()?)
+}
+
+/// Returns whether `origin` is the origin of a union, `typing.Union` or, for
+/// the `X | Y` syntax of Python 3.10, `types.UnionType`.
+pub fn is_union(py: Python, origin: &PyAny) -> PyResult<bool> {
+  if origin.as_ptr() == py.import("typing")?.getattr("Union")?.as_ptr() {
+    return Ok(true);
+  }
+  Ok(py.import("types")?.getattr("UnionType").map_or(false, |union| origin.as_ptr() == union.as_ptr()))
+}
+
+/// Returns the annotation that string annotation or `typing.ForwardRef` `ty`
+/// refers to, or `ty` itself if it is neither.
+///
+/// The annotation is evaluated in the globals of the module of the forward
+/// reference if known, e.g. for `typing.ForwardRef('Node', module=...)`, and
+/// else in those of `__main__`. Forward references in class annotations are
+/// resolved with the globals of the module of the class by `get_type_hints`,
+/// see `structs::build`.
+fn resolve_forward_ref<'py>(py: Python<'py>, ty: &'py PyAny) -> PyResult<&'py PyAny> {
+  let typing = py.import("typing")?;
+  let builtins = py.import("builtins")?;
+  let arg = if builtins.call1("isinstance", (ty, builtins.getattr("str")?))?.is_true()? {
+    ty
+  } else if builtins.call1("isinstance", (ty, typing.getattr("ForwardRef")?))?.is_true()? {
+    ty.getattr("__forward_arg__")?
+  } else {
+    return Ok(ty);
+  };
+  let modules = py.import("sys")?.getattr("modules")?;
+  let module = match ty.getattr("__forward_module__") {
+    Ok(module) if !module.is_none() => modules.get_item(module)?,
+    _ => modules.get_item("__main__")?,
+  };
+  let globals = PyDict::new(py);
+  globals.call_method1("update", (typing.dict(),))?;
+  globals.call_method1("update", (module.getattr("__dict__")?,))?;
+  match builtins.call1("eval", (arg, globals)) {
+    Ok(resolved) => Ok(resolved),
+    Err(err) if err.is_instance::<NameError>(py) => Err(TypeError::py_err(format!("cannot resolve forward reference '{}': {}", arg, err.to_object(py).as_ref(py)))),
+    Err(err) => Err(err),
+  }
+}
+
+/// Builds the schema of Python type annotation `ty` with the options in effect
+/// in the current thread.
+pub fn build(py: Python, ty: &PyAny) -> PyResult<Box<dyn Schema>> {
+  build_with(py, ty, &options::current())
+}
+
+/// Builds the schema of Python type annotation `ty`.
+///
+/// Containers of which all items are core are left to `serde_python_typing`
+/// entirely. Types with value aliases, see `stringly.register_aliases`, are
+/// wrapped in a `registry::Aliased` schema. String annotations and forward
+/// references are resolved first.
+pub fn build_with(py: Python, ty: &PyAny, options: &Options) -> PyResult<Box<dyn Schema>> {
+  let ty = resolve_forward_ref(py, ty)?;
+  let schema = build_unaliased(py, ty, options)?;
+  match registry::aliases(ty) {
+    Some(aliases) => Ok(Box::new(registry::Aliased { aliases, schema })),
+    None => Ok(schema),
+  }
+}
+
+/// Builds the schema of Python type annotation `ty` without value aliases.
+fn build_unaliased(py: Python, ty: &PyAny, options: &Options) -> PyResult<Box<dyn Schema>> {
+  let typing = py.import("typing")?;
+  let builtins = py.import("builtins")?;
+  let none_type = builtins.getattr("type")?.call1((py.None(),))?;
+  let ellipsis = builtins.getattr("Ellipsis")?;
+  let origin = typing.call1("get_origin", (ty,))?;
+  let args: Vec<&PyAny> = typing.call1("get_args", (ty,))?.extract()?;
+  let is = |a: &PyAny, b: &PyAny| a.as_ptr() == b.as_ptr();
+  let mut stringified = is(ty, py.import("decimal")?.getattr("Decimal")?) || is(ty, py.import("uuid")?.getattr("UUID")?);
+  for name in &["Path", "PurePath", "PurePosixPath", "PureWindowsPath", "PosixPath", "WindowsPath"] {
+    stringified |= is(ty, py.import("pathlib")?.getattr(name)?);
+  }
+  for name in &["IPv4Address", "IPv6Address", "IPv4Network", "IPv6Network", "IPv4Interface", "IPv6Interface"] {
+    stringified |= is(ty, py.import("ipaddress")?.getattr(name)?);
+  }
+  let mut temporal = None;
+  for name in &["datetime", "date", "time", "timedelta"] {
+    if is(ty, py.import("datetime")?.getattr(name)?) {
+      temporal = Some(*name);
+    }
+  }
+  if let Some(adapter) = registry::lookup(py, ty) {
+    Ok(Box::new(adapter))
+  } else if let (false, Ok(supertype)) = (is_subclass(py, ty, builtins.getattr("object")?)?, ty.getattr("__supertype__")) {
+    // A `typing.NewType` is serialized as its underlying type, unless an
+    // adapter is registered for it.
+    build_with(py, supertype, options)
+  } else if let Some(subclasses) = registry::subclasses(py, ty) {
+    let mut variants = Vec::new();
+    for (tag, class) in subclasses {
+      let schema = build_with(py, class.as_ref(py), options)?;
+      variants.push(Variant { tag, class: Some(class), schema });
+    }
+    Ok(Box::new(Union { variants }))
+  } else if is_subclass(py, ty, builtins.getattr("object")?)? && ty.hasattr("__stringly_dumps__")? && ty.hasattr("__stringly_loads__")? {
+    Ok(Box::new(Protocol { ty: ty.into() }))
+  } else if is(origin, typing.getattr("Annotated")?) {
+    if let Some(index) = args[1..].iter().position(|item| markers::is_lazy(py, item)) {
+      let mut rest = args.clone();
+      rest.remove(index + 1);
+      let inner = if rest.len() == 1 { rest[0] } else { typing.getattr("Annotated")?.get_item(PyTuple::new(py, &rest))? };
+      return Ok(Box::new(markers::Deferred::new(inner, options)));
+    }
+    if let Some((index, base_dir)) = args[1..].iter().enumerate().find_map(|(index, item)| markers::from_file(py, item).map(|base_dir| (index, base_dir))) {
+      let mut rest = args.clone();
+      rest.remove(index + 1);
+      let inner = if rest.len() == 1 { rest[0] } else { typing.getattr("Annotated")?.get_item(PyTuple::new(py, &rest))? };
+      return Ok(Box::new(markers::FileContents::new(build_with(py, inner, options)?, base_dir.as_deref().or(options.base_dir.as_deref()))));
+    }
+    markers::annotate(py, args[0], &args[1..], options)
+  } else if is(ty, typing.getattr("Callable")?) || is(ty, py.import("collections.abc")?.getattr("Callable")?) || is(origin, py.import("collections.abc")?.getattr("Callable")?) {
+    Ok(Box::new(Callable {}))
+  } else if is(origin, typing.getattr("Literal")?) {
+    let mut values = Vec::new();
+    for arg in args {
+      values.push((dumps(py, build_with(py, arg.get_type(), options)?.as_ref(), arg)?, arg.into()));
+    }
+    Ok(Box::new(Literal { values }))
+  } else if is(origin, builtins.getattr("list")?) && args.len() == 1 {
+    let item = build_with(py, args[0], options)?;
+    if item.is_core() {
+      return Core::new(ty);
+    }
+    Ok(Box::new(List { item }))
+  } else if (is(origin, builtins.getattr("set")?) || is(origin, builtins.getattr("frozenset")?)) && args.len() == 1 {
+    Ok(Box::new(Set { item: build_with(py, args[0], options)?, class: origin.into() }))
+  } else if is(origin, builtins.getattr("tuple")?) && !args.iter().any(|arg| is(arg, ellipsis)) {
+    let items = args.iter().map(|arg| build_with(py, arg, options)).collect::<PyResult<Vec<_>>>()?;
+    if items.iter().all(|item| item.is_core()) {
+      return Core::new(ty);
+    }
+    Ok(Box::new(Tuple { items }))
+  } else if is(origin, builtins.getattr("tuple")?) && args.len() == 2 && is(args[1], ellipsis) {
+    Ok(Box::new(VarTuple { item: build_with(py, args[0], options)? }))
+  } else if is(origin, builtins.getattr("dict")?) && args.len() == 2 {
+    let (key, value) = (build_with(py, args[0], options)?, build_with(py, args[1], options)?);
+    let text_key = !is(args[0], builtins.getattr("str")?);
+    if !text_key && key.is_core() && value.is_core() && !options.sort_keys {
+      return Core::new(ty);
+    }
+    Ok(Box::new(Dict { key, value, sort_keys: options.sort_keys, text_key, container: None }))
+  } else if let Some(name) = ["OrderedDict", "defaultdict", "Counter"].iter().copied().find(|name| py.import("collections").and_then(|collections| collections.getattr(name)).map_or(false, |class| is(origin, class))) {
+    let value_ty = if name == "Counter" { builtins.getattr("int")? } else { args.get(1).copied().ok_or_else(|| TypeError::py_err(format!("{} requires a key and a value type", name)))? };
+    let key_ty = *args.get(0).ok_or_else(|| TypeError::py_err(format!("{} requires a key type", name)))?;
+    let factory = if name == "defaultdict" {
+      match registry::default_factory(py, value_ty) {
+        Some(factory) => Some(factory),
+        None if !typing.call1("get_origin", (value_ty,))?.is_none() => Some(typing.call1("get_origin", (value_ty,))?.into()),
+        None if builtins.call1("isinstance", (value_ty, builtins.getattr("type")?))?.is_true()? => Some(value_ty.into()),
+        None => return Err(TypeError::py_err(format!("no default factory for {}, see stringly.register_default_factory", type_name(value_ty)?))),
+      }
+    } else {
+      None
+    };
+    let container = Container { class: origin.into(), name, factory };
+    Ok(Box::new(Dict { key: build_with(py, key_ty, options)?, value: build_with(py, value_ty, options)?, sort_keys: options.sort_keys && name != "OrderedDict", text_key: !is(key_ty, builtins.getattr("str")?), container: Some(container) }))
+  } else if is_union(py, origin)? && args.len() == 2 && args.iter().any(|arg| is(arg, none_type)) {
+    Ok(Box::new(Optional { item: build_with(py, if is(args[1], none_type) { args[0] } else { args[1] }, options)? }))
+  } else if is_union(py, origin)? {
+    let mut variants = Vec::new();
+    for arg in args.iter().copied().filter(|arg| !is(arg, none_type)) {
+      let schema = build_with(py, arg, options)?;
+      let tag = schema.describe(py)?;
+      if variants.iter().any(|variant: &Variant| variant.tag == tag) {
+        return Err(SerializationError::py_err(format!("variants of {} have the same tag '{}'", type_name(ty)?, tag)));
+      }
+      let class = if is(typing.call1("get_origin", (arg,))?, typing.getattr("Annotated")?) { typing.call1("get_args", (arg,))?.get_item(0)? } else { arg };
+      let class = match typing.call1("get_origin", (class,))? {
+        origin if origin.is_none() => class,
+        origin => origin,
+      };
+      let class = if builtins.call1("isinstance", (class, builtins.getattr("type")?))?.is_true()? { Some(class.into()) } else { None };
+      variants.push(Variant { tag, class, schema });
+    }
+    let union = Box::new(Union { variants });
+    if args.iter().any(|arg| is(arg, none_type)) {
+      Ok(Box::new(Optional { item: union }))
+    } else {
+      Ok(union)
+    }
+  } else if options.tolerant && is(ty, builtins.getattr("bool")?) {
+    Ok(Box::new(TolerantBool { core: Core::new(ty)? }))
+  } else if is(ty, builtins.getattr("int")?) || is(ty, builtins.getattr("float")?) {
+    let mut schema = Core::new(ty)?;
+    if let (Some(spec), false) = (&options.float_format, is(ty, builtins.getattr("int")?)) {
+      schema = Box::new(markers::Formatted::new(py, ty, spec)?);
+    }
+    if options.allow_expressions || options.constants.is_some() {
+      schema = Box::new(Expression { is_int: is(ty, builtins.getattr("int")?), allow_expressions: options.allow_expressions, constants: options.constants.clone().unwrap_or_default(), core: schema });
+    }
+    Ok(schema)
+  } else if is_subclass(py, ty, py.import("enum")?.getattr("Enum")?)? {
+    enumeration(py, ty, ty.getattr("__stringly_by_value__").map_or(Ok(false), |by_value| by_value.is_true())?)
+  } else if markers::Encoded::supports(py, ty)? {
+    Ok(Box::new(markers::Encoded::new(py, ty, options.bytes_encoding.as_deref().unwrap_or("hex"))?))
+  } else if is(ty, py.import("re")?.getattr("Pattern")?) || is(origin, py.import("re")?.getattr("Pattern")?) {
+    Ok(Box::new(Pattern { re: py.import("re")?.into() }))
+  } else if stringified {
+    Ok(Box::new(Stringified { ty: ty.into() }))
+  } else if let Some(name) = temporal {
+    Ok(Box::new(Temporal { ty: ty.into(), name }))
+  } else if let Some(schema) = structs::build(py, ty, options)? {
+    Ok(schema)
+  } else if let Some(schema) = pydantic::build(py, ty, options)? {
+    Ok(schema)
+  } else if let Some(schema) = numpy::build(py, ty, options)? {
+    Ok(schema)
+  } else {
+    Core::new(ty)
+  }
+}
+
+/// A schema handled by `serde_python_typing`.
+pub struct Core {
+  ty: PyObject,
+}
+
+impl Core {
+  fn new(ty: &PyAny) -> PyResult<Box<dyn Schema>> {
+    // Validate the annotation early.
+    Type::from_python(ty)?;
+    Ok(Box::new(Core { ty: ty.into() }))
+  }
+}
+
+impl Schema for Core {
+  fn core(&self, py: Python) -> PyResult<PyObject> {
+    Ok(self.ty.clone_ref(py))
+  }
+  fn to_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    Ok(value.to_object(py))
+  }
+  fn from_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    Ok(value.to_object(py))
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(Type::from_python(self.ty.as_ref(py))?.to_string())
+  }
+  fn is_core(&self) -> bool {
+    true
+  }
+  fn choices(&self, py: Python) -> PyResult<Option<Vec<String>>> {
+    if self.ty.as_ref(py).as_ptr() != py.import("builtins")?.getattr("bool")?.as_ptr() {
+      return Ok(None);
+    }
+    let mut choices = Vec::new();
+    for value in &[true, false] {
+      choices.push(dumps(py, self, value.to_object(py).as_ref(py))?);
+    }
+    Ok(Some(choices))
+  }
+}
+
+/// A `typing.List` of items that are not all core.
+struct List {
+  item: Box<dyn Schema>,
+}
+
+impl Schema for List {
+  fn core(&self, py: Python) -> PyResult<PyObject> {
+    Ok(py.import("typing")?.getattr("List")?.get_item(self.item.core(py)?)?.into())
+  }
+  fn to_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let items = value.iter()?.map(|item| self.item.to_core(py, item?)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyList::new(py, items).into())
+  }
+  fn from_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let items = value.iter()?.map(|item| self.item.from_core(py, item?)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyList::new(py, items).into())
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("List[{}]", self.item.describe(py)?))
+  }
+  fn as_list(&self) -> Option<&dyn Schema> {
+    Some(self.item.as_ref())
+  }
+}
+
+/// A `typing.Set` or `typing.FrozenSet`, serialized as a list of which the
+/// items are sorted by their serialized form.
+struct Set {
+  item: Box<dyn Schema>,
+  /// The container class, `set` or `frozenset`.
+  class: PyObject,
+}
+
+impl Schema for Set {
+  fn core(&self, py: Python) -> PyResult<PyObject> {
+    Ok(py.import("typing")?.getattr("List")?.get_item(self.item.core(py)?)?.into())
+  }
+  fn to_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let items = value.iter()?.collect::<PyResult<Vec<_>>>()?;
+    let mut keyed = items.into_iter().map(|item| Ok((dumps(py, self.item.as_ref(), item)?, item))).collect::<PyResult<Vec<_>>>()?;
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    let items = keyed.into_iter().map(|(_, item)| self.item.to_core(py, item)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyList::new(py, items).into())
+  }
+  fn from_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let items = value.iter()?.map(|item| self.item.from_core(py, item?)).collect::<PyResult<Vec<_>>>()?;
+    Ok(self.class.as_ref(py).call1((PyList::new(py, items),))?.into())
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    let name = if type_name(self.class.as_ref(py))? == "frozenset" { "FrozenSet" } else { "Set" };
+    Ok(format!("{}[{}]", name, self.item.describe(py)?))
+  }
+}
+
+/// A fixed length `typing.Tuple` of items that are not all core.
+struct Tuple {
+  items: Vec<Box<dyn Schema>>,
+}
+
+impl Schema for Tuple {
+  fn core(&self, py: Python) -> PyResult<PyObject> {
+    let items = self.items.iter().map(|item| item.core(py)).collect::<PyResult<Vec<_>>>()?;
+    Ok(py.import("typing")?.getattr("Tuple")?.get_item(PyTuple::new(py, items))?.into())
+  }
+  fn to_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let values = value.iter()?.collect::<PyResult<Vec<_>>>()?;
+    if values.len() != self.items.len() {
+      return Err(SerializationError::py_err(format!("expected a tuple of length {} but got length {}", self.items.len(), values.len())));
+    }
+    let items = self.items.iter().zip(values).map(|(item, value)| item.to_core(py, value)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyTuple::new(py, items).into())
+  }
+  fn from_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let values = value.iter()?.collect::<PyResult<Vec<_>>>()?;
+    let items = self.items.iter().zip(values).map(|(item, value)| item.from_core(py, value)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyTuple::new(py, items).into())
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Tuple[{}]", self.items.iter().map(|item| item.describe(py)).collect::<PyResult<Vec<_>>>()?.join(", ")))
+  }
+}
+
+/// A variable length `typing.Tuple[T, ...]`, serialized as a list and loaded
+/// as a tuple.
+struct VarTuple {
+  item: Box<dyn Schema>,
+}
+
+impl Schema for VarTuple {
+  fn core(&self, py: Python) -> PyResult<PyObject> {
+    Ok(py.import("typing")?.getattr("List")?.get_item(self.item.core(py)?)?.into())
+  }
+  fn to_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let items = value.iter()?.map(|item| self.item.to_core(py, item?)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyList::new(py, items).into())
+  }
+  fn from_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let items = value.iter()?.map(|item| self.item.from_core(py, item?)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyTuple::new(py, items).into())
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Tuple[{}, ...]", self.item.describe(py)?))
+  }
+}
+
+/// The class of a mapping other than `dict` of which loaded values are
+/// instances.
+struct Container {
+  /// One of `collections.OrderedDict`, `collections.defaultdict` and
+  /// `collections.Counter`.
+  class: PyObject,
+  name: &'static str,
+  /// The default factory of a `defaultdict`.
+  factory: Option<PyObject>,
+}
+
+/// A `typing.Dict` with a key that is not a string, a key or value that is
+/// not core, or of which the items are sorted by key, or a mapping of
+/// `collections` that is loaded as an instance of its own class.
+///
+/// The items of an `OrderedDict` are never sorted; a `Counter` has `int`
+/// values.
+struct Dict {
+  key: Box<dyn Schema>,
+  value: Box<dyn Schema>,
+  sort_keys: bool,
+  /// Whether keys are converted to strings by their schema, so that keys of
+  /// any type, e.g. `int`, an enum or a tuple, are supported.
+  text_key: bool,
+  container: Option<Container>,
+}
+
+impl Dict {
+  fn key_to_core(&self, py: Python, key: &PyAny) -> PyResult<PyObject> {
+    if self.text_key {
+      Ok(dumps(py, self.key.as_ref(), key)?.to_object(py))
+    } else {
+      self.key.to_core(py, key)
+    }
+  }
+  fn key_from_core(&self, py: Python, key: &PyAny) -> PyResult<PyObject> {
+    if self.text_key {
+      loads(py, self.key.as_ref(), key.extract()?)
+    } else {
+      self.key.from_core(py, key)
+    }
+  }
+}
+
+impl Schema for Dict {
+  fn core(&self, py: Python) -> PyResult<PyObject> {
+    let key = if self.text_key { py.import("builtins")?.getattr("str")?.into() } else { self.key.core(py)? };
+    Ok(py.import("typing")?.getattr("Dict")?.get_item(PyTuple::new(py, &[key, self.value.core(py)?]))?.into())
+  }
+  fn to_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let mut items = value.call_method0("items")?.iter()?.map(|item| item?.extract()).collect::<PyResult<Vec<(&PyAny, &PyAny)>>>()?;
+    if self.sort_keys {
+      let mut keyed = items.into_iter().map(|(key, value)| Ok((dumps(py, self.key.as_ref(), key)?, key, value))).collect::<PyResult<Vec<_>>>()?;
+      keyed.sort_by(|a, b| a.0.cmp(&b.0));
+      items = keyed.into_iter().map(|(_, key, value)| (key, value)).collect();
+    }
+    let dict = PyDict::new(py);
+    for (key, value) in items {
+      dict.set_item(self.key_to_core(py, key)?, self.value.to_core(py, value)?)?;
+    }
+    Ok(dict.into())
+  }
+  fn from_core(&self, py: Python, value: &PyAny) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    for (key, value) in value.extract::<&PyDict>()?.iter() {
+      dict.set_item(self.key_from_core(py, key)?, self.value.from_core(py, value)?)?;
+    }
+    match &self.container {
+      Some(Container { class, factory: Some(factory), .. }) => Ok(class.as_ref(py).call1((factory.clone_ref(py), dict))?.into()),
+      Some(Container { class, .. }) => Ok(class.as_ref(py).call1((dict,))?.into()),
+      None => Ok(dict.into()),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    match &self.container {
+      Some(Container { name: "Counter", .. }) => Ok(format!("Counter[{}]", self.key.describe(py)?)),
+      Some(Container { name: "defaultdict", .. }) => Ok(format!("DefaultDict[{}, {}]", self.key.describe(py)?, self.value.describe(py)?)),
+      Some(Container { name, .. }) => Ok(format!("{}[{}, {}]", name, self.key.describe(py)?, self.value.describe(py)?)),
+      None => Ok(format!("Dict[{}, {}]", self.key.describe(py)?, self.value.describe(py)?)),
+    }
+  }
+}
+
+/// A `typing.Optional`.
+///
+/// `None` is serialized as the empty string. A value of the item that is
+/// serialized as the empty string as well, for example an empty `str` or
+/// `list`, cannot be distinguished from `None` and is rejected.
+struct Optional {
+  item: Box<dyn Schema>,
+}
+
+impl Text for Optional {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    if value.is_none() {
+      return Ok(String::new());
+    }
+    let s = dumps(py, self.item.as_ref(), value)?;
+    if s.is_empty() {
+      return Err(SerializationError::py_err(format!("{} cannot be serialized as {} because its serialized form is empty, which denotes None", value.repr()?, Text::describe(self, py)?)));
+    }
+    Ok(s)
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    if s.is_empty() {
+      Ok(py.None())
+    } else {
+      loads(py, self.item.as_ref(), s)
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    Ok(format!("Optional[{}]", self.item.describe(py)?))
+  }
+  fn as_optional(&self) -> Option<&dyn Schema> {
+    Some(self.item.as_ref())
+  }
+}
+
+/// A `typing.Literal`, serialized as the serialized form of the literal.
+struct Literal {
+  values: Vec<(String, PyObject)>,
+}
+
+impl Literal {
+  /// Returns the permitted serialized values as a comma separated list.
+  fn permitted(&self) -> String {
+    self.values.iter().map(|(s, _)| s.as_str()).collect::<Vec<_>>().join(", ")
+  }
+}
+
+impl Text for Literal {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    for (s, literal) in &self.values {
+      let literal = literal.as_ref(py);
+      if value.get_type().as_ptr() == literal.get_type().as_ptr() && value.rich_compare(literal, CompareOp::Eq)?.is_true()? {
+        return Ok(s.clone());
+      }
+    }
+    Err(SerializationError::py_err(format!("{} is not permitted, expected one of: {}", value.repr()?, self.permitted())))
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    match self.values.iter().find(|(value, _)| value == s.trim()) {
+      Some((_, literal)) => Ok(literal.clone_ref(py)),
+      None => Err(SerializationError::py_err(format!("'{}' is not permitted, expected one of: {}", s, self.permitted()))),
+    }
+  }
+  fn describe(&self, _py: Python) -> PyResult<String> {
+    Ok(format!("Literal[{}]", self.permitted()))
+  }
+}
+
+/// A variant of a `Union`.
+struct Variant {
+  tag: String,
+  /// The class of which values of the variant are instances, if known.
+  class: Option<PyObject>,
+  schema: Box<dyn Schema>,
+}
+
+/// A `typing.Union` of types other than `None`.
+///
+/// Values are serialized as `tag{value}`, where the tag is the description of
+/// the variant, for example `int{3}` or `str{3}`. A value is serialized by the
+/// first variant of which its type is the class, else the first variant of
+/// which it is an instance, else the first variant that serializes it.
+///
+/// A base class with subclasses registered by `stringly.register_subclasses`
+/// is a union of the subclasses, tagged by their registered names.
+///
+/// If a value fails to load because of its tag, the error message lists the
+/// first line of the docstring of the class of each variant, if any.
+struct Union {
+  variants: Vec<Variant>,
+}
+
+impl Union {
+  /// Returns the message suffix that describes the variants by the first line
+  /// of their docstrings, or an empty string if no variant has a docstring.
+  ///
+  /// Docstrings of builtin classes and those generated for dataclasses, which
+  /// repeat the signature, are skipped.
+  fn variant_docs(&self, py: Python) -> PyResult<String> {
+    let mut docs = Vec::new();
+    for variant in &self.variants {
+      let class = match &variant.class {
+        Some(class) => class.as_ref(py),
+        None => continue,
+      };
+      if class.getattr("__module__")?.extract::<&str>().ok() == Some("builtins") || class.getattr("__doc__")?.is_none() {
+        continue;
+      }
+      let text = DocString::new(class)?.text;
+      let line = text.lines().next().unwrap_or("").trim().trim_end_matches('.');
+      let generated = format!("{}(", class.getattr("__name__")?.extract::<&str>()?);
+      if !line.is_empty() && !line.starts_with(&generated) {
+        docs.push(format!("{} ({})", variant.tag, line));
+      }
+    }
+    Ok(if docs.is_empty() { String::new() } else { format!("; variants are: {}", docs.join(", ")) })
+  }
+}
+
+impl Text for Union {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    let builtins = py.import("builtins")?;
+    let exact = |variant: &&Variant| variant.class.as_ref().map_or(false, |class| value.get_type().as_ptr() == class.as_ptr());
+    let instance = |variant: &&Variant| -> bool { variant.class.as_ref().map_or(false, |class| builtins.call1("isinstance", (value, class)).and_then(|result| result.is_true()).unwrap_or(false)) };
+    let candidates = self.variants.iter().filter(exact).chain(self.variants.iter().filter(instance)).chain(self.variants.iter().filter(|variant| variant.class.is_none()));
+    for variant in candidates {
+      if let Ok(s) = dumps(py, variant.schema.as_ref(), value) {
+        return Ok(format!("{}{{{}}}", variant.tag, s));
+      }
+    }
+    Err(SerializationError::py_err(format!("{} is not a value of any variant of {}", value.repr()?, Text::describe(self, py)?)))
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let tags = || self.variants.iter().map(|variant| variant.tag.as_str()).collect::<Vec<_>>().join(", ");
+    let (tag, value) = match (s.find('{'), s.ends_with('}')) {
+      (Some(index), true) => (s[..index].trim(), &s[index + 1..s.len() - 1]),
+      _ => return Err(SerializationError::py_err(format!("expected tag{{value}} with one of the tags {} but got '{}'{}", tags(), s, self.variant_docs(py)?))),
+    };
+    match self.variants.iter().find(|variant| variant.tag == tag) {
+      Some(variant) => loads(py, variant.schema.as_ref(), value),
+      None => Err(SerializationError::py_err(format!("unknown tag '{}', expected one of: {}{}", tag, tags(), self.variant_docs(py)?))),
+    }
+  }
+  fn describe(&self, _py: Python) -> PyResult<String> {
+    Ok(format!("Union[{}]", self.variants.iter().map(|variant| variant.tag.as_str()).collect::<Vec<_>>().join(", ")))
+  }
+}
+
+/// An `int` or `float` that accepts named constants or constant arithmetic
+/// expressions in addition to the values of its `core` schema.
+struct Expression {
+  is_int: bool,
+  allow_expressions: bool,
+  constants: Vec<(String, f64)>,
+  core: Box<dyn Schema>,
+}
+
+impl Text for Expression {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    dumps(py, self.core.as_ref(), value)
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let err = match loads(py, self.core.as_ref(), s) {
+      Ok(value) => return Ok(value),
+      Err(err) => err,
+    };
+    let value = match self.constants.iter().find(|(name, _)| name == s.trim()) {
+      Some((_, value)) => *value,
+      None if !self.allow_expressions => return Err(err),
+      None => match expr::evaluate(s, &self.constants) {
+        Ok(value) => value,
+        Err(_) if !s.trim_start_matches(|c| c == '+' || c == '-').contains(|c| "+-*/()".contains(c)) => return Err(err),
+        Err(message) => return Err(SerializationError::py_err(format!("invalid expression '{}': {}", s, message))),
+      },
+    };
+    if !self.is_int {
+      Ok(value.to_object(py))
+    } else if value.fract() == 0. && value.abs() < 2f64.powi(63) {
+      Ok((value as i64).to_object(py))
+    } else {
+      Err(SerializationError::py_err(format!("'{}' does not evaluate to an integer", s)))
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    self.core.describe(py)
+  }
+}
+
+/// A `datetime`, `date` or `time`, serialized in ISO 8601 format, or a
+/// `timedelta`, serialized as a duration like `1h30m`.
+struct Temporal {
+  ty: PyObject,
+  name: &'static str,
+}
+
+impl Text for Temporal {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    if !py.import("builtins")?.call1("isinstance", (value, self.ty.as_ref(py)))?.is_true()? {
+      return Err(SerializationError::py_err(format!("expected a {} but got {}", self.name, value.repr()?)));
+    }
+    if self.name != "timedelta" {
+      return value.call_method0("isoformat")?.extract();
+    }
+    Ok(markers::format_duration(markers::timedelta_microseconds(value)?))
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    if self.name != "timedelta" {
+      return match self.ty.as_ref(py).call_method1("fromisoformat", (s.trim(),)) {
+        Ok(value) => Ok(value.into()),
+        Err(_) => Err(SerializationError::py_err(format!("expected an ISO 8601 {} but got '{}'", self.name, s))),
+      };
+    }
+    match markers::parse_duration(s) {
+      Some(microseconds) => match self.ty.as_ref(py).call((), Some([("microseconds", microseconds.round())].into_py_dict(py))) {
+        Ok(value) => Ok(value.into()),
+        Err(err) if err.is_instance::<OverflowError>(py) => Err(SerializationError::py_err(format!("duration '{}' is out of range", s))),
+        Err(err) => Err(err),
+      },
+      None => Err(SerializationError::py_err(format!("expected a duration like '1h30m' or '250ms' but got '{}'", s))),
+    }
+  }
+  fn describe(&self, _py: Python) -> PyResult<String> {
+    Ok(self.name.to_string())
+  }
+}
+
+/// A type of which values are serialized as `str(value)` and deserialized by
+/// calling the type with the string: `decimal.Decimal`, which preserves the
+/// exponent and scale exactly, `uuid.UUID`, in the canonical hyphenated form,
+/// the classes of `pathlib` and the addresses, networks and interfaces of
+/// `ipaddress`, e.g. `192.168.0.0/24`, in their compressed form.
+struct Stringified {
+  ty: PyObject,
+}
+
+impl Text for Stringified {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    if !py.import("builtins")?.call1("isinstance", (value, self.ty.as_ref(py)))?.is_true()? {
+      return Err(SerializationError::py_err(format!("expected a {} but got {}", type_name(self.ty.as_ref(py))?, value.repr()?)));
+    }
+    Ok(value.str()?.to_str()?.to_string())
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    match self.ty.as_ref(py).call1((s,)) {
+      Ok(value) => Ok(value.into()),
+      Err(err) if err.is_instance::<ValueError>(py) || err.is_instance::<ArithmeticError>(py) => Err(SerializationError::py_err(format!("invalid {} '{}'", type_name(self.ty.as_ref(py))?, s))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    type_name(self.ty.as_ref(py))
+  }
+}
+
+/// The flags of `re` and their letters in the inline flag syntax.
+const PATTERN_FLAGS: [(&str, char); 6] = [("ASCII", 'a'), ("IGNORECASE", 'i'), ("LOCALE", 'L'), ("MULTILINE", 'm'), ("DOTALL", 's'), ("VERBOSE", 'x')];
+
+/// A compiled `re.Pattern` of a `str`, serialized as the source of the
+/// pattern if it has no flags, e.g. `[a-z]+`, or otherwise as the source in
+/// braces followed by the letters of the flags, e.g. `{[a-z]+}im`.
+///
+/// A source that itself starts with a brace is always wrapped, so that the
+/// suffix is unambiguous.
+struct Pattern {
+  re: PyObject,
+}
+
+impl Text for Pattern {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    let re = self.re.as_ref(py);
+    if !py.import("builtins")?.call1("isinstance", (value, re.getattr("Pattern")?))?.is_true()? {
+      return Err(SerializationError::py_err(format!("expected a Pattern but got {}", value.repr()?)));
+    }
+    let source: &str = value.getattr("pattern")?.extract().map_err(|_| SerializationError::py_err(format!("expected a pattern of str but got {}", value.repr()?)))?;
+    let flags: i64 = value.getattr("flags")?.extract()?;
+    let mut suffix = String::new();
+    for (name, letter) in &PATTERN_FLAGS {
+      if flags & re.getattr(name)?.extract::<i64>()? != 0 {
+        suffix.push(*letter);
+      }
+    }
+    if suffix.is_empty() && !source.starts_with('{') {
+      Ok(source.to_string())
+    } else {
+      Ok(format!("{{{}}}{}", source, suffix))
+    }
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let re = self.re.as_ref(py);
+    let (source, suffix) = match (s.starts_with('{'), s.rfind('}')) {
+      (true, Some(index)) if s[index + 1..].chars().all(|c| PATTERN_FLAGS.iter().any(|(_, letter)| *letter == c)) => (&s[1..index], &s[index + 1..]),
+      _ => (s, ""),
+    };
+    let mut flags = 0;
+    for (name, letter) in &PATTERN_FLAGS {
+      if suffix.contains(*letter) {
+        flags |= re.getattr(name)?.extract::<i64>()?;
+      }
+    }
+    match re.call_method1("compile", (source, flags)) {
+      Ok(pattern) => Ok(pattern.into()),
+      Err(err) if err.matches(py, re.getattr("error")?) => Err(SerializationError::py_err(format!("invalid pattern '{}': {}", source, err.to_object(py).as_ref(py)))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, _py: Python) -> PyResult<String> {
+    Ok("Pattern".to_string())
+  }
+}
+
+/// A class that serializes its values itself by the classmethods
+/// `__stringly_dumps__(value)`, returning a string, and
+/// `__stringly_loads__(s)`.
+///
+/// The protocol takes precedence over the structural inspection of the
+/// class, e.g. of a dataclass, but not over an adapter registered for it. A
+/// `ValueError` raised by `__stringly_loads__` is reported as
+/// `SerializationError`.
+struct Protocol {
+  ty: PyObject,
+}
+
+impl Text for Protocol {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    self.ty.as_ref(py).call_method1("__stringly_dumps__", (value,))?.extract()
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    match self.ty.as_ref(py).call_method1("__stringly_loads__", (s,)) {
+      Ok(value) => Ok(value.into()),
+      Err(err) if err.is_instance::<ValueError>(py) => Err(SerializationError::py_err(format!("invalid {} '{}': {}", type_name(self.ty.as_ref(py))?, s, err.to_object(py).as_ref(py)))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    type_name(self.ty.as_ref(py))
+  }
+}
+
+/// A `typing.Callable`, serialized as the import path `module:qualname` of a
+/// function or class, e.g. `scipy.optimize:newton`.
+///
+/// Only callables that can be imported by their path can be dumped; a path
+/// that cannot be imported raises `ImportFunctionError` on load.
+struct Callable {}
+
+/// Returns the callable of import path `module:qualname`.
+fn import_callable<'py>(py: Python<'py>, path: &str) -> PyResult<&'py PyAny> {
+  let (module, qualname) = match path.find(':') {
+    Some(index) => (&path[..index], &path[index + 1..]),
+    None => return Err(ImportFunctionError::py_err(format!("expected an import path 'module:qualname' but got '{}'", path))),
+  };
+  let mut value: &PyAny = match py.import(module) {
+    Ok(module) => module,
+    Err(err) => return Err(ImportFunctionError::py_err(format!("cannot import module '{}': {}", module, err.to_object(py).as_ref(py)))),
+  };
+  for attr in qualname.split('.') {
+    value = match value.getattr(attr) {
+      Ok(value) => value,
+      Err(_) => return Err(ImportFunctionError::py_err(format!("module '{}' has no attribute '{}'", module, qualname))),
+    };
+  }
+  if !py.import("builtins")?.call1("callable", (value,))?.is_true()? {
+    return Err(ImportFunctionError::py_err(format!("'{}' is not callable", path)));
+  }
+  Ok(value)
+}
+
+impl Text for Callable {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    let path = match (value.getattr("__module__").and_then(|module| module.extract::<String>()), value.getattr("__qualname__").and_then(|qualname| qualname.extract::<String>())) {
+      (Ok(module), Ok(qualname)) => format!("{}:{}", module, qualname),
+      _ => return Err(SerializationError::py_err(format!("cannot serialize {} without a module and qualified name", value.repr()?))),
+    };
+    match import_callable(py, &path) {
+      Ok(imported) if imported.as_ptr() == value.as_ptr() => Ok(path),
+      _ => Err(SerializationError::py_err(format!("cannot serialize {}: it cannot be imported as '{}'", value.repr()?, path))),
+    }
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    Ok(import_callable(py, s.trim())?.into())
+  }
+  fn describe(&self, _py: Python) -> PyResult<String> {
+    Ok("Callable".to_string())
+  }
+}
+
+/// A `bool` that also accepts `yes`, `on`, `1` and their negations, in any
+/// case.
+struct TolerantBool {
+  core: Box<dyn Schema>,
+}
+
+impl Text for TolerantBool {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    dumps(py, self.core.as_ref(), value)
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    match s.trim().to_lowercase().as_str() {
+      "true" | "yes" | "y" | "on" | "1" => Ok(true.to_object(py)),
+      "false" | "no" | "n" | "off" | "0" => Ok(false.to_object(py)),
+      _ => loads(py, self.core.as_ref(), s),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    self.core.describe(py)
+  }
+  fn choices(&self, py: Python) -> PyResult<Option<Vec<String>>> {
+    self.core.choices(py)
+  }
+}
+
+/// Returns the schema of `enum.Enum` subclass `ty`, serialized by member value
+/// instead of name if `by_value` is true.
+///
+/// Serialization by value is selected per type by the class attribute
+/// `__stringly_by_value__ = True` or per annotation by the `ByValue` marker,
+/// and is supported for enumerations with `int` or `str` values, such as
+/// `IntEnum` and `StrEnum`, but not for flags.
+pub fn enumeration(py: Python, ty: &PyAny, by_value: bool) -> PyResult<Box<dyn Schema>> {
+  let flag = is_subclass(py, ty, py.import("enum")?.getattr("Flag")?)?;
+  if by_value && flag {
+    return Err(TypeError::py_err(format!("serialization by value is not supported for flag {}", type_name(ty)?)));
+  }
+  Ok(Box::new(Enum { ty: ty.into(), flag, by_value }))
+}
+
+/// An `enum.Enum` subclass, serialized by member name.
+///
+/// Values of an `enum.Flag` subclass are serialized as the comma separated
+/// names of the single bit members they combine. If `by_value` is true,
+/// members are serialized by their `int` or `str` value and loaded from either
+/// their value or their name, for legacy strings with numeric codes.
+struct Enum {
+  ty: PyObject,
+  flag: bool,
+  by_value: bool,
+}
+
+impl Enum {
+  /// Returns the text of the value of `member`, see `by_value`.
+  fn value_text(&self, member: &PyAny) -> PyResult<String> {
+    let value = member.getattr("value")?;
+    match (value.extract::<String>(), value.extract::<i64>()) {
+      (Ok(s), _) => Ok(s),
+      (_, Ok(i)) => Ok(i.to_string()),
+      _ => Err(SerializationError::py_err(format!("cannot serialize {} by value: expected an int or str value but got {}", member.repr()?, value.repr()?))),
+    }
+  }
+  /// Returns the names of the members, excluding aliases.
+  fn names(&self, py: Python) -> PyResult<Vec<String>> {
+    self.ty.as_ref(py).iter()?.map(|member| member?.getattr("name")?.extract()).collect()
+  }
+  /// Returns the member with name `name`.
+  fn member<'py>(&self, py: Python<'py>, name: &str) -> PyResult<&'py PyAny> {
+    match self.ty.as_ref(py).getattr("__members__")?.get_item(name.trim()) {
+      Ok(member) => Ok(member),
+      Err(_) => Err(SerializationError::py_err(format!("unknown member '{}' of {}, expected one of: {}", name, type_name(self.ty.as_ref(py))?, self.names(py)?.join(", ")))),
+    }
+  }
+}
+
+impl Text for Enum {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    if !py.import("builtins")?.call1("isinstance", (value, self.ty.as_ref(py)))?.is_true()? {
+      return Err(SerializationError::py_err(format!("expected a member of {} but got {}", type_name(self.ty.as_ref(py))?, value.repr()?)));
+    }
+    if self.by_value {
+      return self.value_text(value);
+    }
+    if !self.flag {
+      return value.getattr("name")?.extract();
+    }
+    let bits: u64 = value.getattr("value")?.extract()?;
+    let mut names = Vec::new();
+    let mut covered = 0;
+    for member in self.ty.as_ref(py).getattr("__members__")?.call_method0("values")?.iter()? {
+      let member = member?;
+      let bit: u64 = member.getattr("value")?.extract()?;
+      if bit != 0 && bit & (bit - 1) == 0 && bits & bit == bit && covered & bit == 0 {
+        names.push(member.getattr("name")?.extract::<String>()?);
+        covered |= bit;
+      }
+    }
+    if covered != bits {
+      return Err(SerializationError::py_err(format!("{} is not a combination of named members", value.repr()?)));
+    }
+    Ok(names.join(","))
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    if self.by_value {
+      for member in self.ty.as_ref(py).iter()? {
+        let member = member?;
+        if self.value_text(member).ok().as_deref() == Some(s.trim()) {
+          return Ok(member.into());
+        }
+      }
+    }
+    if !self.flag {
+      return Ok(self.member(py, s)?.into());
+    }
+    let mut value = self.ty.as_ref(py).call1((0,))?;
+    for name in stringly::util::safesplit(s, ',').filter(|name| !name.trim().is_empty()) {
+      value = value.call_method1("__or__", (self.member(py, name)?,))?;
+    }
+    Ok(value.into())
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    type_name(self.ty.as_ref(py))
+  }
+}
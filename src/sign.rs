@@ -0,0 +1,44 @@
+use crate::file::deprettify;
+use crate::SerializationError;
+use pyo3::types::{PyAny, PyBytes};
+use pyo3::{PyResult, Python};
+
+const FOOTER: &str = "# hmac-sha256: ";
+
+/// Returns the hexadecimal HMAC-SHA256 digest of `s` with key `key`.
+///
+/// The key is either `bytes` or a `str`, which is encoded as UTF-8.
+fn hmac(py: Python, s: &str, key: &PyAny) -> PyResult<String> {
+  let key = match key.extract::<&str>() {
+    Ok(key) => PyBytes::new(py, key.as_bytes()),
+    Err(_) => key.extract::<&PyBytes>()?,
+  };
+  py.import("hmac")?.call1("new", (key, PyBytes::new(py, s.as_bytes()), "sha256"))?.call_method0("hexdigest")?.extract()
+}
+
+/// Appends a signature footer to the canonical (deprettified) string `s`.
+pub fn sign(py: Python, s: &str, key: &PyAny) -> PyResult<String> {
+  Ok(format!("{}\n{}{}", s, FOOTER, hmac(py, s, key)?))
+}
+
+/// Verifies and strips the signature footer of `signed`.
+///
+/// The signature is computed over the canonical form of the body, such that
+/// prettifying a signed string does not invalidate the signature. Returns the
+/// canonical body.
+pub fn verify(py: Python, signed: &str, key: &PyAny) -> PyResult<String> {
+  let trimmed = signed.trim_end();
+  let (body, footer) = match trimmed.rfind('\n') {
+    Some(index) => (&trimmed[..index], &trimmed[index + 1..]),
+    None => return Err(SerializationError::py_err("missing signature")),
+  };
+  if !footer.starts_with(FOOTER) {
+    return Err(SerializationError::py_err("missing signature"));
+  }
+  let body = if body.contains('\n') { deprettify(body)? } else { body.to_string() };
+  let expected = hmac(py, &body, key)?;
+  if !py.import("hmac")?.call1("compare_digest", (expected, footer[FOOTER.len()..].trim()))?.is_true()? {
+    return Err(SerializationError::py_err("signature mismatch, the value was modified or signed with a different key"));
+  }
+  Ok(body)
+}
@@ -0,0 +1,99 @@
+use crate::guard;
+use pyo3::proc_macro::pyfunction;
+use pyo3::types::{PyDict, PyModule};
+use pyo3::{wrap_pyfunction, PyResult, Python};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// A phase of `dumps` and `loads` that is timed when profiling is enabled.
+#[derive(Clone, Copy)]
+pub enum Phase {
+  /// Building the schema of a type, i.e. introspection of annotations.
+  Build,
+  /// Expanding presets and resolving references of a serialized string.
+  Parse,
+  /// Deserializing a string to Python objects.
+  Construct,
+  /// Serializing a Python object to a string.
+  Serialize,
+}
+
+const PHASES: [(Phase, &str); 4] = [(Phase::Build, "build"), (Phase::Parse, "parse"), (Phase::Construct, "construct"), (Phase::Serialize, "serialize")];
+
+/// Whether profiling is enabled, see `set_profiling`.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The number of calls and the total time in seconds per phase.
+static STATS: Mutex<[(usize, f64); 4]> = Mutex::new([(0, 0.); 4]);
+
+/// Runs `f` and, if profiling is enabled, adds its duration to `phase`.
+pub fn timed<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+  if !ENABLED.load(Ordering::Relaxed) {
+    return f();
+  }
+  let start = Instant::now();
+  let result = f();
+  let seconds = start.elapsed().as_secs_f64();
+  let mut stats = STATS.lock().unwrap_or_else(|err| err.into_inner());
+  let entry = &mut stats[phase as usize];
+  entry.0 += 1;
+  entry.1 += seconds;
+  result
+}
+
+/// Enables or disables profiling of `dumps` and `loads`, see `stats`.
+///
+/// Profiling is disabled by default, as timing every phase of every call adds
+/// a small overhead.
+#[pyfunction]
+#[text_signature = "(enabled, /)"]
+fn set_profiling(enabled: bool) -> PyResult<()> {
+  guard("stringly.set_profiling", || {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    Ok(())
+  })
+}
+
+/// Returns the profile collected since profiling was enabled or `reset_stats`
+/// was called, as a dict of the phases `build`, `parse`, `construct` and
+/// `serialize` to dicts with the number of `calls` and the total `seconds`.
+///
+/// Building is the introspection of type annotations, parsing the expansion
+/// of presets and references, constructing the deserialization of strings to
+/// Python objects and serializing the reverse. The phases of nested values
+/// that are serialized separately, such as `Lazy` fields, are included.
+#[pyfunction]
+#[text_signature = "()"]
+fn stats(py: Python) -> PyResult<&PyDict> {
+  guard("stringly.stats", || {
+    let stats = *STATS.lock().unwrap_or_else(|err| err.into_inner());
+    let dict = PyDict::new(py);
+    for (phase, name) in &PHASES {
+      let (calls, seconds) = stats[*phase as usize];
+      let entry = PyDict::new(py);
+      entry.set_item("calls", calls)?;
+      entry.set_item("seconds", seconds)?;
+      dict.set_item(name, entry)?;
+    }
+    Ok(dict)
+  })
+}
+
+/// Resets the profile collected by `stats`.
+#[pyfunction]
+#[text_signature = "()"]
+fn reset_stats() -> PyResult<()> {
+  guard("stringly.reset_stats", || {
+    *STATS.lock().unwrap_or_else(|err| err.into_inner()) = [(0, 0.); 4];
+    Ok(())
+  })
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(set_profiling))?;
+  m.add_wrapped(wrap_pyfunction!(stats))?;
+  m.add_wrapped(wrap_pyfunction!(reset_stats))?;
+
+  Ok(())
+}
@@ -0,0 +1,71 @@
+use crate::{dumps_value, guard, loads_value, SerializationError};
+use pyo3::exceptions::EOFError;
+use pyo3::proc_macro::pyfunction;
+use pyo3::types::{PyAny, PyBytes, PyModule};
+use pyo3::{wrap_pyfunction, PyObject, PyResult, Python};
+
+/// The default maximum payload size in bytes accepted by `recv`.
+pub const MAX_FRAME: usize = 64 << 20;
+
+/// The maximum number of bytes requested from the file per read.
+const CHUNK: usize = 1 << 16;
+
+/// Writes `value` to binary file object `file` as a single frame.
+///
+/// A frame consists of the decimal length of the payload in bytes, a newline
+/// and the UTF-8 encoded serialized value.
+#[pyfunction]
+#[text_signature = "(file, type, value, /)"]
+fn send(py: Python, file: &PyAny, ty: &PyAny, val: &PyAny) -> PyResult<()> {
+  guard("stringly.stream.send", || {
+    let payload = dumps_value(py, ty, val)?;
+    let mut frame = format!("{}\n", payload.len()).into_bytes();
+    frame.extend_from_slice(payload.as_bytes());
+    file.call_method1("write", (PyBytes::new(py, &frame),))?;
+    file.call_method0("flush")?;
+    Ok(())
+  })
+}
+
+/// Reads a single frame written by `send` from binary file object `file`.
+///
+/// Raises `EOFError` if the stream ends before the start of a frame and
+/// `SerializationError` if the announced payload exceeds `max_frame` bytes,
+/// which defaults to `MAX_FRAME` (64 MiB).
+#[pyfunction(max_frame = "MAX_FRAME")]
+#[text_signature = "(file, type, /, max_frame=MAX_FRAME)"]
+fn recv(py: Python, file: &PyAny, ty: &PyAny, max_frame: usize) -> PyResult<PyObject> {
+  guard("stringly.stream.recv", || {
+    let header = file.call_method1("readline", (21,))?.extract::<&PyBytes>()?.as_bytes();
+    if header.is_empty() {
+      return Err(EOFError::py_err("end of stream"));
+    }
+    let length = match std::str::from_utf8(header).ok().and_then(|header| header.strip_suffix('\n')).and_then(|header| header.parse::<usize>().ok()) {
+      Some(length) => length,
+      None => return Err(SerializationError::py_err("invalid frame header")),
+    };
+    if length > max_frame {
+      return Err(SerializationError::py_err(format!("frame of {} bytes exceeds the maximum of {} bytes", length, max_frame)));
+    }
+    let mut payload = Vec::new();
+    while payload.len() < length {
+      let chunk = file.call_method1("read", ((length - payload.len()).min(CHUNK),))?.extract::<&PyBytes>()?.as_bytes();
+      if chunk.is_empty() {
+        return Err(SerializationError::py_err(format!("truncated frame, expected {} bytes but got {}", length, payload.len())));
+      }
+      payload.extend_from_slice(chunk);
+    }
+    match String::from_utf8(payload) {
+      Ok(s) => loads_value(py, ty, &s),
+      Err(_) => Err(SerializationError::py_err("frame payload is not valid UTF-8")),
+    }
+  })
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(send))?;
+  m.add_wrapped(wrap_pyfunction!(recv))?;
+  m.add("MAX_FRAME", MAX_FRAME)?;
+
+  Ok(())
+}
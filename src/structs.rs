@@ -0,0 +1,490 @@
+use crate::options::Options;
+use crate::schema::{build_with, dumps, loads, type_name, Schema, Text};
+use crate::{markers, messages, SerializationError};
+use pyo3::class::basic::CompareOp;
+use pyo3::exceptions::{TypeError, ValueError};
+use pyo3::proc_macro::{pyclass, pymethods};
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyTuple};
+use pyo3::{AsPyPointer, PyObject, PyResult, Python, ToPyObject};
+use std::cell::{OnceCell, RefCell};
+use stringly::util::{protect, safesplit, safesplit_once, unprotect};
+
+/// A field of a `Struct`.
+pub struct Field {
+  pub name: String,
+  pub schema: Box<dyn Schema>,
+  /// Whether the field must be present in the serialized string.
+  pub required: bool,
+  /// The environment variable that provides the value if the field is absent.
+  pub env: Option<String>,
+  /// Whether the key of the field may be repeated, to give the items of a
+  /// list one by one, e.g. `tag=a,tag=b`.
+  pub repeatable: bool,
+  /// Whether the field is a `bool` that may be given as a bare key, e.g.
+  /// `verbose` for `verbose=true` and `noverbose` or `!verbose` for
+  /// `verbose=false`.
+  pub flag: bool,
+}
+
+/// A record type that is serialized as `key=value` items, one per field.
+///
+/// Values are read from attributes, or items if `mapping` is true, and
+/// constructed by calling `ty` with the fields as keyword arguments. Fields
+/// that are not required and are absent from the serialized string are left
+/// to the defaults of `ty`.
+///
+/// If `preserve` is true, the original text of the numeric and boolean fields
+/// of a loaded value is stored in its attribute `__stringly_source__`, if the
+/// value accepts attributes, and dumped instead of the value as long as the
+/// text loads to a value equal to the field.
+///
+/// If `positional` is true the first field may be given without its key as
+/// the first item, e.g. `1e-8,maxiter=100` for `tol=1e-8,maxiter=100`; a
+/// dataclass declares this by the metadata `{'positional': True}` of its first
+/// field. Values are always dumped with keys.
+pub struct Struct {
+  pub ty: PyObject,
+  pub fields: Vec<Field>,
+  pub mapping: bool,
+  pub preserve: bool,
+  pub positional: bool,
+}
+
+/// The attribute of a loaded value that holds the original text of its
+/// scalar fields, see `Struct`.
+const SOURCE: &str = "__stringly_source__";
+
+thread_local! {
+  /// The record types of which the struct schema is being built in this
+  /// thread, see `build`.
+  static BUILDING: RefCell<Vec<usize>> = RefCell::new(Vec::new());
+}
+
+/// Returns the struct schema of record type `ty`, or `None` if `ty` is not a
+/// record type.
+///
+/// Supported record types are dataclasses, `typing.NamedTuple` classes and
+/// `typing.TypedDict` classes. Annotations of fields may refer to classes
+/// defined later, as strings or by `from __future__ import annotations`. A
+/// record type that refers to itself, directly or through other types, e.g.
+/// `children: List['Tree']` of class `Tree`, gets a `Recursive` schema at the
+/// inner reference, so that trees and other recursive structures can be
+/// serialized.
+///
+/// A record type deriving from `typing.Generic` may be parametrized, e.g.
+/// `Box[int]`, in which case the type arguments are substituted for the type
+/// variables in the annotations of the fields.
+pub fn build(py: Python, ty: &PyAny, options: &Options) -> PyResult<Option<Box<dyn Schema>>> {
+  let builtins = py.import("builtins")?;
+  if !builtins.call1("isinstance", (class(py, ty)?, builtins.getattr("type")?))?.is_true()? {
+    return Ok(None);
+  }
+  let key = ty.as_ptr() as usize;
+  if BUILDING.with(|building| building.borrow().contains(&key)) {
+    return Ok(Some(Box::new(Recursive::new(ty, options))));
+  }
+  BUILDING.with(|building| building.borrow_mut().push(key));
+  let result = build_record(py, ty, options);
+  BUILDING.with(|building| building.borrow_mut().retain(|item| *item != key));
+  result
+}
+
+/// Returns the class of `ty`, the origin if `ty` is a parametrized generic
+/// class such as `Box[int]`.
+fn class<'py>(py: Python<'py>, ty: &'py PyAny) -> PyResult<&'py PyAny> {
+  let origin = py.import("typing")?.call1("get_origin", (ty,))?;
+  Ok(if origin.is_none() { ty } else { origin })
+}
+
+/// Returns the struct schema of class or parametrized generic class `ty`, see
+/// `build`.
+fn build_record(py: Python, ty: &PyAny, options: &Options) -> PyResult<Option<Box<dyn Schema>>> {
+  let builtins = py.import("builtins")?;
+  let class = class(py, ty)?;
+  if builtins.call1("issubclass", (class, builtins.getattr("tuple")?))?.is_true()? && class.hasattr("_fields")? {
+    return Ok(Some(named_tuple(py, ty, options)?));
+  }
+  if builtins.call1("issubclass", (class, builtins.getattr("dict")?))?.is_true()? && class.hasattr("__required_keys__")? {
+    return Ok(Some(typed_dict(py, ty, options)?));
+  }
+  let dataclasses = py.import("dataclasses")?;
+  if !dataclasses.call1("is_dataclass", (class,))?.is_true()? {
+    return Ok(None);
+  }
+  let hints = type_hints(py, ty)?;
+  let missing = dataclasses.getattr("MISSING")?;
+  let mut fields = Vec::new();
+  let mut positional = false;
+  for field in dataclasses.call1("fields", (class,))?.iter()? {
+    let field = field?;
+    if !field.getattr("init")?.is_true()? {
+      continue;
+    }
+    if fields.is_empty() {
+      positional = field.getattr("metadata")?.call_method1("get", ("positional", false))?.is_true()?;
+    }
+    let name: String = field.getattr("name")?.extract()?;
+    let required = field.getattr("default")?.as_ptr() == missing.as_ptr() && field.getattr("default_factory")?.as_ptr() == missing.as_ptr();
+    fields.push(Field::new(py, name, hints.get_item(name.as_str())?, required, options)?);
+  }
+  Ok(Some(Box::new(Struct { ty: ty.into(), fields, mapping: false, preserve: options.preserve_scalars, positional })))
+}
+
+/// Returns the type hints of class `ty`, including `typing.Annotated`
+/// metadata.
+///
+/// If `ty` is a parametrized generic class the type arguments are substituted
+/// for the type variables of the class.
+pub fn type_hints<'py>(py: Python<'py>, ty: &'py PyAny) -> PyResult<&'py PyAny> {
+  let typing = py.import("typing")?;
+  let class = class(py, ty)?;
+  let hints = typing.call("get_type_hints", (class,), Some([("include_extras", true)].into_py_dict(py)))?;
+  if class.as_ptr() == ty.as_ptr() {
+    return Ok(hints);
+  }
+  let parameters: Vec<&PyAny> = class.getattr("__parameters__")?.extract()?;
+  let args: Vec<&PyAny> = typing.call1("get_args", (ty,))?.extract()?;
+  let substitutions: Vec<(&PyAny, &PyAny)> = parameters.into_iter().zip(args).collect();
+  let substituted = PyDict::new(py);
+  for item in hints.call_method0("items")?.iter()? {
+    let (name, hint): (&PyAny, &PyAny) = item?.extract()?;
+    substituted.set_item(name, substitute(py, hint, &substitutions)?)?;
+  }
+  Ok(substituted.as_ref())
+}
+
+/// Returns annotation `hint` with the type variables replaced according to
+/// `substitutions`, e.g. `List[T]` becomes `List[int]` for `T` to `int`.
+fn substitute<'py>(py: Python<'py>, hint: &'py PyAny, substitutions: &[(&'py PyAny, &'py PyAny)]) -> PyResult<&'py PyAny> {
+  if let Some((_, arg)) = substitutions.iter().find(|(parameter, _)| parameter.as_ptr() == hint.as_ptr()) {
+    return Ok(arg);
+  }
+  let parameters: Vec<&PyAny> = match hint.getattr("__parameters__").and_then(|parameters| parameters.extract()) {
+    Ok(parameters) if !parameters.is_empty() => parameters,
+    _ => return Ok(hint),
+  };
+  let args = parameters.into_iter().map(|parameter| substitute(py, parameter, substitutions)).collect::<PyResult<Vec<_>>>()?;
+  hint.get_item(PyTuple::new(py, args))
+}
+
+impl Field {
+  /// Returns field `name` of annotation `ty`.
+  ///
+  /// A field with a coercion hook in the `coerce` option is loaded by the
+  /// hook. A field annotated with an `EnvDefault` marker is, if absent, loaded
+  /// from its environment variable if that is set. With the `tolerant` option
+  /// the key of a `List` field may be repeated and a `bool` field is a flag.
+  pub fn new(py: Python, name: String, ty: &PyAny, required: bool, options: &Options) -> PyResult<Self> {
+    let mut schema = build_with(py, ty, options)?;
+    if let Some((_, hook)) = options.coerce.iter().flatten().find(|(field, _)| *field == name) {
+      schema = Box::new(Coerced { hook: hook.clone_ref(py), schema });
+    }
+    let env = markers::env_default(py, ty)?;
+    let repeatable = options.tolerant && py.import("typing")?.call1("get_origin", (ty,))?.as_ptr() == py.import("builtins")?.getattr("list")?.as_ptr();
+    let flag = options.tolerant && ty.as_ptr() == py.import("builtins")?.getattr("bool")?.as_ptr();
+    Ok(Field { name, schema, required, env, repeatable, flag })
+  }
+}
+
+/// A field that is loaded by a coercion hook, called with the serialized
+/// string, and dumped by its `schema`.
+struct Coerced {
+  hook: PyObject,
+  schema: Box<dyn Schema>,
+}
+
+impl Text for Coerced {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    dumps(py, self.schema.as_ref(), value)
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    match self.hook.as_ref(py).call1((s,)) {
+      Ok(value) => Ok(value.into()),
+      Err(err) if err.is_instance::<ValueError>(py) => Err(SerializationError::py_err(format!("cannot coerce '{}': {}", s, err.to_object(py).as_ref(py)))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    self.schema.describe(py)
+  }
+}
+
+/// Returns the struct schema of `typing.NamedTuple` class `ty`.
+///
+/// Fields without annotation, as of `collections.namedtuple`, are strings.
+fn named_tuple(py: Python, ty: &PyAny, options: &Options) -> PyResult<Box<dyn Schema>> {
+  let hints = type_hints(py, ty)?;
+  let defaults = class(py, ty)?.getattr("_field_defaults")?;
+  let mut fields = Vec::new();
+  for name in class(py, ty)?.getattr("_fields")?.iter()? {
+    let name: String = name?.extract()?;
+    let annotation = match hints.get_item(name.as_str()) {
+      Ok(annotation) => annotation,
+      Err(_) => py.import("builtins")?.getattr("str")?,
+    };
+    let required = !defaults.call_method1("__contains__", (name.as_str(),))?.is_true()?;
+    fields.push(Field::new(py, name, annotation, required, options)?);
+  }
+  Ok(Box::new(Struct { ty: ty.into(), fields, mapping: false, preserve: options.preserve_scalars, positional: false }))
+}
+
+/// Returns the struct schema of `typing.TypedDict` class `ty`.
+///
+/// Keys that are not required, by `total=False` or `NotRequired`, may be
+/// absent from both the serialized string and the dict.
+fn typed_dict(py: Python, ty: &PyAny, options: &Options) -> PyResult<Box<dyn Schema>> {
+  let hints = type_hints(py, ty)?;
+  let required = class(py, ty)?.getattr("__required_keys__")?;
+  let mut fields = Vec::new();
+  for item in hints.call_method0("items")?.iter()? {
+    let (name, annotation): (String, &PyAny) = item?.extract()?;
+    let required = required.call_method1("__contains__", (name.as_str(),))?.is_true()?;
+    fields.push(Field::new(py, name, annotation, required, options)?);
+  }
+  Ok(Box::new(Struct { ty: ty.into(), fields, mapping: true, preserve: options.preserve_scalars, positional: false }))
+}
+
+impl Struct {
+  /// Returns the unprotected `key=value` items of serialized struct `s`.
+  ///
+  /// Items without a key are accepted for a positional first field, as the
+  /// first item, and for flags, see `Field::flag`: a bare key sets the flag to
+  /// `true`, the key prefixed by `no` or `!` to `false`.
+  pub fn items<'a>(&'a self, s: &'a str) -> PyResult<Vec<(&'a str, &'a str)>> {
+    if s.is_empty() {
+      return Ok(Vec::new());
+    }
+    let mut result = Vec::new();
+    for (index, item) in safesplit(s, ',').enumerate() {
+      if let Ok((key, value)) = safesplit_once(item, '=') {
+        result.push((unprotect(key), unprotect(value)));
+        continue;
+      }
+      if let (0, true, Some(first)) = (index, self.positional, self.fields.first()) {
+        result.push((first.name.as_str(), unprotect(item)));
+        continue;
+      }
+      let name = item.trim();
+      let negated = name.strip_prefix('!').or_else(|| name.strip_prefix("no"));
+      if let Some(field) = self.fields.iter().find(|field| field.flag && field.name == name) {
+        result.push((field.name.as_str(), "true"));
+      } else if let Some(field) = negated.and_then(|negated| self.fields.iter().find(|field| field.flag && field.name == negated)) {
+        result.push((field.name.as_str(), "false"));
+      } else {
+        return Err(SerializationError::py_err(format!("expected key=value but got '{}'", item)));
+      }
+    }
+    Ok(result)
+  }
+  fn field(&self, py: Python, key: &str) -> PyResult<&Field> {
+    match self.fields.iter().find(|field| field.name == key) {
+      Some(field) => Ok(field),
+      None => Err(SerializationError::py_err(format!("{} has no field '{}'; valid fields are: {}", type_name(self.ty.as_ref(py))?, key, self.fields.iter().map(|field| field.name.as_str()).collect::<Vec<_>>().join(", ")))),
+    }
+  }
+}
+
+impl Struct {
+  /// Returns the original text of `field` with `value` from the `source` of
+  /// the struct, if any and if the text still loads to `value`.
+  fn source(&self, py: Python, source: Option<&PyAny>, field: &Field, value: &PyAny) -> PyResult<Option<String>> {
+    let text: String = match source.and_then(|source| source.get_item(field.name.as_str()).ok()) {
+      Some(text) => text.extract()?,
+      None => return Ok(None),
+    };
+    let loaded = match loads(py, field.schema.as_ref(), &text) {
+      Ok(loaded) => loaded,
+      Err(_) => return Ok(None),
+    };
+    let loaded = loaded.as_ref(py);
+    let unchanged = loaded.get_type().as_ptr() == value.get_type().as_ptr() && loaded.rich_compare(value, CompareOp::Eq)?.is_true()?;
+    Ok(if unchanged { Some(text) } else { None })
+  }
+}
+
+/// Returns the `items` of a serialized struct with the repeated keys of
+/// repeatable fields merged into a single item, of which the value is the
+/// concatenation of the values in order.
+fn repeated<'a>(schema: &Struct, items: Vec<(&'a str, &'a str)>) -> Vec<(&'a str, String)> {
+  let mut merged: Vec<(&str, String)> = Vec::new();
+  for (key, value) in items {
+    let repeatable = schema.fields.iter().any(|field| field.repeatable && field.name == key.trim());
+    match merged.iter_mut().find(|(item, _)| item.trim() == key.trim()) {
+      Some((_, values)) if repeatable => {
+        if !value.is_empty() {
+          if !values.is_empty() {
+            values.push(',');
+          }
+          values.push_str(value);
+        }
+      }
+      _ => merged.push((key, value.to_string())),
+    }
+  }
+  merged
+}
+
+/// Returns whether `value` is a number or a boolean, of which the original
+/// text is kept if the `preserve_scalars` option is set.
+fn is_scalar(py: Python, value: &PyAny) -> PyResult<bool> {
+  py.import("builtins")?.call1("isinstance", (value, py.import("numbers")?.getattr("Number")?))?.is_true()
+}
+
+impl Text for Struct {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    let source = if self.preserve { value.getattr(SOURCE).ok() } else { None };
+    let mut items = Vec::new();
+    for field in &self.fields {
+      let value = if !self.mapping {
+        value.getattr(field.name.as_str())?
+      } else if value.call_method1("__contains__", (field.name.as_str(),))?.is_true()? {
+        value.get_item(field.name.as_str())?
+      } else if field.required {
+        return Err(SerializationError::py_err(format!("missing key '{}' of {}", field.name, type_name(self.ty.as_ref(py))?)));
+      } else {
+        continue;
+      };
+      let s = match self.source(py, source, field, value)? {
+        Some(s) => s,
+        None => dumps(py, field.schema.as_ref(), value)?,
+      };
+      items.push(format!("{}={}", field.name, protect(&s, ',')));
+    }
+    Ok(items.join(","))
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    let kwargs = PyDict::new(py);
+    let source = PyDict::new(py);
+    for (key, text) in repeated(self, self.items(s)?) {
+      let field = self.field(py, key.trim())?;
+      let text = text.as_str();
+      let value = loads(py, field.schema.as_ref(), text).map_err(|err| messages::with_field(py, err, &field.name))?;
+      if self.preserve && is_scalar(py, value.as_ref(py))? {
+        source.set_item(field.name.as_str(), text.trim())?;
+      }
+      kwargs.set_item(field.name.as_str(), value)?;
+    }
+    for field in &self.fields {
+      if let (Some(env), false) = (&field.env, kwargs.contains(field.name.as_str())?) {
+        if let Ok(value) = std::env::var(env) {
+          let value = loads(py, field.schema.as_ref(), &value).map_err(|err| messages::with_field(py, err, &field.name))?;
+          kwargs.set_item(field.name.as_str(), value)?;
+        }
+      }
+    }
+    if let Some(field) = self.fields.iter().find(|field| field.required && !kwargs.contains(field.name.as_str()).unwrap_or(false)) {
+      return Err(SerializationError::py_err(format!("missing field '{}' of {}", field.name, type_name(self.ty.as_ref(py))?)));
+    }
+    match self.ty.as_ref(py).call(PyTuple::empty(py), Some(kwargs)) {
+      Ok(value) => {
+        if self.preserve && !self.mapping && !source.is_empty() {
+          // Frozen dataclasses are set through `object`; values without
+          // attributes, such as named tuples, keep no source.
+          let _ = py.import("builtins")?.getattr("object")?.call_method1("__setattr__", (value, SOURCE, source));
+        }
+        Ok(value.into())
+      }
+      Err(err) if err.is_instance::<TypeError>(py) || err.is_instance::<ValueError>(py) => Err(SerializationError::py_err(format!("cannot construct {}: {}", type_name(self.ty.as_ref(py))?, err.to_object(py).as_ref(py)))),
+      Err(err) => Err(err),
+    }
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    type_name(self.ty.as_ref(py))
+  }
+  fn as_struct(&self) -> Option<&Struct> {
+    Some(self)
+  }
+}
+
+/// A reference of a record type to itself, of which the struct schema is built
+/// when a value is serialized or the schema is queried.
+///
+/// The schema is built once, on first use, so that the schema of a tree grows
+/// only as deep as the values that are serialized.
+struct Recursive {
+  ty: PyObject,
+  options: Options,
+  schema: OnceCell<Box<dyn Schema>>,
+}
+
+impl Recursive {
+  fn new(ty: &PyAny, options: &Options) -> Self {
+    Recursive { ty: ty.into(), options: options.clone(), schema: OnceCell::new() }
+  }
+  fn schema(&self, py: Python) -> PyResult<&dyn Schema> {
+    if let Some(schema) = self.schema.get() {
+      return Ok(schema.as_ref());
+    }
+    let schema = build_with(py, self.ty.as_ref(py), &self.options)?;
+    Ok(self.schema.get_or_init(|| schema).as_ref())
+  }
+}
+
+impl Text for Recursive {
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    dumps(py, self.schema(py)?, value)
+  }
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    loads(py, self.schema(py)?, s)
+  }
+  fn describe(&self, py: Python) -> PyResult<String> {
+    type_name(self.ty.as_ref(py))
+  }
+  fn as_struct(&self) -> Option<&Struct> {
+    let gil = Python::acquire_gil();
+    self.schema(gil.python()).ok()?.as_struct()
+  }
+}
+
+/// The fields of a loaded value that were given explicitly and those that
+/// were filled from defaults, as dotted paths into nested structs, with the
+/// index for items of lists, e.g. `children.0.name`.
+#[pyclass]
+#[derive(Default)]
+pub struct Report {
+  #[pyo3(get)]
+  explicit: Vec<String>,
+  #[pyo3(get)]
+  defaulted: Vec<String>,
+}
+
+#[pymethods]
+impl Report {
+  fn __repr__(&self) -> String {
+    format!("Report(explicit={:?}, defaulted={:?})", self.explicit, self.defaulted)
+  }
+}
+
+impl Report {
+  /// Adds the fields of serialized string `s` of `schema` with path `prefix`.
+  pub fn add(&mut self, schema: &dyn Schema, s: &str, prefix: &str) -> PyResult<()> {
+    if let Some(item) = schema.as_optional() {
+      return if s.is_empty() { Ok(()) } else { self.add(item, s, prefix) };
+    }
+    if let Some(item) = schema.as_list() {
+      if !s.is_empty() {
+        for (index, value) in safesplit(s, ',').enumerate() {
+          self.add(item, unprotect(value), &format!("{}{}.", prefix, index))?;
+        }
+      }
+      return Ok(());
+    }
+    let schema = match schema.as_struct() {
+      Some(schema) => schema,
+      None => return Ok(()),
+    };
+    let items = schema.items(s)?;
+    for field in &schema.fields {
+      let path = format!("{}{}", prefix, field.name);
+      match items.iter().rev().find(|(key, _)| key.trim() == field.name) {
+        Some((_, value)) => {
+          self.add(field.schema.as_ref(), value, &format!("{}.", path))?;
+          self.explicit.push(path);
+        }
+        None if !field.required || field.env.is_some() => self.defaulted.push(path),
+        None => (),
+      }
+    }
+    Ok(())
+  }
+}
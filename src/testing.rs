@@ -0,0 +1,144 @@
+use crate::markers::{Color, Duration, Percent, Size};
+use crate::schema::{is_union, type_name};
+use crate::{dumps_value, guard, options, structs};
+use pyo3::exceptions::TypeError;
+use pyo3::proc_macro::pyfunction;
+use pyo3::type_object::PyTypeObject;
+use pyo3::types::{IntoPyDict, PyAny, PyDict, PyList, PyModule, PyTuple};
+use pyo3::{wrap_pyfunction, AsPyPointer, PyObject, PyRef, PyResult, Python, ToPyObject};
+
+/// The characters of random strings, including the separators that require
+/// protection.
+const ALPHABET: &str = "abcxyzABC019 _-.,=[]{}";
+
+/// The maximum nesting depth of random containers.
+const MAX_DEPTH: usize = 4;
+
+/// Returns a random value of annotation `ty` drawn from `rng`, an instance of
+/// `random.Random`.
+///
+/// Containers get fewer items as the nesting `depth` increases.
+fn random(py: Python, rng: &PyAny, ty: &PyAny, depth: usize) -> PyResult<PyObject> {
+  let typing = py.import("typing")?;
+  let builtins = py.import("builtins")?;
+  let datetime = py.import("datetime")?;
+  let origin = typing.call1("get_origin", (ty,))?;
+  let args: Vec<&PyAny> = typing.call1("get_args", (ty,))?.extract()?;
+  let is = |a: &PyAny, b: &PyAny| a.as_ptr() == b.as_ptr();
+  let randint = |a: i64, b: i64| -> PyResult<i64> { rng.call_method1("randint", (a, b))?.extract() };
+  let length = || randint(0, (MAX_DEPTH - depth.min(MAX_DEPTH)) as i64);
+  let none_type = builtins.getattr("type")?.call1((py.None(),))?;
+  if is(origin, typing.getattr("Annotated")?) {
+    for item in &args[1..] {
+      if item.extract::<PyRef<Color>>().is_ok() || item.as_ptr() == Color::type_object(py).as_ptr() {
+        let rgb = (randint(0, 255)?, randint(0, 255)?, randint(0, 255)?);
+        return if builtins.call1("isinstance", (args[0], builtins.getattr("type")?))?.is_true()? && args[0].hasattr("_fields")? { Ok(args[0].call1(rgb)?.into()) } else { Ok(rgb.to_object(py)) };
+      } else if item.extract::<PyRef<Size>>().is_ok() || item.as_ptr() == Size::type_object(py).as_ptr() {
+        return Ok((randint(0, 1 << 20)? << randint(0, 30)?).to_object(py));
+      } else if item.extract::<PyRef<Percent>>().is_ok() || item.as_ptr() == Percent::type_object(py).as_ptr() {
+        return Ok(rng.call_method0("random")?.to_object(py));
+      } else if item.extract::<PyRef<Duration>>().is_ok() || item.as_ptr() == Duration::type_object(py).as_ptr() {
+        let microseconds = randint(0, 1 << 40)?;
+        return if is(args[0], datetime.getattr("timedelta")?) {
+          Ok(datetime.getattr("timedelta")?.call((), Some([("microseconds", microseconds)].into_py_dict(py)))?.into())
+        } else if is(args[0], builtins.getattr("int")?) {
+          Ok((microseconds / 1_000_000).to_object(py))
+        } else {
+          Ok((microseconds as f64 / 1e6).to_object(py))
+        };
+      }
+    }
+    random(py, rng, args[0], depth)
+  } else if is(origin, typing.getattr("Literal")?) {
+    Ok(rng.call_method1("choice", (PyList::new(py, &args),))?.into())
+  } else if is_union(py, origin)? {
+    let optional = args.iter().any(|arg| is(arg, none_type));
+    if optional && depth >= MAX_DEPTH {
+      return Ok(py.None());
+    }
+    let choice = rng.call_method1("choice", (PyList::new(py, &args),))?;
+    let value = random(py, rng, choice, depth)?;
+    // An `Optional` rejects values that serialize as the empty string, which
+    // denotes `None`.
+    if optional && !value.is_none() && dumps_value(py, choice, value.as_ref(py))?.trim().is_empty() {
+      return Ok(py.None());
+    }
+    Ok(value)
+  } else if is(origin, builtins.getattr("list")?) && args.len() == 1 {
+    let items = (0..length()?).map(|_| random(py, rng, args[0], depth + 1)).collect::<PyResult<Vec<_>>>()?;
+    Ok(PyList::new(py, items).into())
+  } else if is(origin, builtins.getattr("tuple")?) {
+    let items = if args.len() == 2 && is(args[1], builtins.getattr("Ellipsis")?) {
+      (0..length()?).map(|_| random(py, rng, args[0], depth + 1)).collect::<PyResult<Vec<_>>>()?
+    } else {
+      args.iter().map(|arg| random(py, rng, arg, depth + 1)).collect::<PyResult<Vec<_>>>()?
+    };
+    Ok(PyTuple::new(py, items).into())
+  } else if is(origin, builtins.getattr("dict")?) && args.len() == 2 {
+    let dict = PyDict::new(py);
+    for _ in 0..length()? {
+      dict.set_item(random(py, rng, args[0], depth + 1)?, random(py, rng, args[1], depth + 1)?)?;
+    }
+    Ok(dict.into())
+  } else if ty.is_none() || is(ty, none_type) {
+    Ok(py.None())
+  } else if is(ty, builtins.getattr("bool")?) {
+    Ok((randint(0, 1)? == 1).to_object(py))
+  } else if is(ty, builtins.getattr("int")?) {
+    Ok(randint(-1000, 1000)?.to_object(py))
+  } else if is(ty, builtins.getattr("float")?) {
+    Ok(rng.call_method1("uniform", (-1000., 1000.))?.into())
+  } else if is(ty, builtins.getattr("str")?) {
+    let chars: Vec<char> = ALPHABET.chars().collect();
+    let s = (0..randint(0, 8)?).map(|_| Ok(chars[randint(0, chars.len() as i64 - 1)? as usize])).collect::<PyResult<String>>()?;
+    Ok(s.to_object(py))
+  } else if is(ty, datetime.getattr("timedelta")?) {
+    Ok(datetime.getattr("timedelta")?.call((), Some([("microseconds", randint(-1 << 40, 1 << 40)?)].into_py_dict(py)))?.into())
+  } else if is(ty, datetime.getattr("date")?) {
+    Ok(datetime.getattr("date")?.call_method1("fromordinal", (randint(1, 3_652_059)?,))?.into())
+  } else if is(ty, datetime.getattr("time")?) {
+    Ok(datetime.getattr("time")?.call1((randint(0, 23)?, randint(0, 59)?, randint(0, 59)?, randint(0, 999_999)?))?.into())
+  } else if is(ty, datetime.getattr("datetime")?) {
+    let date = datetime.getattr("date")?.call_method1("fromordinal", (randint(1, 3_652_059)?,))?;
+    let time = datetime.getattr("time")?.call1((randint(0, 23)?, randint(0, 59)?, randint(0, 59)?, randint(0, 999_999)?))?;
+    Ok(datetime.getattr("datetime")?.call_method1("combine", (date, time))?.into())
+  } else if builtins.call1("isinstance", (ty, builtins.getattr("type")?))?.is_true()? && builtins.call1("issubclass", (ty, py.import("enum")?.getattr("Enum")?))?.is_true()? {
+    Ok(rng.call_method1("choice", (builtins.call1("list", (ty,))?,))?.into())
+  } else if let Some(schema) = structs::build(py, ty, &options::current())? {
+    let record = match schema.as_struct() {
+      Some(record) => record,
+      None => return Err(TypeError::py_err(format!("cannot generate random values of {}", type_name(ty)?))),
+    };
+    let hints = structs::type_hints(py, ty)?;
+    let kwargs = PyDict::new(py);
+    for field in &record.fields {
+      if field.required || (depth < MAX_DEPTH && randint(0, 1)? == 1) {
+        kwargs.set_item(field.name.as_str(), random(py, rng, hints.get_item(field.name.as_str())?, depth + 1)?)?;
+      }
+    }
+    Ok(record.ty.as_ref(py).call(PyTuple::empty(py), Some(kwargs))?.into())
+  } else {
+    Err(TypeError::py_err(format!("cannot generate random values of {}", type_name(ty)?)))
+  }
+}
+
+/// Returns a random valid value of annotation `type`.
+///
+/// The value honors the markers of `typing.Annotated` annotations and the
+/// choices of `Literal`, `Enum` and `bool`. Dataclasses, named tuples and
+/// typed dicts get random values for their required fields and for a random
+/// selection of the others. Passing the same `seed` yields the same value.
+#[pyfunction(seed = "None")]
+#[text_signature = "(type, seed=None)"]
+fn random_value(py: Python, ty: &PyAny, seed: &PyAny) -> PyResult<PyObject> {
+  guard("stringly.testing.random_value", || {
+    let rng = py.import("random")?.call1("Random", (seed,))?;
+    random(py, rng, ty, 0)
+  })
+}
+
+pub fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_wrapped(wrap_pyfunction!(random_value))?;
+
+  Ok(())
+}
@@ -76,6 +76,13 @@ pub struct VecLineIter<'a, 'b> {
   index: usize,
 }
 
+impl<'a, 'b> VecLineIter<'a, 'b> {
+  /// Returns the number, starting at 1, of the next line.
+  pub fn line_number(&self) -> usize {
+    self.index + 1
+  }
+}
+
 impl<'a, 'b> Iterator for VecLineIter<'a, 'b> {
   type Item = &'a str;
 
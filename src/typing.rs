@@ -0,0 +1,124 @@
+use crate::markers::{rgb_class, ByValue, Color, Duration, EnvDefault, Format, FromFile, Glob, Lazy, Percent, Size};
+use crate::{dumps_value, guard, loads_value, registry, schema};
+use pyo3::proc_macro::{pyclass, pyfunction, pymethods};
+use pyo3::type_object::PyTypeObject;
+use pyo3::types::{PyAny, PyDict, PyModule};
+use pyo3::{wrap_pyfunction, PyObject, PyResult, Python};
+
+/// Returns the human readable description of annotation `type`.
+#[pyfunction]
+#[text_signature = "(type, /)"]
+fn describe(py: Python, ty: &PyAny) -> PyResult<String> {
+  guard("stringly.typing.describe", || schema::build(py, ty)?.describe(py))
+}
+
+/// Returns the annotation of type string `s`, e.g. `'Dict[str, List[int]]'`.
+///
+/// The string is evaluated without builtins in a namespace of the names of
+/// `typing`, the builtin scalar and container types, the types of `datetime`,
+/// the markers of this module and the short names registered by
+/// `stringly.alias`.
+pub fn parse(py: Python, s: &str) -> PyResult<PyObject> {
+  let namespace = py.import("typing")?.dict().copy()?;
+  let builtins = py.import("builtins")?;
+  for name in &["bool", "int", "float", "complex", "str", "bytes", "list", "tuple", "dict", "set", "frozenset"] {
+    namespace.set_item(name, builtins.getattr(name)?)?;
+  }
+  let datetime = py.import("datetime")?;
+  for name in &["date", "time", "datetime", "timedelta"] {
+    namespace.set_item(name, datetime.getattr(name)?)?;
+  }
+  namespace.set_item("Format", Format::type_object(py))?;
+  namespace.set_item("Percent", Percent::type_object(py))?;
+  namespace.set_item("Duration", Duration::type_object(py))?;
+  namespace.set_item("Size", Size::type_object(py))?;
+  namespace.set_item("Color", Color::type_object(py))?;
+  namespace.set_item("Lazy", Lazy::type_object(py))?;
+  namespace.set_item("FromFile", FromFile::type_object(py))?;
+  namespace.set_item("Glob", Glob::type_object(py))?;
+  namespace.set_item("EnvDefault", EnvDefault::type_object(py))?;
+  namespace.set_item("ByValue", ByValue::type_object(py))?;
+  for (name, ty) in registry::type_aliases(py) {
+    namespace.set_item(name, ty)?;
+  }
+  namespace.set_item("__builtins__", PyDict::new(py))?;
+  Ok(py.eval(s, Some(namespace), None)?.into())
+}
+
+/// Returns the annotation of type string `s`, see `describe` for the inverse.
+///
+/// The string may use the names of `typing`, the builtin types, the types of
+/// `datetime`, the markers of `stringly.typing` and the short names registered
+/// by `stringly.alias`.
+#[pyfunction]
+#[text_signature = "(s, /)"]
+fn parse_type_str(py: Python, s: &str) -> PyResult<PyObject> {
+  guard("stringly.typing.parse_type_str", || parse(py, s))
+}
+
+/// A handle to an annotation for repeated serialization.
+///
+/// `TypeHandle(List[int]).loads('1,2')` is equivalent to
+/// `stringly.loads(List[int], '1,2')`. The type may also be given as a type
+/// string, see `parse_type_str`.
+#[pyclass]
+struct TypeHandle {
+  ty: PyObject,
+}
+
+#[pymethods]
+impl TypeHandle {
+  #[new]
+  fn new(py: Python, ty: &PyAny) -> PyResult<Self> {
+    guard("stringly.typing.TypeHandle", || {
+      let ty = match ty.extract::<&str>() {
+        Ok(s) => parse(py, s)?,
+        Err(_) => ty.into(),
+      };
+      // Validate the annotation early.
+      schema::build(py, ty.as_ref(py))?;
+      Ok(TypeHandle { ty })
+    })
+  }
+  #[getter]
+  fn get_type(&self, py: Python) -> PyObject {
+    self.ty.clone_ref(py)
+  }
+  /// Serializes `value`.
+  #[text_signature = "($self, value, /)"]
+  fn dumps(&self, py: Python, value: &PyAny) -> PyResult<String> {
+    guard("stringly.typing.TypeHandle.dumps", || dumps_value(py, self.ty.as_ref(py), value))
+  }
+  /// Deserializes `s`.
+  #[text_signature = "($self, s, /)"]
+  fn loads(&self, py: Python, s: &str) -> PyResult<PyObject> {
+    guard("stringly.typing.TypeHandle.loads", || loads_value(py, self.ty.as_ref(py), s))
+  }
+  /// Returns the human readable description of the type.
+  #[text_signature = "($self)"]
+  fn describe(&self, py: Python) -> PyResult<String> {
+    guard("stringly.typing.TypeHandle.describe", || schema::build(py, self.ty.as_ref(py))?.describe(py))
+  }
+  fn __repr__(&self, py: Python) -> PyResult<String> {
+    Ok(format!("TypeHandle({})", schema::build(py, self.ty.as_ref(py))?.describe(py)?))
+  }
+}
+
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+  m.add_class::<Format>()?;
+  m.add_class::<Percent>()?;
+  m.add_class::<Duration>()?;
+  m.add_class::<Size>()?;
+  m.add_class::<Color>()?;
+  m.add("RGB", rgb_class(py)?)?;
+  m.add_class::<Lazy>()?;
+  m.add_class::<FromFile>()?;
+  m.add_class::<Glob>()?;
+  m.add_class::<EnvDefault>()?;
+  m.add_class::<ByValue>()?;
+  m.add_class::<TypeHandle>()?;
+  m.add_wrapped(wrap_pyfunction!(describe))?;
+  m.add_wrapped(wrap_pyfunction!(parse_type_str))?;
+
+  Ok(())
+}
@@ -0,0 +1,45 @@
+import typing
+
+import pytest
+
+import stringly
+
+
+def setup_function():
+    stringly.set_cache_size(2)
+    stringly.clear_cache()
+
+
+def teardown_function():
+    stringly.set_cache_size(0)
+    stringly.clear_cache()
+
+
+def test_hit():
+    first = stringly.loads(typing.List[int], '1,2')
+    second = stringly.loads(typing.List[int], '1,2')
+    assert first == second == [1, 2]
+    assert first is not second
+    assert stringly.cache_info() == dict(hits=1, misses=1, size=1, maxsize=2)
+
+
+def test_key():
+    stringly.loads(typing.List[int], '1,2')
+    stringly.loads(typing.List[float], '1,2')
+    stringly.loads(typing.List[int], '1,2,3')
+    assert stringly.cache_info() == dict(hits=0, misses=3, size=2, maxsize=2)
+
+
+def test_options():
+    assert stringly.loads(int, '2*3', allow_expressions=True) == 6
+    assert stringly.loads(int, '2*3', allow_expressions=True) == 6
+    assert stringly.cache_info()['hits'] == 1
+    with pytest.raises(stringly.error.SerializationError):
+        stringly.loads(int, '2*3')
+    assert stringly.cache_info()['misses'] == 2
+
+
+def test_disabled():
+    stringly.set_cache_size(0)
+    assert stringly.loads(int, '1') == 1
+    assert stringly.cache_info() == dict(hits=0, misses=0, size=0, maxsize=0)
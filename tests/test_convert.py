@@ -0,0 +1,58 @@
+import dataclasses
+
+import pytest
+
+import stringly
+
+
+@dataclasses.dataclass
+class OldSolver:
+    method: str
+    tol: float = 1e-8
+    maxiter: int = 10
+
+
+@dataclasses.dataclass
+class NewSolver:
+    method: str
+    tolerance: float = 1e-8
+    damping: float = 1.
+
+
+@dataclasses.dataclass
+class OldConfig:
+    solver: OldSolver
+    name: str = ''
+
+
+@dataclasses.dataclass
+class NewConfig:
+    solver: NewSolver
+    name: str = ''
+
+
+@dataclasses.dataclass
+class Strict:
+    method: str
+    order: int
+
+
+def test_convert():
+    conversion = stringly.convert(OldSolver, NewSolver, 'method=newton,tol=0.001,maxiter=5', renames={'tol': 'tolerance'})
+    assert stringly.loads(NewSolver, conversion.value) == NewSolver('newton', 0.001, 1.)
+    assert conversion.renamed == [('tol', 'tolerance')]
+    assert conversion.dropped == ['maxiter']
+    assert conversion.defaulted == ['damping']
+
+
+def test_convert_nested():
+    conversion = stringly.convert(OldConfig, NewConfig, 'solver={method=newton,tol=0.001}', renames={'solver.tol': 'tolerance'})
+    assert stringly.loads(NewConfig, conversion.value) == NewConfig(NewSolver('newton', 0.001, 1.))
+    assert conversion.renamed == [('solver.tol', 'solver.tolerance')]
+    assert conversion.dropped == []
+    assert conversion.defaulted == ['solver.damping', 'name']
+
+
+def test_convert_missing_field():
+    with pytest.raises(stringly.error.SerializationError, match="missing value for field 'order'"):
+        stringly.convert(OldSolver, Strict, 'method=newton')
@@ -0,0 +1,16 @@
+import pytest
+
+import stringly
+
+
+def test_panic_raises_stringly_error():
+    with pytest.raises(stringly.error.StringlyError, match=r'^internal error in stringly\._panic: boom; please report this as a bug$'):
+        stringly._panic('boom')
+
+
+def test_interpreter_survives_panic():
+    for _ in range(3):
+        with pytest.raises(stringly.error.StringlyError):
+            stringly._panic('boom')
+    assert stringly.loads(int, '1') == 1
+    assert stringly.dumps(float, 0.5) == '0.5'
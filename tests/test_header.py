@@ -0,0 +1,67 @@
+import dataclasses
+import typing
+
+import pytest
+
+import stringly
+
+
+@dataclasses.dataclass
+class Shape:
+    name: str
+
+
+@dataclasses.dataclass
+class Circle(Shape):
+    radius: float = 1.
+
+
+stringly.alias('Circle', Circle)
+
+
+def test_dumps_header():
+    assert stringly.dumps(typing.List[int], [1, 2], header=True) == '!stringly/1 List[int]\n1,2'
+    assert stringly.dumps(Shape, Shape('a'), header=True) == f'!stringly/1 {__name__}:Shape\nname=a'
+
+
+def test_loads_header():
+    s = stringly.dumps(Shape, Shape('a'), header=True)
+    assert stringly.loads(Shape, s, header=True) == Shape('a')
+    assert stringly.loads(Shape, 'name=a', header=True) == Shape('a')
+
+
+def test_loads_header_dispatch():
+    s = stringly.dumps(Circle, Circle('c', 2.), header=True)
+    assert s.startswith('!stringly/1 Circle\n')
+    assert stringly.loads(Shape, s, header=True) == Circle('c', 2.)
+
+
+def test_loads_header_mismatch():
+    with pytest.raises(stringly.error.SerializationError, match="header declares 'Circle'"):
+        stringly.loads(int, '!stringly/1 Circle\nname=a', header=True)
+    with pytest.raises(stringly.error.SerializationError, match='unknown type'):
+        stringly.loads(Circle, f'!stringly/1 {__name__}:Shape\nname=a', header=True)
+
+
+def test_loads_header_version():
+    with pytest.raises(stringly.error.SerializationError, match='unsupported header version 2'):
+        stringly.loads(Shape, '!stringly/2 Circle\nname=a', header=True)
+
+
+def test_loads_any_alias():
+    s = stringly.dumps(Circle, Circle('c', 2.), header=True)
+    assert stringly.loads_any(s) == Circle('c', 2.)
+
+
+def test_loads_any_types():
+    s = stringly.dumps(Shape, Shape('a'), header=True)
+    assert stringly.loads_any(s, types={f'{__name__}:Shape': Shape}) == Shape('a')
+    with pytest.raises(stringly.error.SerializationError, match='unknown type'):
+        stringly.loads_any(s, types={'Circle': Circle})
+
+
+def test_loads_any_never_imports():
+    with pytest.raises(stringly.error.SerializationError, match='unknown type'):
+        stringly.loads_any(f'!stringly/1 {__name__}:Shape\nname=a')
+    with pytest.raises(stringly.error.SerializationError, match='expected a header'):
+        stringly.loads_any('name=a')
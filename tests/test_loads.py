@@ -0,0 +1,62 @@
+import dataclasses
+import typing
+
+import stringly
+
+
+@dataclasses.dataclass
+class Solver:
+    method: str
+    tol: float = 1e-8
+    steps: typing.List[int] = dataclasses.field(default_factory=lambda: [1, 2])
+
+
+class Point(typing.NamedTuple):
+    x: int
+    y: int = 0
+
+
+@dataclasses.dataclass
+class Tree:
+    name: str
+    weight: int = 1
+    children: typing.List['Tree'] = dataclasses.field(default_factory=list)
+    parent: typing.Optional['Tree'] = None
+
+
+def test_dataclass_defaults():
+    assert stringly.loads(Solver, 'method=newton') == Solver('newton', 1e-8, [1, 2])
+    assert stringly.loads(Solver, 'method=newton,tol=1e-3') == Solver('newton', 1e-3, [1, 2])
+
+
+def test_default_factory_is_called_per_load():
+    a = stringly.loads(Solver, 'method=newton')
+    b = stringly.loads(Solver, 'method=newton')
+    assert a.steps is not b.steps
+
+
+def test_named_tuple_defaults():
+    assert stringly.loads(Point, 'x=1') == Point(1, 0)
+
+
+def test_report_lists_defaulted_fields():
+    _, report = stringly.loads_with_report(Solver, 'method=newton')
+    assert report.explicit == ['method']
+    assert report.defaulted == ['tol', 'steps']
+
+
+def test_report_descends_into_recursive_fields():
+    value, report = stringly.loads_with_report(Tree, 'name=root,children={{name=a,weight=2},name=b},parent=name=up')
+    assert value == Tree('root', children=[Tree('a', 2), Tree('b')], parent=Tree('up'))
+    assert report.explicit == ['name', 'children.0.name', 'children.0.weight', 'children.1.name', 'children', 'parent.name', 'parent']
+    assert report.defaulted == ['weight', 'children.0.children', 'children.0.parent', 'children.1.weight', 'children.1.children', 'children.1.parent', 'parent.weight', 'parent.children', 'parent.parent']
+
+
+def test_parse_construct():
+    parsed = stringly.parse(Solver, 'method=newton,steps=3')
+    a = stringly.construct(parsed)
+    b = stringly.construct(parsed)
+    assert a == b == Solver('newton', 1e-8, [3])
+    assert a is not b
+    assert stringly.construct(parsed, coerce=dict(method=str.upper)) == Solver('NEWTON', 1e-8, [3])
+    assert stringly.construct(parsed) == a
@@ -0,0 +1,85 @@
+import typing
+
+import pytest
+
+import stringly
+
+Bytes = typing.Annotated[int, stringly.Size()]
+
+
+def test_size_roundtrip():
+    for value, s in [(0, '0B'), (512 * 2**20, '512MiB'), (2 * 10**9, '2GB'), (1001, '1001B')]:
+        assert stringly.dumps(Bytes, value) == s
+        assert stringly.loads(Bytes, s) == value
+
+
+def test_size_fraction():
+    assert stringly.loads(Bytes, '1.5KiB') == 1536
+    assert stringly.loads(Bytes, '0.001kB') == 1
+    with pytest.raises(stringly.error.SerializationError, match='whole number of bytes'):
+        stringly.loads(Bytes, '1.0001kB')
+
+
+def test_size_exact():
+    assert stringly.loads(Bytes, '9007199254740993') == 2**53 + 1
+    assert stringly.loads(Bytes, '16383PiB') == 16383 * 2**50
+
+
+def test_size_overflow():
+    with pytest.raises(stringly.error.SerializationError, match='exceeds the maximum'):
+        stringly.loads(Bytes, '20000PiB')
+    with pytest.raises(stringly.error.SerializationError, match='exceeds the maximum'):
+        stringly.loads(Bytes, '1' * 50)
+
+
+def test_size_invalid():
+    for s in ['', '.', 'MiB', '1.2.3kB', '1 parsec']:
+        with pytest.raises(stringly.error.SerializationError):
+            stringly.loads(Bytes, s)
+
+
+class Opaque:
+
+    def __init__(self, name):
+        self.name = name
+
+
+class ByName:
+
+    def __stringly_dumps__(self, value, codec):
+        return value.name
+
+    def __stringly_loads__(self, s, codec):
+        return Opaque(s)
+
+
+def test_custom_metadata_unsupported_base():
+    T = typing.Annotated[Opaque, ByName()]
+    assert stringly.dumps(T, Opaque('foo')) == 'foo'
+    assert stringly.loads(T, 'bar').name == 'bar'
+    assert stringly.dumps(typing.List[T], [Opaque('a'), Opaque('b')]) == 'a,b'
+
+
+def test_lazy_forwards_protocols():
+    value = stringly.loads(typing.Annotated[typing.List[int], stringly.Lazy()], '1,2,3')
+    assert not value.resolved
+    assert len(value) == 3
+    assert value.resolved
+    assert value[1] == 2
+    assert list(value) == [1, 2, 3]
+    assert 3 in value
+    assert value == [1, 2, 3]
+    assert value != [1, 2]
+    assert value
+    assert str(value) == '[1, 2, 3]'
+    assert repr(value) == 'LazyValue([1, 2, 3])'
+    assert value.count(2) == 1
+    assert value.resolve() == [1, 2, 3]
+
+
+def test_lazy_mapping():
+    value = stringly.loads(typing.Annotated[typing.Dict[str, int], stringly.Lazy()], 'a=1,b=2')
+    assert repr(value).startswith('LazyValue(<unloaded ')
+    assert value['b'] == 2
+    assert sorted(value) == ['a', 'b']
+    assert 'a' in value
@@ -0,0 +1,43 @@
+import pytest
+
+import stringly
+
+
+def test_replace():
+    assert stringly.merge('a=1,b=2', 'b=3,c=4') == 'a=1,b=3,c=4'
+    assert stringly.merge('a=1', 'a=2', 'a=3,b=4') == 'a=3,b=4'
+
+
+def test_dotted():
+    assert stringly.merge('a=1,s={x=1,y=2}', 's.x=5') == 'a=1,s={x=5,y=2}'
+
+
+def test_append_list():
+    assert stringly.merge('l={1,2},n=0', 'l+=3', sparse=True) == 'l={1,2,3},n=0'
+    assert stringly.merge('n=0', 'l+=3', sparse=True) == 'n=0,l=3'
+
+
+def test_append_dict():
+    assert stringly.merge('d={a=1,b=2}', 'd+={b=3,c=4}', sparse=True) == 'd={a=1,b=3,c=4}'
+    assert stringly.merge('s={d=a=1}', 's.d+=b=2', sparse=True) == 's={d={a=1,b=2}}'
+
+
+def test_replace_list():
+    assert stringly.merge('l={1,2},n=0', 'l=3') == 'l=3,n=0'
+
+
+def test_remove_items():
+    assert stringly.merge('l={1,2,3,2}', 'l-=2', sparse=True) == 'l={1,3}'
+    assert stringly.merge('l={1,2,3}', 'l-={1,3}', sparse=True) == 'l=2'
+    assert stringly.merge('d={a=1,b=2,c=3}', 'd-={a,c}', sparse=True) == 'd=b=2'
+
+
+def test_delete_key():
+    assert stringly.merge('a=1,b=2,c=3', '!b', sparse=True) == 'a=1,c=3'
+    assert stringly.merge('a=1,s={x=1,y=2}', '!s.x', sparse=True) == 'a=1,s=y=2'
+    assert stringly.merge('a=1', '!b', sparse=True) == 'a=1'
+
+
+def test_delete_requires_sparse():
+    with pytest.raises(stringly.error.SerializationError):
+        stringly.merge('a=1,b=2', '!b')
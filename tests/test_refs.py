@@ -0,0 +1,46 @@
+import dataclasses
+
+import pytest
+
+import stringly
+
+
+@dataclasses.dataclass
+class Grid:
+    nx: int
+    ny: int
+    label: str = ''
+
+
+@dataclasses.dataclass
+class Mesh:
+    grid: Grid
+    n: int
+
+
+def test_reference():
+    assert stringly.loads(Grid, 'nx=100,ny=$nx', resolve_references=True) == Grid(100, 100)
+    assert stringly.loads(Grid, 'ny=$nx,nx=100', resolve_references=True) == Grid(100, 100)
+
+
+def test_nested_reference():
+    assert stringly.loads(Mesh, 'grid={nx=4,ny=5},n=$grid.ny', resolve_references=True) == Mesh(Grid(4, 5), 5)
+
+
+def test_escaped_dollar():
+    assert stringly.loads(Grid, 'nx=1,ny=2,label=$$x', resolve_references=True) == Grid(1, 2, '$x')
+
+
+def test_circular_reference():
+    with pytest.raises(stringly.error.SerializationError, match='circular reference nx -> ny -> nx'):
+        stringly.loads(Grid, 'nx=$ny,ny=$nx', resolve_references=True)
+
+
+def test_unknown_reference():
+    with pytest.raises(stringly.error.SerializationError, match="ny references unknown key 'nz'"):
+        stringly.loads(Grid, 'nx=1,ny=$nz', resolve_references=True)
+
+
+def test_disabled_by_default():
+    with pytest.raises(stringly.error.SerializationError):
+        stringly.loads(Grid, 'nx=100,ny=$nx')
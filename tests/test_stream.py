@@ -0,0 +1,37 @@
+import io
+import typing
+
+import pytest
+
+import stringly
+
+
+def test_roundtrip():
+    f = io.BytesIO()
+    stringly.stream.send(f, typing.List[int], [1, 2, 3])
+    stringly.stream.send(f, str, 'héllo')
+    f.seek(0)
+    assert stringly.stream.recv(f, typing.List[int]) == [1, 2, 3]
+    assert stringly.stream.recv(f, str) == 'héllo'
+    with pytest.raises(EOFError):
+        stringly.stream.recv(f, str)
+
+
+def test_oversized_frame():
+    f = io.BytesIO(b'1000000000000\n')
+    with pytest.raises(stringly.error.SerializationError, match='exceeds the maximum'):
+        stringly.stream.recv(f, str)
+
+
+def test_max_frame():
+    f = io.BytesIO()
+    stringly.stream.send(f, str, 'abcdef')
+    f.seek(0)
+    with pytest.raises(stringly.error.SerializationError, match='exceeds the maximum of 4 bytes'):
+        stringly.stream.recv(f, str, max_frame=4)
+
+
+def test_truncated_frame():
+    f = io.BytesIO(b'10\nabc')
+    with pytest.raises(stringly.error.SerializationError, match='truncated frame'):
+        stringly.stream.recv(f, str)